@@ -0,0 +1,288 @@
+//! Packing of small images into shared textures.
+
+use std::collections::HashMap;
+
+use crate::geometry::{Float, Rect};
+
+/// Shelf heights are rounded up to a multiple of this, so that images of
+/// similar heights share shelves.
+const SHELF_ROUNDING: u32 = 4;
+
+/// Identifier of an allocation in an [`AtlasAllocator`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AllocationId {
+    id: usize,
+}
+
+/// Region reserved in an atlas, in texels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Allocation {
+    pub id: AllocationId,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Allocation {
+    pub fn rect(&self) -> Rect {
+        Rect::from_float(
+            self.x as Float,
+            self.y as Float,
+            (self.x + self.width) as Float,
+            (self.y + self.height) as Float,
+        )
+    }
+}
+
+/// Row of allocations sharing a height.
+#[derive(Clone, Debug)]
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// Free spans as `(x, width)`, sorted and coalesced.
+    free: Vec<(u32, u32)>,
+    allocations: usize,
+}
+
+impl Shelf {
+    fn new(y: u32, height: u32, width: u32) -> Self {
+        Shelf {
+            y,
+            height,
+            free: vec![(0, width)],
+            allocations: 0,
+        }
+    }
+
+    /// Index of the narrowest free span fitting `width`.
+    fn best_span(&self, width: u32) -> Option<usize> {
+        self.free
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, w))| *w >= width)
+            .min_by_key(|(_, (_, w))| *w)
+            .map(|(i, _)| i)
+    }
+
+    fn release(&mut self, x: u32, width: u32) {
+        let i = self.free.partition_point(|&(fx, _)| fx < x);
+        self.free.insert(i, (x, width));
+        if i + 1 < self.free.len() && x + width == self.free[i + 1].0 {
+            self.free[i].1 += self.free.remove(i + 1).1;
+        }
+        if i > 0 && self.free[i - 1].0 + self.free[i - 1].1 == x {
+            self.free[i - 1].1 += self.free.remove(i).1;
+        }
+        self.allocations -= 1;
+    }
+}
+
+/// Shelf packing allocator for rectangles of a fixed size atlas.
+///
+/// Rectangles are placed left to right on horizontal shelves stacked from
+/// the top. Freed space is reused by later allocations of similar height,
+/// and shelves are merged back once empty.
+#[derive(Clone, Debug)]
+pub struct AtlasAllocator {
+    width: u32,
+    height: u32,
+    shelves: Vec<Shelf>,
+    /// Live allocations by id. Ids are never reused, so freeing twice
+    /// cannot free a newer allocation.
+    allocations: HashMap<usize, Allocation>,
+    next_id: usize,
+}
+
+impl AtlasAllocator {
+    pub fn new(width: u32, height: u32) -> Self {
+        AtlasAllocator {
+            width,
+            height,
+            shelves: Vec::new(),
+            allocations: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Returns `true` if nothing is allocated.
+    pub fn is_empty(&self) -> bool {
+        self.shelves.iter().all(|s| s.allocations == 0)
+    }
+
+    /// Reserves a `width` x `height` region, or returns `None` if there is
+    /// no room left.
+    pub fn allocate(&mut self, width: u32, height: u32) -> Option<Allocation> {
+        if width == 0 || height == 0 || width > self.width || height > self.height {
+            return None;
+        }
+        let shelf_height = height.next_multiple_of(SHELF_ROUNDING).min(self.height);
+        // Fullest fitting shelf wasting the least height: non-empty shelves
+        // up to 1.5 times as high, or empty ones of any height.
+        let shelf = self
+            .shelves
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| {
+                s.height >= shelf_height
+                    && (s.allocations == 0 || s.height * 2 <= shelf_height * 3)
+                    && s.best_span(width).is_some()
+            })
+            .min_by_key(|(_, s)| (s.height - shelf_height, s.allocations == 0))
+            .map(|(i, _)| i);
+        let shelf = match shelf {
+            Some(i) => i,
+            None => {
+                let y = self.shelves.last().map_or(0, |s| s.y + s.height);
+                if y + shelf_height > self.height {
+                    return None;
+                }
+                self.shelves.push(Shelf::new(y, shelf_height, self.width));
+                self.shelves.len() - 1
+            }
+        };
+
+        // Empty shelves are split to the needed height.
+        let s = &self.shelves[shelf];
+        if s.allocations == 0 && s.height > shelf_height {
+            let rest = Shelf::new(s.y + shelf_height, s.height - shelf_height, self.width);
+            self.shelves[shelf].height = shelf_height;
+            self.shelves.insert(shelf + 1, rest);
+        }
+
+        let s = &mut self.shelves[shelf];
+        let span = s.best_span(width)?;
+        let (x, free_width) = s.free[span];
+        if free_width == width {
+            s.free.remove(span);
+        } else {
+            s.free[span] = (x + width, free_width - width);
+        }
+        s.allocations += 1;
+        let id = self.next_id;
+        self.next_id += 1;
+        let allocation = Allocation {
+            id: AllocationId { id },
+            x,
+            y: s.y,
+            width,
+            height,
+        };
+        self.allocations.insert(id, allocation);
+        Some(allocation)
+    }
+
+    /// Frees an allocation. Does nothing if it has already been freed.
+    pub fn deallocate(&mut self, id: AllocationId) {
+        let Some(allocation) = self.allocations.remove(&id.id) else {
+            return;
+        };
+        let Some(i) = self.shelves.iter().position(|s| s.y == allocation.y) else {
+            return;
+        };
+        self.shelves[i].release(allocation.x, allocation.width);
+        self.merge_empty_shelves();
+    }
+
+    /// Frees every allocation.
+    pub fn clear(&mut self) {
+        self.shelves.clear();
+        self.allocations.clear();
+    }
+
+    /// Merges adjacent empty shelves and drops empty shelves at the bottom.
+    fn merge_empty_shelves(&mut self) {
+        let mut i = 0;
+        while i + 1 < self.shelves.len() {
+            if self.shelves[i].allocations == 0 && self.shelves[i + 1].allocations == 0 {
+                let height = self.shelves.remove(i + 1).height;
+                self.shelves[i].height += height;
+            } else {
+                i += 1;
+            }
+        }
+        if self.shelves.last().is_some_and(|s| s.allocations == 0) {
+            self.shelves.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_shelves_left_to_right() {
+        let mut atlas = AtlasAllocator::new(16, 16);
+        let a = atlas.allocate(8, 6).unwrap();
+        let b = atlas.allocate(8, 7).unwrap();
+        let c = atlas.allocate(4, 4).unwrap();
+        assert_eq!((a.x, a.y, b.x, b.y), (0, 0, 8, 0));
+        // Heights are rounded to 8, too high a shelf for 4 texels.
+        assert_eq!((c.x, c.y), (0, 8));
+        assert!(atlas.allocate(17, 1).is_none());
+        assert!(atlas.allocate(0, 1).is_none());
+    }
+
+    #[test]
+    fn coalesces_free_spans() {
+        let mut atlas = AtlasAllocator::new(16, 16);
+        let spans: Vec<_> = (0..4).map(|_| atlas.allocate(4, 4).unwrap()).collect();
+        assert_eq!(spans.iter().map(|s| s.x).collect::<Vec<_>>(), [0, 4, 8, 12]);
+        atlas.deallocate(spans[1].id);
+        atlas.deallocate(spans[2].id);
+        assert_eq!(atlas.shelves[0].free, [(4, 8)]);
+        atlas.deallocate(spans[0].id);
+        assert_eq!(atlas.shelves[0].free, [(0, 12)]);
+        let wide = atlas.allocate(12, 4).unwrap();
+        assert_eq!((wide.x, wide.y), (0, 0));
+        // Freeing twice does nothing.
+        atlas.deallocate(spans[0].id);
+        assert!(atlas.shelves[0].free.is_empty());
+    }
+
+    #[test]
+    fn splits_and_merges_empty_shelves() {
+        let mut atlas = AtlasAllocator::new(16, 32);
+        let tall = atlas.allocate(16, 16).unwrap();
+        let bottom = atlas.allocate(16, 8).unwrap();
+        assert_eq!(bottom.y, 16);
+        atlas.deallocate(tall.id);
+
+        // The empty shelf is split to the needed height.
+        let short = atlas.allocate(16, 4).unwrap();
+        assert_eq!(short.y, 0);
+        let heights: Vec<_> = atlas.shelves.iter().map(|s| s.height).collect();
+        assert_eq!(heights, [4, 12, 8]);
+
+        // And merged back once empty.
+        atlas.deallocate(short.id);
+        let heights: Vec<_> = atlas.shelves.iter().map(|s| s.height).collect();
+        assert_eq!(heights, [16, 8]);
+        assert_eq!(atlas.allocate(16, 16).unwrap().y, 0);
+
+        atlas.clear();
+        assert!(atlas.is_empty());
+        assert_eq!(atlas.allocate(16, 32).unwrap().y, 0);
+    }
+
+    #[test]
+    fn drops_empty_shelves_at_the_bottom() {
+        let mut atlas = AtlasAllocator::new(16, 16);
+        let a = atlas.allocate(16, 8).unwrap();
+        let b = atlas.allocate(16, 8).unwrap();
+        assert!(atlas.allocate(1, 1).is_none());
+        atlas.deallocate(b.id);
+        atlas.deallocate(a.id);
+        assert!(atlas.is_empty());
+        assert!(atlas.shelves.is_empty());
+    }
+}
@@ -0,0 +1,350 @@
+use std::{error, fmt, str::FromStr};
+
+use crate::{geometry::Float, paint::ColorF};
+
+/// Straight alpha color in the sRGB color space, with sRGB encoded
+/// components.
+///
+/// Components are in `0.0..=1.0` for colors inside the sRGB gamut. Colors
+/// converted from wider gamuts, such as Display-P3, may have components
+/// outside of it; see [`Color::clamp`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Color {
+    pub c: ColorF,
+}
+
+impl Color {
+    pub const TRANSPARENT: Color = Color::new(0.0, 0.0, 0.0, 0.0);
+    pub const BLACK: Color = Color::rgb(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::rgb(1.0, 1.0, 1.0);
+    pub const RED: Color = Color::rgb(1.0, 0.0, 0.0);
+    pub const GREEN: Color = Color::rgb(0.0, 1.0, 0.0);
+    pub const BLUE: Color = Color::rgb(0.0, 0.0, 1.0);
+
+    pub const fn new(r: Float, g: Float, b: Float, a: Float) -> Self {
+        Color { c: [r, g, b, a] }
+    }
+
+    /// Opaque color.
+    pub const fn rgb(r: Float, g: Float, b: Float) -> Self {
+        Color::new(r, g, b, 1.0)
+    }
+
+    pub fn from_rgba8(r: u8, g: u8, b: u8, a: u8) -> Self {
+        let f = |v: u8| v as Float / 255.0;
+        Color::new(f(r), f(g), f(b), f(a))
+    }
+
+    /// Color from linear sRGB components and alpha.
+    pub fn from_linear(linear: ColorF) -> Self {
+        let [r, g, b, a] = linear;
+        Color::new(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a)
+    }
+
+    /// Opaque color from a hue in degrees, and saturation and lightness in
+    /// `0.0..=1.0`.
+    pub fn hsl(hue: Float, saturation: Float, lightness: Float) -> Self {
+        let s = saturation.clamp(0.0, 1.0);
+        let l = lightness.clamp(0.0, 1.0);
+        let a = s * l.min(1.0 - l);
+        let f = |n: Float| {
+            let k = (n + hue / 30.0).rem_euclid(12.0);
+            l - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+        };
+        Color::rgb(f(0.0), f(8.0), f(4.0))
+    }
+
+    /// Opaque color from OKLCH lightness in `0.0..=1.0`, chroma and a hue in
+    /// degrees. Colors outside the sRGB gamut are not clamped.
+    pub fn oklch(lightness: Float, chroma: Float, hue: Float) -> Self {
+        let (sin, cos) = hue.to_radians().sin_cos();
+        let (a, b) = (chroma * cos, chroma * sin);
+        let l = (lightness + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+        let m = (lightness - 0.105_561_35 * a - 0.063_854_17 * b).powi(3);
+        let s = (lightness - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+        Color::from_linear([
+            4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+            -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+            -0.004_196_086 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+            1.0,
+        ])
+    }
+
+    /// Color from Display-P3 encoded components and alpha. Colors outside
+    /// the sRGB gamut get components outside `0.0..=1.0`.
+    pub fn display_p3(r: Float, g: Float, b: Float, a: Float) -> Self {
+        let [r, g, b] = [r, g, b].map(srgb_to_linear);
+        Color::from_linear([
+            1.224_940_2 * r - 0.224_940_4 * g,
+            -0.042_056_955 * r + 1.042_057_1 * g,
+            -0.019_637_555 * r - 0.078_636_04 * g + 1.098_273_6 * b,
+            a,
+        ])
+    }
+
+    /// Parses `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`, with or without
+    /// the leading `#`.
+    pub fn from_hex(hex: &str) -> Result<Self, ParseColorError> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        let digit = |i: usize| {
+            hex.as_bytes()
+                .get(i)
+                .and_then(|&d| (d as char).to_digit(16))
+                .map(|d| d as u8)
+                .ok_or(ParseColorError::InvalidDigit)
+        };
+        let short = |i| digit(i).map(|d| d * 17);
+        let long = |i| Ok(digit(i)? * 16 + digit(i + 1)?);
+        match hex.len() {
+            3 => Ok(Color::from_rgba8(short(0)?, short(1)?, short(2)?, 255)),
+            4 => Ok(Color::from_rgba8(
+                short(0)?,
+                short(1)?,
+                short(2)?,
+                short(3)?,
+            )),
+            6 => Ok(Color::from_rgba8(long(0)?, long(2)?, long(4)?, 255)),
+            8 => Ok(Color::from_rgba8(long(0)?, long(2)?, long(4)?, long(6)?)),
+            _ => Err(ParseColorError::InvalidLength),
+        }
+    }
+
+    pub fn r(&self) -> Float {
+        self.c[0]
+    }
+
+    pub fn g(&self) -> Float {
+        self.c[1]
+    }
+
+    pub fn b(&self) -> Float {
+        self.c[2]
+    }
+
+    pub fn a(&self) -> Float {
+        self.c[3]
+    }
+
+    pub fn with_alpha(self, alpha: Float) -> Self {
+        let [r, g, b, _] = self.c;
+        Color::new(r, g, b, alpha)
+    }
+
+    /// Multiplies the alpha by `opacity`.
+    pub fn fade(self, opacity: Float) -> Self {
+        self.with_alpha(self.a() * opacity)
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.a() >= 1.0
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.a() <= 0.0
+    }
+
+    /// Linear sRGB components and alpha.
+    pub fn to_linear(self) -> ColorF {
+        let [r, g, b, a] = self.c;
+        [srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a]
+    }
+
+    /// Display-P3 encoded components and alpha.
+    pub fn to_display_p3(self) -> ColorF {
+        let [r, g, b, a] = self.to_linear();
+        let p3 = [
+            0.822_462_1 * r + 0.177_538 * g,
+            0.033_194_2 * r + 0.966_805_8 * g,
+            0.017_082_632 * r + 0.072_397_44 * g + 0.910_519_9 * b,
+        ];
+        let [r, g, b] = p3.map(linear_to_srgb);
+        [r, g, b, a]
+    }
+
+    /// Components multiplied by alpha.
+    pub fn premultiplied(self) -> ColorF {
+        crate::paint::premultiply(self.c)
+    }
+
+    /// Returns `true` if the color is inside the sRGB gamut.
+    pub fn is_in_gamut(&self) -> bool {
+        self.c.iter().all(|v| (0.0..=1.0).contains(v))
+    }
+
+    /// Clamps the components into `0.0..=1.0`.
+    pub fn clamp(self) -> Self {
+        Color {
+            c: self.c.map(|v| v.clamp(0.0, 1.0)),
+        }
+    }
+
+    /// Components rounded to 8 bits, clamped to the sRGB gamut.
+    pub fn to_rgba8(self) -> [u8; 4] {
+        self.c.map(|v| (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8)
+    }
+
+    /// Packs the color as `r | g << 8 | b << 16 | a << 24`, the layout of
+    /// vertex colors.
+    pub fn pack(self) -> u32 {
+        u32::from_le_bytes(self.to_rgba8())
+    }
+
+    /// Inverse of [`Color::pack`].
+    pub fn unpack(packed: u32) -> Self {
+        let [r, g, b, a] = packed.to_le_bytes();
+        Color::from_rgba8(r, g, b, a)
+    }
+}
+
+impl From<ColorF> for Color {
+    fn from(c: ColorF) -> Self {
+        Color { c }
+    }
+}
+
+impl From<Color> for ColorF {
+    fn from(color: Color) -> Self {
+        color.c
+    }
+}
+
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Color::from_hex(s)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseColorError {
+    /// The number of hex digits is not 3, 4, 6 or 8.
+    InvalidLength,
+    InvalidDigit,
+}
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseColorError::InvalidLength => write!(f, "hex color must have 3, 4, 6 or 8 digits"),
+            ParseColorError::InvalidDigit => write!(f, "invalid hex digit in color"),
+        }
+    }
+}
+
+impl error::Error for ParseColorError {}
+
+/// Decodes an sRGB encoded component. Negative components are mirrored, as
+/// in extended sRGB.
+#[inline]
+pub(crate) fn srgb_to_linear(v: Float) -> Float {
+    let a = v.abs();
+    let linear = if a <= 0.04045 {
+        a / 12.92
+    } else {
+        ((a + 0.055) / 1.055).powf(2.4)
+    };
+    linear.copysign(v)
+}
+
+/// Encodes a linear component to sRGB. Negative components are mirrored, as
+/// in extended sRGB.
+#[inline]
+pub(crate) fn linear_to_srgb(v: Float) -> Float {
+    let a = v.abs();
+    let encoded = if a <= 0.003_130_8 {
+        a * 12.92
+    } else {
+        1.055 * a.powf(1.0 / 2.4) - 0.055
+    };
+    encoded.copysign(v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: ColorF, b: ColorF) {
+        assert!(
+            a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4),
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(Color::from_hex("#fff"), Ok(Color::WHITE));
+        assert_eq!(
+            Color::from_hex("0f08").unwrap().to_rgba8(),
+            [0, 255, 0, 136]
+        );
+        assert_eq!(
+            Color::from_hex("#1a2B3c").unwrap().to_rgba8(),
+            [0x1a, 0x2b, 0x3c, 255]
+        );
+        assert_eq!(
+            "#1a2b3c80".parse::<Color>().unwrap().to_rgba8(),
+            [0x1a, 0x2b, 0x3c, 0x80]
+        );
+    }
+
+    #[test]
+    fn invalid_hex_colors() {
+        assert_eq!(Color::from_hex(""), Err(ParseColorError::InvalidLength));
+        assert_eq!(
+            Color::from_hex("#12345"),
+            Err(ParseColorError::InvalidLength)
+        );
+        assert_eq!(Color::from_hex("#12g"), Err(ParseColorError::InvalidDigit));
+        assert_eq!(Color::from_hex("##123"), Err(ParseColorError::InvalidDigit));
+        // Multi-byte characters are not digits, whatever the byte length.
+        assert_eq!(Color::from_hex("é1"), Err(ParseColorError::InvalidDigit));
+    }
+
+    #[test]
+    fn srgb_transfer_round_trips() {
+        for v in [-0.5, 0.0, 0.002, 0.04045, 0.2, 0.5, 1.0, 1.5] {
+            assert!((linear_to_srgb(srgb_to_linear(v)) - v).abs() < 1e-5);
+        }
+        assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 1e-5);
+        assert_eq!(srgb_to_linear(-0.5), -srgb_to_linear(0.5));
+        assert_close(
+            Color::from_linear(Color::rgb(0.3, 0.6, 0.9).to_linear()).c,
+            [0.3, 0.6, 0.9, 1.0],
+        );
+    }
+
+    #[test]
+    fn display_p3_round_trips() {
+        let color = Color::new(0.2, 0.4, 0.6, 0.5);
+        let [r, g, b, a] = color.to_display_p3();
+        assert_close(Color::display_p3(r, g, b, a).c, color.c);
+        assert_close(Color::WHITE.to_display_p3(), [1.0; 4]);
+        // Display-P3 red is outside the sRGB gamut.
+        let red = Color::display_p3(1.0, 0.0, 0.0, 1.0);
+        assert!(!red.is_in_gamut());
+        assert!(red.r() > 1.0 && red.g() < 0.0);
+        assert!(red.clamp().is_in_gamut());
+    }
+
+    #[test]
+    fn constructors() {
+        assert_close(Color::hsl(0.0, 1.0, 0.5).c, Color::RED.c);
+        assert_close(Color::hsl(240.0, 1.0, 0.5).c, Color::BLUE.c);
+        assert_close(Color::hsl(120.0, 0.0, 1.0).c, Color::WHITE.c);
+        assert_close(Color::oklch(1.0, 0.0, 0.0).c, Color::WHITE.c);
+        assert_close(Color::oklch(0.0, 0.0, 0.0).c, Color::BLACK.c);
+    }
+
+    #[test]
+    fn packing() {
+        let color = Color::from_rgba8(1, 2, 3, 4);
+        assert_eq!(color.pack(), 0x0403_0201);
+        assert_eq!(Color::unpack(color.pack()), color);
+        assert_eq!(
+            Color::new(1.5, -0.5, 0.5, 1.0).to_rgba8(),
+            [255, 0, 128, 255]
+        );
+    }
+}
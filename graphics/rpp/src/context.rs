@@ -1,7 +1,132 @@
-use crate::shader::*;
+use crate::{
+    effect::BoxShadow,
+    geometry::{Float, Mat3, Rect, RoundedRect},
+    image::{Glyph, ImageId, ImageOptions},
+    paint::{BlendMode, Border, Paint},
+    path::{Path, StrokeStyle},
+};
 
+/// Drawing commands of a backend.
+///
+/// Coordinates are in user space, mapped to the target by the current
+/// transform. Clips are transformed when pushed.
 pub trait Context {
-    fn create_solid_color_shader(&mut self) -> ShaderId;
-    fn set_shader(&mut self, id: ShaderId);
-    fn fill_rect();
+    fn fill_rect(&mut self, rect: Rect, paint: &Paint);
+    /// Fills `rrect` with `paint`, then draws `border` inside its outline.
+    fn fill_rounded_rect(&mut self, rrect: RoundedRect, paint: &Paint, border: Option<Border>);
+    /// Draws the drop or inner shadow of `rrect`, without `rrect` itself.
+    fn draw_box_shadow(&mut self, rrect: RoundedRect, shadow: BoxShadow);
+    /// Blurs what has been drawn so far inside `rrect`, as CSS
+    /// `backdrop-filter: blur()`.
+    fn backdrop_blur(&mut self, rrect: RoundedRect, blur_radius: Float);
+    /// Fills `path` using its fill rule.
+    fn fill_path(&mut self, path: &Path, paint: &Paint);
+    /// Strokes the outline of `path` as described by `style`.
+    fn stroke_path(&mut self, path: &Path, style: &StrokeStyle, paint: &Paint);
+    /// Draws the texels of `image` inside `src_rect` stretched to
+    /// `dst_rect`, or nine-sliced if `options` has a [`NineSlice`].
+    ///
+    /// [`NineSlice`]: crate::NineSlice
+    fn draw_image(
+        &mut self,
+        image: ImageId,
+        src_rect: Rect,
+        dst_rect: Rect,
+        options: &ImageOptions,
+    );
+    /// Draws a run of shaped and rasterized glyphs: `paint` masked by the
+    /// alpha of each glyph, linearly filtered.
+    fn draw_glyphs(&mut self, glyphs: &[Glyph], paint: &Paint);
+
+    /// Restricts drawing to `rect`, intersected with the current clip, until
+    /// the matching [`Context::pop_clip`]. The rect is snapped to pixels.
+    fn push_clip_rect(&mut self, rect: Rect);
+    /// Restricts drawing to `rrect`, intersected with the current clip.
+    fn push_clip_rounded_rect(&mut self, rrect: RoundedRect);
+    /// Restricts drawing to the inside of `path` according to its fill
+    /// rule, intersected with the current clip.
+    fn push_clip_path(&mut self, path: &Path);
+    /// Removes the clip pushed last. Does nothing if there is none.
+    fn pop_clip(&mut self);
+
+    /// Draws into a new transparent layer until the matching
+    /// [`Context::pop_layer`], which composites it with `opacity` and
+    /// `blend_mode`. Layers still open at the end of the frame are
+    /// composited then.
+    fn push_layer(&mut self, opacity: Float, blend_mode: BlendMode) {
+        self.push_blurred_layer(opacity, blend_mode, 0.0);
+    }
+    /// Like [`Context::push_layer`], but blurs the content of the layer
+    /// before compositing it, as CSS `filter: blur()`. `blur_radius` is in
+    /// user space when pushed.
+    fn push_blurred_layer(&mut self, opacity: Float, blend_mode: BlendMode, blur_radius: Float);
+    /// Composites the layer pushed last into the content below, inside the
+    /// current clip. Does nothing if there is none.
+    fn pop_layer(&mut self);
+
+    /// Saves the current transform, until the matching [`Context::restore`].
+    fn save(&mut self);
+    /// Restores the transform saved last. Does nothing if there is none.
+    fn restore(&mut self);
+    /// Transform from user space to the target, identity by default.
+    fn transform(&self) -> Mat3;
+    fn set_transform(&mut self, transform: Mat3);
+
+    /// Applies `transform` to user space before the current transform.
+    fn concat(&mut self, transform: Mat3) {
+        self.set_transform(self.transform() * transform);
+    }
+
+    fn translate(&mut self, dx: Float, dy: Float) {
+        self.concat(Mat3::translate(dx, dy));
+    }
+
+    fn scale(&mut self, sx: Float, sy: Float) {
+        self.concat(Mat3::scale(sx, sy));
+    }
+
+    /// Rotates user space by `angle` radians, clockwise.
+    fn rotate(&mut self, angle: Float) {
+        self.concat(Mat3::rotate(angle));
+    }
+}
+
+/// Current and saved transforms of a [`Context`].
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct TransformStack {
+    current: Mat3,
+    saved: Vec<Mat3>,
+}
+
+impl Default for TransformStack {
+    fn default() -> Self {
+        TransformStack {
+            current: Mat3::identity(),
+            saved: Vec::new(),
+        }
+    }
+}
+
+impl TransformStack {
+    pub(crate) fn current(&self) -> Mat3 {
+        self.current
+    }
+
+    pub(crate) fn set(&mut self, transform: Mat3) {
+        self.current = transform;
+    }
+
+    pub(crate) fn save(&mut self) {
+        self.saved.push(self.current);
+    }
+
+    pub(crate) fn restore(&mut self) {
+        if let Some(transform) = self.saved.pop() {
+            self.current = transform;
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
 }
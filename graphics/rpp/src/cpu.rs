@@ -0,0 +1,914 @@
+//! Reference software implementation of [`Context`].
+
+use std::sync::Arc;
+
+use crate::{
+    context::{Context, TransformStack},
+    effect::{self, BoxShadow},
+    geometry::{Float, Mat3, Point, Rect, RoundedRect},
+    image::{self, Glyph, Image, ImageId, ImageOptions, Sampling},
+    paint::{BlendMode, Border, ColorF, Paint, PaintSampler},
+    path::{FillRule, Path, Polyline, StrokeStyle},
+    tessellator::{self, Edge},
+};
+
+/// Vertical samples per pixel used for anti-aliasing.
+const SUBSAMPLES: usize = 16;
+
+/// RGBA8 image with premultiplied alpha.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pixmap {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Pixmap {
+    /// Creates a transparent pixmap.
+    pub fn new(width: u32, height: u32) -> Self {
+        Pixmap {
+            width,
+            height,
+            data: vec![0; width as usize * height as usize * 4],
+        }
+    }
+
+    /// Creates a pixmap from the straight alpha texels of `image`.
+    pub fn from_image(image: &Image) -> Self {
+        let mut data = image.data().to_vec();
+        for texel in data.chunks_exact_mut(4) {
+            let a = texel[3] as Float / 255.0;
+            for c in &mut texel[..3] {
+                *c = to_u8(*c as Float / 255.0 * a);
+            }
+        }
+        Pixmap {
+            width: image.width(),
+            height: image.height(),
+            data,
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Premultiplied RGBA8 texels, row by row.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        Some([
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        ])
+    }
+
+    /// Replaces every pixel with `color`.
+    pub fn fill(&mut self, color: impl Into<ColorF>) {
+        let mut premultiplied = [0; 4];
+        for (c, v) in premultiplied.iter_mut().zip(premultiply(color.into(), 1.0)) {
+            *c = to_u8(v);
+        }
+        for pixel in self.data.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&premultiplied);
+        }
+    }
+
+    /// Applies a Gaussian blur with a CSS style `blur_radius`. Texels past
+    /// the edges repeat the edge texels.
+    pub fn blur(&mut self, blur_radius: Float) {
+        self.blur_sigma(effect::blur_sigma(blur_radius));
+    }
+
+    /// Applies a Gaussian blur of standard deviation `sigma` in texels.
+    fn blur_sigma(&mut self, sigma: Float) {
+        let (width, height) = (self.width, self.height);
+        let blurred = self.blurred(0, 0, width, height, sigma);
+        for (texel, v) in self.data.iter_mut().zip(blurred) {
+            *texel = to_u8(v);
+        }
+    }
+
+    /// Premultiplied color at `(x, y)` in texels. Texels past the edges
+    /// repeat the edge texels.
+    fn sample(&self, x: Float, y: Float, nearest: bool) -> [Float; 4] {
+        let texel = |x: Float, y: Float| {
+            let x = (x.max(0.0) as u32).min(self.width - 1);
+            let y = (y.max(0.0) as u32).min(self.height - 1);
+            let i = (y as usize * self.width as usize + x as usize) * 4;
+            std::array::from_fn::<Float, 4, _>(|c| self.data[i + c] as Float / 255.0)
+        };
+        if nearest {
+            return texel(x.floor(), y.floor());
+        }
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (a, b) = (texel(x0, y0), texel(x0 + 1.0, y0));
+        let (c, d) = (texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0));
+        std::array::from_fn(|i| {
+            let top = a[i] + (b[i] - a[i]) * fx;
+            let bottom = c[i] + (d[i] - c[i]) * fx;
+            top + (bottom - top) * fy
+        })
+    }
+
+    /// Next mipmap level, half as large rounding down.
+    fn downsample(&self) -> Pixmap {
+        let (width, height) = ((self.width / 2).max(1), (self.height / 2).max(1));
+        let mut level = Pixmap::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let center = (2 * x + 1) as Float;
+                let color = self.sample(center, (2 * y + 1) as Float, false);
+                let i = (y as usize * width as usize + x as usize) * 4;
+                for (texel, v) in level.data[i..i + 4].iter_mut().zip(color) {
+                    *texel = to_u8(v);
+                }
+            }
+        }
+        level
+    }
+
+    /// Blurred premultiplied texels of a region, as if it was the whole
+    /// image.
+    fn blurred(&self, x0: u32, y0: u32, width: u32, height: u32, sigma: Float) -> Vec<Float> {
+        let (w, h) = (width as usize, height as usize);
+        if w == 0 || h == 0 {
+            return Vec::new();
+        }
+        let mut texels = vec![0.0; w * h * 4];
+        for (y, row) in texels.chunks_exact_mut(w * 4).enumerate() {
+            let offset = ((y0 as usize + y) * self.width as usize + x0 as usize) * 4;
+            for (v, &texel) in row.iter_mut().zip(&self.data[offset..offset + w * 4]) {
+                *v = texel as Float / 255.0;
+            }
+        }
+
+        let kernel = effect::gaussian_kernel(sigma);
+        let mut tmp = vec![0.0; texels.len()];
+        blur_pass(&texels, &mut tmp, w, h, &kernel, [1, 0]);
+        blur_pass(&tmp, &mut texels, w, h, &kernel, [0, 1]);
+        texels
+    }
+}
+
+/// One direction of a separable blur of RGBA texels.
+fn blur_pass(
+    src: &[Float],
+    dst: &mut [Float],
+    w: usize,
+    h: usize,
+    kernel: &[Float],
+    dir: [i64; 2],
+) {
+    let radius = (kernel.len() / 2) as i64;
+    for y in 0..h {
+        for x in 0..w {
+            let mut sum = [0.0; 4];
+            for (k, &weight) in kernel.iter().enumerate() {
+                let step = k as i64 - radius;
+                let sx = (x as i64 + step * dir[0]).clamp(0, w as i64 - 1) as usize;
+                let sy = (y as i64 + step * dir[1]).clamp(0, h as i64 - 1) as usize;
+                let i = (sy * w + sx) * 4;
+                for c in 0..4 {
+                    sum[c] += src[i + c] * weight;
+                }
+            }
+            let i = (y * w + x) * 4;
+            dst[i..i + 4].copy_from_slice(&sum);
+        }
+    }
+}
+
+#[inline(always)]
+fn to_u8(v: Float) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+}
+
+#[inline(always)]
+fn premultiply(color: ColorF, coverage: Float) -> [Float; 4] {
+    let a = color[3] * coverage;
+    [color[0] * a, color[1] * a, color[2] * a, a]
+}
+
+/// Source-over compositing of a premultiplied color.
+#[inline(always)]
+fn blend(dst: &mut [u8], src: [Float; 4]) {
+    let inv = 1.0 - src[3];
+    for i in 0..4 {
+        dst[i] = to_u8(src[i] + dst[i] as Float / 255.0 * inv);
+    }
+}
+
+/// Pixels overlapping `rect`, clamped to the pixmap, as `(x0, y0, x1, y1)`.
+fn pixel_bounds(rect: Rect, width: u32, height: u32) -> (u32, u32, u32, u32) {
+    let (min, max) = (rect.min(), rect.max());
+    (
+        (min.x().floor().max(0.0) as u32).min(width),
+        (min.y().floor().max(0.0) as u32).min(height),
+        (max.x().ceil().max(0.0) as u32).min(width),
+        (max.y().ceil().max(0.0) as u32).min(height),
+    )
+}
+
+/// Computes anti-aliased coverage of the area inside `edges`, calling `f`
+/// with the row, the first column and the coverage of each covered row.
+pub(crate) fn rasterize(
+    edges: &[Edge],
+    fill_rule: FillRule,
+    width: u32,
+    height: u32,
+    mut f: impl FnMut(u32, u32, &[Float]),
+) {
+    if edges.is_empty() || width == 0 || height == 0 {
+        return;
+    }
+    let top = edges.iter().map(|e| e.y0).fold(Float::INFINITY, Float::min);
+    let bottom = edges
+        .iter()
+        .map(|e| e.y1)
+        .fold(Float::NEG_INFINITY, Float::max);
+    let y_start = top.floor().max(0.0) as u32;
+    let y_end = (bottom.ceil().max(0.0) as u32).min(height);
+
+    let mut coverage = vec![0.0; width as usize + 1];
+    let mut row_edges: Vec<&Edge> = Vec::new();
+    let mut crossings: Vec<(Float, i32)> = Vec::new();
+    let weight = 1.0 / SUBSAMPLES as Float;
+
+    for y in y_start..y_end {
+        let (row_top, row_bottom) = (y as Float, (y + 1) as Float);
+        row_edges.clear();
+        row_edges.extend(edges.iter().filter(|e| e.y0 < row_bottom && e.y1 > row_top));
+        if row_edges.is_empty() {
+            continue;
+        }
+
+        let mut min_x = width as usize;
+        let mut max_x = 0;
+        for s in 0..SUBSAMPLES {
+            let sy = row_top + (s as Float + 0.5) * weight;
+            crossings.clear();
+            crossings.extend(
+                row_edges
+                    .iter()
+                    .filter(|e| e.y0 <= sy && sy < e.y1)
+                    .map(|e| (e.x_at(sy), e.winding)),
+            );
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut winding = 0;
+            let mut left: Float = 0.0;
+            for &(x, w) in &crossings {
+                let was_inside = fill_rule.is_inside(winding);
+                winding += w;
+                match (was_inside, fill_rule.is_inside(winding)) {
+                    (false, true) => left = x,
+                    (true, false) => {
+                        let xa = left.clamp(0.0, width as Float);
+                        let xb = x.clamp(0.0, width as Float);
+                        if xb <= xa {
+                            continue;
+                        }
+                        let (ia, ib) = (xa as usize, xb as usize);
+                        if ia == ib {
+                            coverage[ia] += (xb - xa) * weight;
+                        } else {
+                            coverage[ia] += (ia as Float + 1.0 - xa) * weight;
+                            for c in &mut coverage[ia + 1..ib] {
+                                *c += weight;
+                            }
+                            coverage[ib] += (xb - ib as Float) * weight;
+                        }
+                        min_x = min_x.min(ia);
+                        max_x = max_x.max(ib + 1);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let max_x = max_x.min(width as usize);
+        if min_x < max_x {
+            for c in &mut coverage[min_x..max_x] {
+                *c = c.min(1.0);
+            }
+            f(y, min_x as u32, &coverage[min_x..max_x]);
+        }
+        coverage.iter_mut().for_each(|c| *c = 0.0);
+    }
+}
+
+/// Area drawing is restricted to.
+#[derive(Clone, Debug)]
+struct Clip {
+    /// Pixels inside the clip, as `(x0, y0, x1, y1)`.
+    bounds: (u32, u32, u32, u32),
+    /// Coverage of every pixel of the pixmap, unless the clip is a rect.
+    mask: Option<Arc<[Float]>>,
+}
+
+impl Clip {
+    fn coverage(&self, width: u32, x: u32, y: u32) -> Float {
+        let (x0, y0, x1, y1) = self.bounds;
+        if x < x0 || x >= x1 || y < y0 || y >= y1 {
+            return 0.0;
+        }
+        self.mask
+            .as_ref()
+            .map_or(1.0, |mask| mask[y as usize * width as usize + x as usize])
+    }
+}
+
+/// Composites `src` over the pixel, scaled by the clip coverage.
+#[inline]
+fn blend_clipped(pixmap: &mut Pixmap, clip: Option<&Clip>, x: u32, y: u32, src: [Float; 4]) {
+    let scale = clip.map_or(1.0, |clip| clip.coverage(pixmap.width, x, y));
+    if scale <= 0.0 {
+        return;
+    }
+    let offset = (y as usize * pixmap.width as usize + x as usize) * 4;
+    blend(&mut pixmap.data[offset..offset + 4], src.map(|v| v * scale));
+}
+
+/// Inverse of `transform`, mapping pixels to user space, and the size of a
+/// pixel in user space. `None` if the transform is singular.
+fn local_space(transform: &Mat3) -> Option<(Mat3, Float)> {
+    let inverse = transform.invert()?;
+    Some((inverse, inverse.determinant().abs().sqrt()))
+}
+
+/// Layer drawn into instead of its parent.
+struct Layer {
+    parent: Pixmap,
+    opacity: Float,
+    blend_mode: BlendMode,
+    /// Standard deviation of the blur of the content, in pixels.
+    sigma: Float,
+}
+
+/// [`Context`] drawing into a [`Pixmap`].
+///
+/// Drawing is in logical units, mapped to the pixels of the pixmap by the
+/// scale factor.
+pub struct CpuContext {
+    /// Pixmap drawn into: the target, or the innermost layer.
+    pixmap: Pixmap,
+    clips: Vec<Clip>,
+    transforms: TransformStack,
+    /// Pixels per logical unit.
+    scale_factor: Float,
+    layers: Vec<Layer>,
+    /// Mipmap levels of the added images, by id.
+    images: Vec<Option<Vec<Pixmap>>>,
+}
+
+impl CpuContext {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self::from_pixmap(Pixmap::new(width, height))
+    }
+
+    pub fn from_pixmap(pixmap: Pixmap) -> Self {
+        CpuContext {
+            pixmap,
+            clips: Vec::new(),
+            transforms: TransformStack::default(),
+            scale_factor: 1.0,
+            layers: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    /// Creates a context drawing `width` x `height` logical units into a
+    /// pixmap of `scale_factor` pixels per unit.
+    pub fn with_scale_factor(width: Float, height: Float, scale_factor: Float) -> Self {
+        let mut context = Self::new(0, 0);
+        context.set_scale_factor(scale_factor);
+        let scale = context.scale_factor;
+        context.pixmap = Pixmap::new(
+            (width * scale).round() as u32,
+            (height * scale).round() as u32,
+        );
+        context
+    }
+
+    /// Pixels per logical unit.
+    pub fn scale_factor(&self) -> Float {
+        self.scale_factor
+    }
+
+    /// Sets the pixels per logical unit of what is drawn next. Non-positive
+    /// scale factors are replaced by 1.
+    pub fn set_scale_factor(&mut self, scale_factor: Float) {
+        self.scale_factor = if scale_factor > 0.0 {
+            scale_factor
+        } else {
+            1.0
+        };
+    }
+
+    /// Adds an image for [`Context::draw_image`], with its mipmaps.
+    pub fn add_image(&mut self, image: &Image) -> ImageId {
+        let mut levels = vec![Pixmap::from_image(image)];
+        while let Some(last) = levels.last()
+            && (last.width > 1 || last.height > 1)
+        {
+            levels.push(last.downsample());
+        }
+        self.images.push(Some(levels));
+        ImageId::new(self.images.len() - 1)
+    }
+
+    /// Frees the image. Its id is never handed out again, so drawing it
+    /// draws nothing.
+    pub fn remove_image(&mut self, image: ImageId) {
+        if let Some(slot) = self.images.get_mut(image.value()) {
+            *slot = None;
+        }
+    }
+
+    pub fn pixmap(&self) -> &Pixmap {
+        &self.pixmap
+    }
+
+    pub fn pixmap_mut(&mut self) -> &mut Pixmap {
+        &mut self.pixmap
+    }
+
+    /// Target pixmap, with the layers still open composited into it.
+    pub fn into_pixmap(mut self) -> Pixmap {
+        while !self.layers.is_empty() {
+            self.pop_layer();
+        }
+        self.pixmap
+    }
+
+    /// Maps user space to pixels.
+    fn device_transform(&self) -> Mat3 {
+        Mat3::scale(self.scale_factor, self.scale_factor).multiply(&self.transforms.current())
+    }
+
+    /// Flattens `path` and maps it to pixels.
+    fn flatten(&self, path: &Path) -> Vec<Polyline> {
+        path.flatten_for(&self.device_transform())
+    }
+
+    /// Maps `polylines` from user space to pixels.
+    fn to_pixels(&self, mut polylines: Vec<Polyline>) -> Vec<Polyline> {
+        let transform = self.device_transform();
+        polylines.iter_mut().for_each(|p| p.transform(&transform));
+        polylines
+    }
+
+    fn fill_edges(&mut self, edges: &[Edge], fill_rule: FillRule, paint: &Paint) {
+        let sampler = PaintSampler::transformed(paint, &self.device_transform());
+        let pixmap = &mut self.pixmap;
+        let clip = self.clips.last();
+        let (width, height) = (pixmap.width, pixmap.height);
+        rasterize(edges, fill_rule, width, height, |y, x0, row| {
+            for (i, &coverage) in row.iter().enumerate() {
+                if coverage <= 0.0 {
+                    continue;
+                }
+                let x = x0 + i as u32;
+                let color = sampler.sample(Point::new(x as Float + 0.5, y as Float + 0.5));
+                blend_clipped(pixmap, clip, x, y, premultiply(color, coverage));
+            }
+        });
+    }
+
+    /// Pushes the intersection of the current clip with `bounds` and the
+    /// coverage computed by `mask`, called with the parent coverage of each
+    /// pixel inside `bounds`.
+    fn push_clip(
+        &mut self,
+        bounds: (u32, u32, u32, u32),
+        mask: Option<&dyn Fn(u32, u32) -> Float>,
+    ) {
+        let (width, height) = (self.pixmap.width, self.pixmap.height);
+        let parent = self.clips.last().cloned().unwrap_or(Clip {
+            bounds: (0, 0, width, height),
+            mask: None,
+        });
+        let (x0, y0, x1, y1) = bounds;
+        let (px0, py0, px1, py1) = parent.bounds;
+        let bounds = (x0.max(px0), y0.max(py0), x1.min(px1), y1.min(py1));
+        let mask = match mask {
+            None => parent.mask,
+            Some(coverage) => {
+                let mut data = vec![0.0; width as usize * height as usize];
+                for y in bounds.1..bounds.3 {
+                    for x in bounds.0..bounds.2 {
+                        data[y as usize * width as usize + x as usize] =
+                            parent.coverage(width, x, y) * coverage(x, y);
+                    }
+                }
+                Some(data.into())
+            }
+        };
+        self.clips.push(Clip { bounds, mask });
+    }
+}
+
+impl Context for CpuContext {
+    fn fill_rect(&mut self, rect: Rect, paint: &Paint) {
+        self.fill_path(&Path::from_rect(rect), paint);
+    }
+
+    fn fill_rounded_rect(&mut self, rrect: RoundedRect, paint: &Paint, border: Option<Border>) {
+        let transform = self.device_transform();
+        let Some((inverse, pixel)) = local_space(&transform) else {
+            return;
+        };
+        let sampler = PaintSampler::new(paint);
+        let bounds = transform.transform_rect(rrect.rect().outset(pixel));
+        let pixmap = &mut self.pixmap;
+        let clip = self.clips.last();
+        let (x_start, y_start, x_end, y_end) = pixel_bounds(bounds, pixmap.width, pixmap.height);
+        let border_width = border.map_or(0.0, |b| b.width.max(0.0));
+
+        for y in y_start..y_end {
+            for x in x_start..x_end {
+                let center =
+                    inverse.transform_point(Point::new(x as Float + 0.5, y as Float + 0.5));
+                let d = rrect.signed_distance(center) / pixel;
+                let outer = effect::coverage(d);
+                if outer <= 0.0 {
+                    continue;
+                }
+                // Same coverage as the SDF shader with one pixel wide ramps.
+                let inner = effect::coverage(d + border_width / pixel);
+                let mut src = premultiply(sampler.sample(center), inner);
+                if let Some(border) = border {
+                    let ring = premultiply(border.color, outer - inner);
+                    for (s, r) in src.iter_mut().zip(ring) {
+                        *s += r;
+                    }
+                }
+                blend_clipped(pixmap, clip, x, y, src);
+            }
+        }
+    }
+
+    fn draw_box_shadow(&mut self, rrect: RoundedRect, shadow: BoxShadow) {
+        let transform = self.device_transform();
+        let Some((inverse, _)) = local_space(&transform) else {
+            return;
+        };
+        let pixmap = &mut self.pixmap;
+        let clip = self.clips.last();
+        let bounds = transform.transform_rect(shadow.bounds(rrect));
+        let (x0, y0, x1, y1) = pixel_bounds(bounds, pixmap.width, pixmap.height);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let center =
+                    inverse.transform_point(Point::new(x as Float + 0.5, y as Float + 0.5));
+                let alpha = shadow.alpha_at(rrect, center);
+                if alpha <= 0.0 {
+                    continue;
+                }
+                blend_clipped(pixmap, clip, x, y, premultiply(shadow.color, alpha));
+            }
+        }
+    }
+
+    fn backdrop_blur(&mut self, rrect: RoundedRect, blur_radius: Float) {
+        let transform = self.device_transform();
+        let Some((inverse, pixel)) = local_space(&transform) else {
+            return;
+        };
+        let sigma = effect::blur_sigma(blur_radius) * transform.max_scale();
+        // The blur reads texels up to the kernel radius outside the shape.
+        let margin = effect::kernel_radius(sigma) as Float + 1.0;
+        let bounds = transform.transform_rect(rrect.rect()).outset(margin);
+        let pixmap = &mut self.pixmap;
+        let (x0, y0, x1, y1) = pixel_bounds(bounds, pixmap.width, pixmap.height);
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+        let blurred = pixmap.blurred(x0, y0, x1 - x0, y1 - y0, sigma);
+        let w = (x1 - x0) as usize;
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let center =
+                    inverse.transform_point(Point::new(x as Float + 0.5, y as Float + 0.5));
+                let clip_coverage = self
+                    .clips
+                    .last()
+                    .map_or(1.0, |clip| clip.coverage(pixmap.width, x, y));
+                let coverage =
+                    effect::coverage(rrect.signed_distance(center) / pixel) * clip_coverage;
+                if coverage <= 0.0 {
+                    continue;
+                }
+                let i = ((y - y0) as usize * w + (x - x0) as usize) * 4;
+                let offset = (y as usize * pixmap.width as usize + x as usize) * 4;
+                for c in 0..4 {
+                    let dst = pixmap.data[offset + c] as Float / 255.0;
+                    pixmap.data[offset + c] = to_u8(dst + (blurred[i + c] - dst) * coverage);
+                }
+            }
+        }
+    }
+
+    fn fill_path(&mut self, path: &Path, paint: &Paint) {
+        let edges = tessellator::edges(&self.to_pixels(self.flatten(path)));
+        self.fill_edges(&edges, path.fill_rule(), paint);
+    }
+
+    fn stroke_path(&mut self, path: &Path, style: &StrokeStyle, paint: &Paint) {
+        let transform = self.device_transform();
+        let outlines = tessellator::stroke_style(&self.flatten(path), style, &transform);
+        let outlines = self.to_pixels(outlines);
+        self.fill_edges(&tessellator::edges(&outlines), FillRule::NonZero, paint);
+    }
+
+    fn draw_image(
+        &mut self,
+        image: ImageId,
+        src_rect: Rect,
+        dst_rect: Rect,
+        options: &ImageOptions,
+    ) {
+        let Some((inverse, pixel)) = local_space(&self.device_transform()) else {
+            return;
+        };
+        for (src, dst) in image::slices(src_rect, dst_rect, options.nine_slice) {
+            let edges = tessellator::edges(&self.to_pixels(self.flatten(&Path::from_rect(dst))));
+            let Some(Some(levels)) = self.images.get(image.value()) else {
+                return;
+            };
+            let scale = [src.width() / dst.width(), src.height() / dst.height()];
+            // Levels to blend and the weight of the second one, as the
+            // trilinear filtering of the GPU.
+            let (level, next, t) = match options.sampling {
+                Sampling::Mipmapped => {
+                    let texels = (pixel * scale[0].max(scale[1])).max(1.0);
+                    let lod = texels.log2().min((levels.len() - 1) as Float);
+                    let level = lod.floor() as usize;
+                    let next = (level + 1).min(levels.len() - 1);
+                    (level, next, lod - level as Float)
+                }
+                Sampling::Nearest | Sampling::Linear => (0, 0, 0.0),
+            };
+            let sample_level = |level: usize, u: Float, v: Float, nearest: bool| {
+                let texels = &levels[level];
+                let factor = [
+                    texels.width as Float / levels[0].width as Float,
+                    texels.height as Float / levels[0].height as Float,
+                ];
+                texels.sample(u * factor[0], v * factor[1], nearest)
+            };
+            let nearest = options.sampling == Sampling::Nearest;
+            let pixmap = &mut self.pixmap;
+            let clip = self.clips.last();
+            let (width, height) = (pixmap.width, pixmap.height);
+            rasterize(&edges, FillRule::NonZero, width, height, |y, x0, row| {
+                for (i, &coverage) in row.iter().enumerate() {
+                    if coverage <= 0.0 {
+                        continue;
+                    }
+                    let x = x0 + i as u32;
+                    let p = inverse.transform_point(Point::new(x as Float + 0.5, y as Float + 0.5));
+                    let u = src.min().x() + (p.x() - dst.min().x()) * scale[0];
+                    let v = src.min().y() + (p.y() - dst.min().y()) * scale[1];
+                    let mut color = sample_level(level, u, v, nearest);
+                    if t > 0.0 {
+                        let other = sample_level(next, u, v, nearest);
+                        for (c, o) in color.iter_mut().zip(other) {
+                            *c += (o - *c) * t;
+                        }
+                    }
+                    blend_clipped(pixmap, clip, x, y, color.map(|c| c * coverage));
+                }
+            });
+        }
+    }
+
+    fn draw_glyphs(&mut self, glyphs: &[Glyph], paint: &Paint) {
+        let transform = self.device_transform();
+        let Some((inverse, _)) = local_space(&transform) else {
+            return;
+        };
+        let sampler = PaintSampler::transformed(paint, &transform);
+        for glyph in glyphs {
+            let (src, dst) = (glyph.src_rect, glyph.dst_rect);
+            if dst.width() <= 0.0 || dst.height() <= 0.0 {
+                continue;
+            }
+            let edges = tessellator::edges(&self.to_pixels(self.flatten(&Path::from_rect(dst))));
+            let Some(Some(levels)) = self.images.get(glyph.image.value()) else {
+                continue;
+            };
+            let scale = [src.width() / dst.width(), src.height() / dst.height()];
+            let pixmap = &mut self.pixmap;
+            let clip = self.clips.last();
+            let (width, height) = (pixmap.width, pixmap.height);
+            rasterize(&edges, FillRule::NonZero, width, height, |y, x0, row| {
+                for (i, &coverage) in row.iter().enumerate() {
+                    if coverage <= 0.0 {
+                        continue;
+                    }
+                    let x = x0 + i as u32;
+                    let center = Point::new(x as Float + 0.5, y as Float + 0.5);
+                    let p = inverse.transform_point(center);
+                    let u = src.min().x() + (p.x() - dst.min().x()) * scale[0];
+                    let v = src.min().y() + (p.y() - dst.min().y()) * scale[1];
+                    let alpha = levels[0].sample(u, v, false)[3];
+                    let color = premultiply(sampler.sample(center), coverage * alpha);
+                    blend_clipped(pixmap, clip, x, y, color);
+                }
+            });
+        }
+    }
+
+    fn push_clip_rect(&mut self, rect: Rect) {
+        let transform = self.device_transform();
+        if !transform.is_axis_aligned() {
+            self.push_clip_path(&Path::from_rect(rect));
+            return;
+        }
+        let rect = transform.transform_rect(rect);
+        let (min, max) = (rect.min(), rect.max());
+        let snapped = Rect::from_float(
+            min.x().round(),
+            min.y().round(),
+            max.x().round(),
+            max.y().round(),
+        );
+        let bounds = pixel_bounds(snapped, self.pixmap.width, self.pixmap.height);
+        self.push_clip(bounds, None);
+    }
+
+    fn push_clip_rounded_rect(&mut self, rrect: RoundedRect) {
+        let transform = self.device_transform();
+        let Some((inverse, pixel)) = local_space(&transform) else {
+            self.push_clip((0, 0, 0, 0), None);
+            return;
+        };
+        let bounds = transform.transform_rect(rrect.rect());
+        let bounds = pixel_bounds(bounds, self.pixmap.width, self.pixmap.height);
+        let coverage = |x: u32, y: u32| {
+            let center = inverse.transform_point(Point::new(x as Float + 0.5, y as Float + 0.5));
+            effect::coverage(rrect.signed_distance(center) / pixel)
+        };
+        self.push_clip(bounds, Some(&coverage));
+    }
+
+    fn push_clip_path(&mut self, path: &Path) {
+        let (width, height) = (self.pixmap.width, self.pixmap.height);
+        let edges = tessellator::edges(&self.to_pixels(self.flatten(path)));
+        let mut data = vec![0.0; width as usize * height as usize];
+        let (mut x0, mut y0, mut x1, mut y1) = (width, height, 0, 0);
+        rasterize(&edges, path.fill_rule(), width, height, |y, start, row| {
+            let offset = y as usize * width as usize + start as usize;
+            data[offset..offset + row.len()].copy_from_slice(row);
+            (x0, y0) = (x0.min(start), y0.min(y));
+            (x1, y1) = (x1.max(start + row.len() as u32), y1.max(y + 1));
+        });
+        let coverage = |x: u32, y: u32| data[y as usize * width as usize + x as usize];
+        self.push_clip((x0, y0, x1.max(x0), y1.max(y0)), Some(&coverage));
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    fn push_blurred_layer(&mut self, opacity: Float, blend_mode: BlendMode, blur_radius: Float) {
+        let sigma = effect::blur_sigma(blur_radius) * self.device_transform().max_scale();
+        let layer = Pixmap::new(self.pixmap.width, self.pixmap.height);
+        self.layers.push(Layer {
+            parent: std::mem::replace(&mut self.pixmap, layer),
+            opacity: opacity.clamp(0.0, 1.0),
+            blend_mode,
+            sigma,
+        });
+    }
+
+    fn pop_layer(&mut self) {
+        let Some(layer) = self.layers.pop() else {
+            return;
+        };
+        let mut content = std::mem::replace(&mut self.pixmap, layer.parent);
+        if layer.sigma > 0.0 {
+            content.blur_sigma(layer.sigma);
+        }
+        let clip = self.clips.last();
+        let width = self.pixmap.width;
+        for (i, (src, dst)) in content
+            .data
+            .chunks_exact(4)
+            .zip(self.pixmap.data.chunks_exact_mut(4))
+            .enumerate()
+        {
+            if src[3] == 0 {
+                continue;
+            }
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            let scale = layer.opacity * clip.map_or(1.0, |clip| clip.coverage(width, x, y));
+            if scale <= 0.0 {
+                continue;
+            }
+            let src = std::array::from_fn(|c| src[c] as Float / 255.0 * scale);
+            let below = std::array::from_fn(|c| dst[c] as Float / 255.0);
+            let out = layer.blend_mode.composite(src, below);
+            for c in 0..4 {
+                dst[c] = to_u8(out[c]);
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        self.transforms.save();
+    }
+
+    fn restore(&mut self) {
+        self.transforms.restore();
+    }
+
+    fn transform(&self) -> Mat3 {
+        self.transforms.current()
+    }
+
+    fn set_transform(&mut self, transform: Mat3) {
+        self.transforms.set(transform);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Alpha of the pixel at `(x, y)`.
+    fn alpha(context: &CpuContext, x: u32, y: u32) -> u8 {
+        context.pixmap().pixel(x, y).unwrap()[3]
+    }
+
+    #[test]
+    fn nested_clips_intersect() {
+        let mut context = CpuContext::new(32, 32);
+        let all = Rect::from_float(0.0, 0.0, 32.0, 32.0);
+        context.push_clip_rect(Rect::from_float(0.0, 0.0, 20.0, 32.0));
+        context.push_clip_rounded_rect(RoundedRect::new(
+            Rect::from_float(4.0, 4.0, 28.0, 28.0),
+            8.0,
+        ));
+        // Triangle below the diagonal from the top right corner.
+        let mut triangle = Path::new();
+        triangle.move_to(Point::new(0.0, 0.0));
+        triangle.line_to(Point::new(32.0, 0.0));
+        triangle.line_to(Point::new(0.0, 32.0));
+        triangle.close();
+        context.push_clip_path(&triangle);
+        context.fill_rect(all, &Paint::solid([1.0; 4]));
+
+        assert_eq!(alpha(&context, 12, 12), 255);
+        // Outside the rect, the rounded corner and the triangle.
+        assert_eq!(alpha(&context, 24, 6), 0);
+        assert_eq!(alpha(&context, 5, 5), 0);
+        assert_eq!(alpha(&context, 15, 18), 0);
+        // Anti-aliased along the diagonal.
+        let edge = alpha(&context, 15, 16);
+        assert!(edge > 0 && edge < 255, "{edge}");
+
+        // Popping more clips than pushed leaves no clip.
+        for _ in 0..4 {
+            context.pop_clip();
+        }
+        context.fill_rect(all, &Paint::solid([1.0; 4]));
+        assert_eq!(alpha(&context, 30, 30), 255);
+    }
+
+    #[test]
+    fn empty_pixmaps_blur_to_nothing() {
+        let mut pixmap = Pixmap::new(0, 4);
+        pixmap.blur(4.0);
+        assert!(pixmap.data().is_empty());
+
+        let mut context = CpuContext::new(0, 4);
+        context.push_blurred_layer(1.0, BlendMode::Normal, 4.0);
+        context.fill_rect(
+            Rect::from_float(0.0, 0.0, 4.0, 4.0),
+            &Paint::solid([1.0; 4]),
+        );
+        context.pop_layer();
+        assert_eq!(context.pixmap().height(), 4);
+    }
+}
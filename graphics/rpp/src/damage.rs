@@ -0,0 +1,482 @@
+//! Damage tracking between frames, to redraw only what changed.
+
+use crate::{
+    context::TransformStack,
+    display_list::{DisplayList, DrawCommand},
+    effect::blur_sigma,
+    geometry::{Float, Mat3, Rect},
+};
+
+/// Largest number of rects of a [`Damage`]. Closest rects are merged past
+/// it, as each rect is redrawn separately.
+pub const MAX_DAMAGE_RECTS: usize = 8;
+
+/// Area of a frame that changed since the previous one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Damage {
+    /// Everything has to be redrawn.
+    Full,
+    /// Only the pixels in these rects, in the coordinates of the target,
+    /// have to be redrawn. Nothing changed if empty.
+    Partial(Vec<Rect>),
+}
+
+impl Default for Damage {
+    fn default() -> Self {
+        Damage::Partial(Vec::new())
+    }
+}
+
+impl Damage {
+    /// Returns `true` if nothing has to be redrawn.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Damage::Partial(rects) if rects.is_empty())
+    }
+
+    pub fn is_full(&self) -> bool {
+        matches!(self, Damage::Full)
+    }
+
+    /// Damaged rects, or `None` if everything is damaged.
+    pub fn rects(&self) -> Option<&[Rect]> {
+        match self {
+            Damage::Full => None,
+            Damage::Partial(rects) => Some(rects),
+        }
+    }
+
+    /// Bounding box of the damaged rects. `None` if nothing or everything
+    /// is damaged.
+    pub fn bounds(&self) -> Option<Rect> {
+        let rects = self.rects()?;
+        let first = *rects.first()?;
+        Some(rects.iter().fold(first, |bounds, r| bounds.union(r)))
+    }
+
+    /// Adds `rect`, merging it with the rects it overlaps.
+    pub fn add(&mut self, rect: Rect) {
+        let Damage::Partial(rects) = self else {
+            return;
+        };
+        if rect.is_empty() {
+            return;
+        }
+        let mut rect = rect;
+        // Merged rects may overlap rects they did not before.
+        while let Some(i) = rects.iter().position(|r| r.intersects(&rect)) {
+            rect = rect.union(&rects.swap_remove(i));
+        }
+        rects.push(rect);
+        if rects.len() > MAX_DAMAGE_RECTS {
+            let (i, j) = closest_pair(rects);
+            let merged = rects[i].union(&rects[j]);
+            rects.swap_remove(j);
+            rects.swap_remove(i);
+            self.add(merged);
+        }
+    }
+
+    /// Adds the damage of `other`.
+    pub fn merge(&mut self, other: &Damage) {
+        match other {
+            Damage::Full => *self = Damage::Full,
+            Damage::Partial(rects) => rects.iter().for_each(|r| self.add(*r)),
+        }
+    }
+}
+
+/// Indices `i < j` of the rects whose union grows the least over their
+/// areas.
+fn closest_pair(rects: &[Rect]) -> (usize, usize) {
+    let area = |r: &Rect| r.width() * r.height();
+    let mut best = (0, 1, Float::INFINITY);
+    for i in 0..rects.len() {
+        for j in i + 1..rects.len() {
+            let cost = area(&rects[i].union(&rects[j])) - area(&rects[i]) - area(&rects[j]);
+            if cost < best.2 {
+                best = (i, j, cost);
+            }
+        }
+    }
+    (best.0, best.1)
+}
+
+/// Drawing command with the state it is drawn in.
+#[derive(Clone, Debug)]
+struct Item {
+    command: DrawCommand,
+    transform: Mat3,
+    /// Number of clips and layers containing the command.
+    depth: usize,
+    /// Area the command may change, in target coordinates. Layers cover
+    /// what is drawn into them.
+    bounds: Option<Rect>,
+}
+
+impl PartialEq for Item {
+    /// The bounds of layers are left out, as the commands drawn into them
+    /// are compared on their own.
+    fn eq(&self, other: &Self) -> bool {
+        self.command == other.command
+            && self.transform == other.transform
+            && self.depth == other.depth
+            && (matches!(self.command, DrawCommand::PushLayer { .. })
+                || self.bounds == other.bounds)
+    }
+}
+
+/// Clip or layer opened by a command.
+struct Scope {
+    /// Bounds of the clip, in target coordinates.
+    clip: Option<Rect>,
+    /// Item of the layer, growing with what is drawn into it.
+    layer: Option<usize>,
+}
+
+/// Computes the [`Damage`] between consecutive frames by diffing their
+/// [`DisplayList`]s.
+///
+/// Commands are compared with the transform, clips and layers they are
+/// drawn in. The damage is the bounds of the commands differing between
+/// both frames, after skipping the commands they share at their start and
+/// end, so that a few commands changing in place only damage their own
+/// bounds. Changes the display lists do not show, such as new contents of
+/// an image, are reported with [`DamageTracker::invalidate`].
+#[derive(Debug, Default)]
+pub struct DamageTracker {
+    /// Items of the previous frame, `None` before the first frame.
+    previous: Option<Vec<Item>>,
+    invalidated: Damage,
+}
+
+impl DamageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Damages `rect` in the next frame, in the coordinates of the target.
+    pub fn invalidate(&mut self, rect: Rect) {
+        self.invalidated.add(rect);
+    }
+
+    /// Damages the whole next frame, such as after a resize.
+    pub fn invalidate_all(&mut self) {
+        self.invalidated = Damage::Full;
+    }
+
+    /// Forgets the previous frame, so that the next one is fully damaged.
+    pub fn reset(&mut self) {
+        self.previous = None;
+        self.invalidated = Damage::default();
+    }
+
+    /// Returns the damage of the frame drawn by `list` since the previous
+    /// call, and keeps `list` to compare the next frame with. The first
+    /// frame is fully damaged.
+    pub fn update(&mut self, list: &DisplayList) -> Damage {
+        let items = items(list);
+        let mut damage = std::mem::take(&mut self.invalidated);
+        match &self.previous {
+            None => damage = Damage::Full,
+            Some(previous) => {
+                let prefix = previous
+                    .iter()
+                    .zip(&items)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                let suffix = previous[prefix..]
+                    .iter()
+                    .rev()
+                    .zip(items[prefix..].iter().rev())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                let changed = previous[prefix..previous.len() - suffix]
+                    .iter()
+                    .chain(&items[prefix..items.len() - suffix]);
+                for item in changed {
+                    if let Some(bounds) = item.bounds {
+                        damage.add(bounds);
+                    }
+                }
+                damage_backdrops(&items, &mut damage);
+            }
+        }
+        self.previous = Some(items);
+        damage
+    }
+}
+
+/// Adds the backdrop blurs and blurred layers reading damaged pixels to
+/// `damage`.
+fn damage_backdrops(items: &[Item], damage: &mut Damage) {
+    for item in items {
+        let (bounds, read) = match (&item.command, item.bounds) {
+            (DrawCommand::BackdropBlur { blur_radius, .. }, Some(bounds)) => (
+                bounds,
+                bounds.outset(blur_margin(*blur_radius, &item.transform)),
+            ),
+            // The bounds of blurred layers already cover what they read.
+            (DrawCommand::PushLayer { blur_radius, .. }, Some(bounds)) if *blur_radius > 0.0 => {
+                (bounds, bounds)
+            }
+            _ => continue,
+        };
+        let reads_damage = damage
+            .rects()
+            .is_some_and(|rects| rects.iter().any(|r| r.intersects(&read)));
+        if reads_damage {
+            damage.add(bounds);
+        }
+    }
+}
+
+/// Commands of `list` with the state they are drawn in. State commands
+/// are folded into the items they affect.
+fn items(list: &DisplayList) -> Vec<Item> {
+    let mut items: Vec<Item> = Vec::with_capacity(list.len());
+    let mut transforms = TransformStack::default();
+    let mut scopes: Vec<Scope> = Vec::new();
+    for command in list.commands() {
+        let transform = transforms.current();
+        let clip = scopes.last().and_then(|s| s.clip);
+        // Area in target coordinates of `rect`, grown for anti-aliasing and
+        // restricted to the clip.
+        let area = |rect: Option<Rect>| {
+            let rect = transform.transform_rect(rect?).outset(1.0);
+            match clip {
+                Some(clip) => rect.intersection(&clip),
+                None => Some(rect),
+            }
+        };
+        let bounds = match command {
+            DrawCommand::FillRect { rect, .. } => area(Some(*rect)),
+            DrawCommand::FillRoundedRect { rrect, .. } => area(Some(rrect.rect())),
+            DrawCommand::DrawBoxShadow { rrect, shadow } => area(Some(shadow.bounds(*rrect))),
+            DrawCommand::BackdropBlur { rrect, .. } => area(Some(rrect.rect())),
+            DrawCommand::FillPath { path, .. } => area(path.bounds()),
+            DrawCommand::StrokePath { path, style, .. } if style.hairline => area(path.bounds())
+                .map(|b| b.outset(style.outset()))
+                .and_then(|b| clip.map_or(Some(b), |clip| b.intersection(&clip))),
+            DrawCommand::StrokePath { path, style, .. } => {
+                area(path.bounds().map(|b| b.outset(style.outset())))
+            }
+            DrawCommand::DrawImage { dst_rect, .. } => area(Some(*dst_rect)),
+            DrawCommand::DrawGlyphs { glyphs, .. } => {
+                area(glyphs.iter().map(|g| g.dst_rect).reduce(|a, b| a.union(&b)))
+            }
+            DrawCommand::PushClipRect(rect) => area(Some(*rect)),
+            DrawCommand::PushClipRoundedRect(rrect) => area(Some(rrect.rect())),
+            DrawCommand::PushClipPath(path) => area(path.bounds()),
+            DrawCommand::PushLayer { .. } => None,
+            DrawCommand::Save => {
+                transforms.save();
+                continue;
+            }
+            DrawCommand::Restore => {
+                transforms.restore();
+                continue;
+            }
+            DrawCommand::SetTransform(transform) => {
+                transforms.set(*transform);
+                continue;
+            }
+            DrawCommand::PopClip | DrawCommand::PopLayer => {
+                let Some(scope) = scopes.pop() else {
+                    continue;
+                };
+                // Layers damage their parent layer where they are drawn, and
+                // as far as their blur spreads it.
+                if let Some(i) = scope.layer {
+                    if let DrawCommand::PushLayer { blur_radius, .. } = items[i].command
+                        && blur_radius > 0.0
+                    {
+                        let margin = blur_margin(blur_radius, &items[i].transform);
+                        items[i].bounds = items[i].bounds.and_then(|b| {
+                            let b = b.outset(margin);
+                            scope.clip.map_or(Some(b), |clip| b.intersection(&clip))
+                        });
+                    }
+                    if let Some(bounds) = items[i].bounds {
+                        grow_layer(&mut items, &scopes, bounds);
+                    }
+                }
+                continue;
+            }
+        };
+        let depth = scopes.len();
+        match command {
+            DrawCommand::PushClipRect(_)
+            | DrawCommand::PushClipRoundedRect(_)
+            | DrawCommand::PushClipPath(_) => scopes.push(Scope {
+                // Nothing is drawn inside empty clips.
+                clip: Some(bounds.unwrap_or(Rect::from_float(0.0, 0.0, 0.0, 0.0))),
+                layer: None,
+            }),
+            DrawCommand::PushLayer { .. } => scopes.push(Scope {
+                clip,
+                layer: Some(items.len()),
+            }),
+            _ => {
+                if let Some(bounds) = bounds {
+                    grow_layer(&mut items, &scopes, bounds);
+                }
+            }
+        }
+        items.push(Item {
+            command: command.clone(),
+            transform,
+            depth,
+            bounds,
+        });
+    }
+    items
+}
+
+/// Distance in target coordinates a blur of `blur_radius` drawn with
+/// `transform` spreads content by.
+fn blur_margin(blur_radius: Float, transform: &Mat3) -> Float {
+    3.0 * blur_sigma(blur_radius) * transform.max_scale()
+}
+
+/// Adds `bounds` to the bounds of the innermost layer of `scopes`.
+fn grow_layer(items: &mut [Item], scopes: &[Scope], bounds: Rect) {
+    if let Some(i) = scopes.iter().rev().find_map(|s| s.layer) {
+        let layer = &mut items[i].bounds;
+        *layer = Some(layer.map_or(bounds, |b| b.union(&bounds)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::Context,
+        paint::{BlendMode, Paint},
+    };
+
+    fn rect(x0: Float, y0: Float, x1: Float, y1: Float) -> Rect {
+        Rect::from_float(x0, y0, x1, y1)
+    }
+
+    fn red() -> Paint {
+        Paint::solid([1.0, 0.0, 0.0, 1.0])
+    }
+
+    #[test]
+    fn merges_overlapping_rects() {
+        let mut damage = Damage::default();
+        assert!(damage.is_empty());
+        damage.add(rect(0.0, 0.0, 10.0, 10.0));
+        damage.add(rect(20.0, 0.0, 30.0, 10.0));
+        assert_eq!(damage.rects().unwrap().len(), 2);
+        // Bridges both rects, which are merged with it.
+        damage.add(rect(5.0, 0.0, 25.0, 5.0));
+        assert_eq!(damage, Damage::Partial(vec![rect(0.0, 0.0, 30.0, 10.0)]));
+        damage.add(rect(5.0, 5.0, 5.0, 20.0));
+        assert_eq!(damage.rects().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn limits_the_number_of_rects() {
+        let mut damage = Damage::default();
+        for i in 0..MAX_DAMAGE_RECTS + 3 {
+            let x = i as Float * 20.0;
+            damage.add(rect(x, 0.0, x + 10.0, 10.0));
+        }
+        let rects = damage.rects().unwrap();
+        assert!(rects.len() <= MAX_DAMAGE_RECTS);
+        assert_eq!(damage.bounds(), Some(rect(0.0, 0.0, 210.0, 10.0)));
+
+        damage.merge(&Damage::Full);
+        assert!(damage.is_full());
+        damage.add(rect(0.0, 0.0, 1.0, 1.0));
+        assert!(damage.is_full());
+    }
+
+    #[test]
+    fn first_frame_is_fully_damaged() {
+        let mut tracker = DamageTracker::new();
+        let mut list = DisplayList::new();
+        list.fill_rect(rect(0.0, 0.0, 10.0, 10.0), &red());
+        assert!(tracker.update(&list).is_full());
+        assert!(tracker.update(&list).is_empty());
+        tracker.reset();
+        assert!(tracker.update(&list).is_full());
+    }
+
+    #[test]
+    fn damages_changed_and_removed_commands() {
+        let mut tracker = DamageTracker::new();
+        let mut list = DisplayList::new();
+        list.fill_rect(rect(0.0, 0.0, 10.0, 10.0), &red());
+        list.fill_rect(rect(20.0, 20.0, 30.0, 30.0), &red());
+        list.fill_rect(rect(40.0, 40.0, 50.0, 50.0), &red());
+        tracker.update(&list);
+
+        // The middle rect is removed: the commands around it are shared.
+        let mut next = DisplayList::new();
+        next.fill_rect(rect(0.0, 0.0, 10.0, 10.0), &red());
+        next.fill_rect(rect(40.0, 40.0, 50.0, 50.0), &red());
+        assert_eq!(
+            tracker.update(&next),
+            Damage::Partial(vec![rect(19.0, 19.0, 31.0, 31.0)])
+        );
+
+        // Moved by a transform.
+        let mut moved = DisplayList::new();
+        moved.fill_rect(rect(0.0, 0.0, 10.0, 10.0), &red());
+        moved.set_transform(Mat3::translate(5.0, 0.0));
+        moved.fill_rect(rect(40.0, 40.0, 50.0, 50.0), &red());
+        assert_eq!(
+            tracker.update(&moved),
+            Damage::Partial(vec![rect(39.0, 39.0, 56.0, 51.0)])
+        );
+    }
+
+    #[test]
+    fn clips_restrict_damage() {
+        let mut tracker = DamageTracker::new();
+        let frame = |color: Paint| {
+            let mut list = DisplayList::new();
+            list.push_clip_rect(rect(0.0, 0.0, 5.0, 5.0));
+            list.fill_rect(rect(0.0, 0.0, 10.0, 10.0), &color);
+            list.pop_clip();
+            list
+        };
+        tracker.update(&frame(red()));
+        let damage = tracker.update(&frame(Paint::solid([0.0, 1.0, 0.0, 1.0])));
+        // The clip, grown for anti-aliasing.
+        assert_eq!(damage, Damage::Partial(vec![rect(-1.0, -1.0, 6.0, 6.0)]));
+    }
+
+    #[test]
+    fn blurred_layers_damage_what_their_blur_spreads() {
+        let mut tracker = DamageTracker::new();
+        let frame = |color: Paint| {
+            let mut list = DisplayList::new();
+            list.push_blurred_layer(1.0, BlendMode::Normal, 4.0);
+            list.fill_rect(rect(10.0, 10.0, 20.0, 20.0), &color);
+            list.pop_layer();
+            list
+        };
+        tracker.update(&frame(red()));
+        let damage = tracker.update(&frame(Paint::solid([0.0, 1.0, 0.0, 1.0])));
+        // The rect grown for anti-aliasing, then by three sigmas.
+        assert_eq!(damage, Damage::Partial(vec![rect(3.0, 3.0, 27.0, 27.0)]));
+    }
+
+    #[test]
+    fn invalidated_rects_are_added_once() {
+        let mut tracker = DamageTracker::new();
+        let list = DisplayList::new();
+        tracker.update(&list);
+        tracker.invalidate(rect(1.0, 1.0, 2.0, 2.0));
+        assert_eq!(
+            tracker.update(&list),
+            Damage::Partial(vec![rect(1.0, 1.0, 2.0, 2.0)])
+        );
+        assert!(tracker.update(&list).is_empty());
+        tracker.invalidate_all();
+        assert!(tracker.update(&list).is_full());
+    }
+}
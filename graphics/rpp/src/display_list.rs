@@ -0,0 +1,430 @@
+//! Recording of drawing commands for later replay.
+
+use crate::{
+    context::{Context, TransformStack},
+    effect::BoxShadow,
+    geometry::{Float, Mat3, Rect, RoundedRect},
+    image::{Glyph, ImageId, ImageOptions},
+    paint::{BlendMode, Border, Paint},
+    path::{Path, StrokeStyle},
+};
+
+/// A [`Context`] call recorded by a [`DisplayList`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DrawCommand {
+    FillRect {
+        rect: Rect,
+        paint: Paint,
+    },
+    FillRoundedRect {
+        rrect: RoundedRect,
+        paint: Paint,
+        border: Option<Border>,
+    },
+    DrawBoxShadow {
+        rrect: RoundedRect,
+        shadow: BoxShadow,
+    },
+    BackdropBlur {
+        rrect: RoundedRect,
+        blur_radius: Float,
+    },
+    FillPath {
+        path: Path,
+        paint: Paint,
+    },
+    StrokePath {
+        path: Path,
+        style: StrokeStyle,
+        paint: Paint,
+    },
+    DrawImage {
+        image: ImageId,
+        src_rect: Rect,
+        dst_rect: Rect,
+        options: ImageOptions,
+    },
+    DrawGlyphs {
+        glyphs: Vec<Glyph>,
+        paint: Paint,
+    },
+    PushClipRect(Rect),
+    PushClipRoundedRect(RoundedRect),
+    PushClipPath(Path),
+    PopClip,
+    PushLayer {
+        opacity: Float,
+        blend_mode: BlendMode,
+        blur_radius: Float,
+    },
+    PopLayer,
+    Save,
+    Restore,
+    /// Transform relative to the one of the context replayed onto.
+    SetTransform(Mat3),
+}
+
+/// [`Context`] recording drawing commands, to replay them onto other
+/// contexts later.
+///
+/// Transforms are recorded relative to the start of the list, so a list
+/// replayed under a transform is drawn under it. Images are recorded by id
+/// and have to exist in the replaying backend.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "DisplayListFields")
+)]
+pub struct DisplayList {
+    commands: Vec<DrawCommand>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    transforms: TransformStack,
+}
+
+impl DisplayList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn commands(&self) -> &[DrawCommand] {
+        &self.commands
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Appends a command, tracking the transforms it sets.
+    pub fn push(&mut self, command: DrawCommand) {
+        match &command {
+            DrawCommand::Save => self.transforms.save(),
+            DrawCommand::Restore => self.transforms.restore(),
+            DrawCommand::SetTransform(transform) => self.transforms.set(*transform),
+            _ => {}
+        }
+        self.commands.push(command);
+    }
+
+    /// Appends the commands of `other`, drawn under the current transform.
+    pub fn extend(&mut self, other: &DisplayList) {
+        other.replay(self);
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+        self.transforms.clear();
+    }
+
+    /// Issues the recorded commands to `context`, under its current
+    /// transform. The transform of `context` is restored afterwards.
+    ///
+    /// Unbalanced commands never reach the state of `context` from before
+    /// the replay: extra restores and pops are skipped, and saves, clips and
+    /// layers left open are closed at the end.
+    pub fn replay(&self, context: &mut impl Context) {
+        let base = context.transform();
+        let mut open = Vec::new();
+        for command in &self.commands {
+            match command {
+                DrawCommand::Save => open.push(Scope::Save),
+                DrawCommand::PushClipRect(_)
+                | DrawCommand::PushClipRoundedRect(_)
+                | DrawCommand::PushClipPath(_) => open.push(Scope::Clip),
+                DrawCommand::PushLayer { .. } => open.push(Scope::Layer),
+                DrawCommand::Restore | DrawCommand::PopClip | DrawCommand::PopLayer => {
+                    let scope = match command {
+                        DrawCommand::Restore => Scope::Save,
+                        DrawCommand::PopClip => Scope::Clip,
+                        _ => Scope::Layer,
+                    };
+                    let Some(i) = open.iter().rposition(|s| *s == scope) else {
+                        continue;
+                    };
+                    open.remove(i);
+                }
+                _ => {}
+            }
+            match command {
+                DrawCommand::FillRect { rect, paint } => context.fill_rect(*rect, paint),
+                DrawCommand::FillRoundedRect {
+                    rrect,
+                    paint,
+                    border,
+                } => context.fill_rounded_rect(*rrect, paint, *border),
+                DrawCommand::DrawBoxShadow { rrect, shadow } => {
+                    context.draw_box_shadow(*rrect, *shadow)
+                }
+                DrawCommand::BackdropBlur { rrect, blur_radius } => {
+                    context.backdrop_blur(*rrect, *blur_radius)
+                }
+                DrawCommand::FillPath { path, paint } => context.fill_path(path, paint),
+                DrawCommand::StrokePath { path, style, paint } => {
+                    context.stroke_path(path, style, paint)
+                }
+                DrawCommand::DrawImage {
+                    image,
+                    src_rect,
+                    dst_rect,
+                    options,
+                } => context.draw_image(*image, *src_rect, *dst_rect, options),
+                DrawCommand::DrawGlyphs { glyphs, paint } => context.draw_glyphs(glyphs, paint),
+                DrawCommand::PushClipRect(rect) => context.push_clip_rect(*rect),
+                DrawCommand::PushClipRoundedRect(rrect) => context.push_clip_rounded_rect(*rrect),
+                DrawCommand::PushClipPath(path) => context.push_clip_path(path),
+                DrawCommand::PopClip => context.pop_clip(),
+                DrawCommand::PushLayer {
+                    opacity,
+                    blend_mode,
+                    blur_radius,
+                } => context.push_blurred_layer(*opacity, *blend_mode, *blur_radius),
+                DrawCommand::PopLayer => context.pop_layer(),
+                DrawCommand::Save => context.save(),
+                DrawCommand::Restore => context.restore(),
+                DrawCommand::SetTransform(transform) => {
+                    context.set_transform(base.multiply(transform))
+                }
+            }
+        }
+        for scope in open.into_iter().rev() {
+            match scope {
+                Scope::Save => context.restore(),
+                Scope::Clip => context.pop_clip(),
+                Scope::Layer => context.pop_layer(),
+            }
+        }
+        context.set_transform(base);
+    }
+}
+
+/// Fields of a deserialized [`DisplayList`], whose transforms are tracked
+/// again by pushing the commands.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct DisplayListFields {
+    commands: Vec<DrawCommand>,
+}
+
+#[cfg(feature = "serde")]
+impl From<DisplayListFields> for DisplayList {
+    fn from(fields: DisplayListFields) -> Self {
+        let mut list = DisplayList::new();
+        for command in fields.commands {
+            list.push(command);
+        }
+        list
+    }
+}
+
+/// State pushed onto a [`Context`] while replaying a [`DisplayList`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Save,
+    Clip,
+    Layer,
+}
+
+impl Context for DisplayList {
+    fn fill_rect(&mut self, rect: Rect, paint: &Paint) {
+        self.push(DrawCommand::FillRect {
+            rect,
+            paint: paint.clone(),
+        });
+    }
+
+    fn fill_rounded_rect(&mut self, rrect: RoundedRect, paint: &Paint, border: Option<Border>) {
+        self.push(DrawCommand::FillRoundedRect {
+            rrect,
+            paint: paint.clone(),
+            border,
+        });
+    }
+
+    fn draw_box_shadow(&mut self, rrect: RoundedRect, shadow: BoxShadow) {
+        self.push(DrawCommand::DrawBoxShadow { rrect, shadow });
+    }
+
+    fn backdrop_blur(&mut self, rrect: RoundedRect, blur_radius: Float) {
+        self.push(DrawCommand::BackdropBlur { rrect, blur_radius });
+    }
+
+    fn fill_path(&mut self, path: &Path, paint: &Paint) {
+        self.push(DrawCommand::FillPath {
+            path: path.clone(),
+            paint: paint.clone(),
+        });
+    }
+
+    fn stroke_path(&mut self, path: &Path, style: &StrokeStyle, paint: &Paint) {
+        self.push(DrawCommand::StrokePath {
+            path: path.clone(),
+            style: style.clone(),
+            paint: paint.clone(),
+        });
+    }
+
+    fn draw_image(
+        &mut self,
+        image: ImageId,
+        src_rect: Rect,
+        dst_rect: Rect,
+        options: &ImageOptions,
+    ) {
+        self.push(DrawCommand::DrawImage {
+            image,
+            src_rect,
+            dst_rect,
+            options: *options,
+        });
+    }
+
+    fn draw_glyphs(&mut self, glyphs: &[Glyph], paint: &Paint) {
+        self.push(DrawCommand::DrawGlyphs {
+            glyphs: glyphs.to_vec(),
+            paint: paint.clone(),
+        });
+    }
+
+    fn push_clip_rect(&mut self, rect: Rect) {
+        self.push(DrawCommand::PushClipRect(rect));
+    }
+
+    fn push_clip_rounded_rect(&mut self, rrect: RoundedRect) {
+        self.push(DrawCommand::PushClipRoundedRect(rrect));
+    }
+
+    fn push_clip_path(&mut self, path: &Path) {
+        self.push(DrawCommand::PushClipPath(path.clone()));
+    }
+
+    fn pop_clip(&mut self) {
+        self.push(DrawCommand::PopClip);
+    }
+
+    fn push_blurred_layer(&mut self, opacity: Float, blend_mode: BlendMode, blur_radius: Float) {
+        self.push(DrawCommand::PushLayer {
+            opacity,
+            blend_mode,
+            blur_radius,
+        });
+    }
+
+    fn pop_layer(&mut self) {
+        self.push(DrawCommand::PopLayer);
+    }
+
+    fn save(&mut self) {
+        self.push(DrawCommand::Save);
+    }
+
+    fn restore(&mut self) {
+        self.push(DrawCommand::Restore);
+    }
+
+    fn transform(&self) -> Mat3 {
+        self.transforms.current()
+    }
+
+    fn set_transform(&mut self, transform: Mat3) {
+        self.push(DrawCommand::SetTransform(transform));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect() -> Rect {
+        Rect::from_float(0.0, 0.0, 10.0, 10.0)
+    }
+
+    #[test]
+    fn replay_skips_extra_pops() {
+        let mut list = DisplayList::new();
+        list.pop_clip();
+        list.restore();
+        list.pop_layer();
+        list.fill_rect(rect(), &Paint::solid([1.0; 4]));
+
+        let mut target = DisplayList::new();
+        target.push_clip_rect(rect());
+        list.replay(&mut target);
+        assert_eq!(
+            target.commands(),
+            [
+                DrawCommand::PushClipRect(rect()),
+                DrawCommand::FillRect {
+                    rect: rect(),
+                    paint: Paint::solid([1.0; 4]),
+                },
+                DrawCommand::SetTransform(Mat3::identity()),
+            ]
+        );
+    }
+
+    #[test]
+    fn replay_closes_open_state() {
+        let mut list = DisplayList::new();
+        list.save();
+        list.push_layer(0.5, BlendMode::Normal);
+        list.push_clip_rect(rect());
+        list.save();
+        list.restore();
+        list.pop_layer();
+
+        let mut target = DisplayList::new();
+        list.replay(&mut target);
+        let commands: Vec<_> = target.commands()[6..].to_vec();
+        assert_eq!(
+            commands,
+            [
+                DrawCommand::PopClip,
+                DrawCommand::Restore,
+                DrawCommand::SetTransform(Mat3::identity()),
+            ]
+        );
+    }
+
+    #[test]
+    fn replay_draws_under_the_current_transform() {
+        let mut list = DisplayList::new();
+        list.set_transform(Mat3::scale(2.0, 2.0));
+
+        let mut target = DisplayList::new();
+        target.set_transform(Mat3::translate(5.0, 0.0));
+        list.replay(&mut target);
+        assert_eq!(
+            target.commands()[1],
+            DrawCommand::SetTransform(Mat3::translate(5.0, 0.0).multiply(&Mat3::scale(2.0, 2.0)))
+        );
+        assert_eq!(target.transform(), Mat3::translate(5.0, 0.0));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialized_list_keeps_its_transform() {
+        let mut list = DisplayList::new();
+        list.save();
+        list.translate(5.0, 0.0);
+        list.fill_rect(rect(), &Paint::solid([1.0; 4]));
+        list.scale(2.0, 2.0);
+        let glyph = Glyph {
+            image: ImageId::new(1),
+            src_rect: rect(),
+            dst_rect: rect(),
+        };
+        list.draw_glyphs(&[glyph], &Paint::solid([1.0; 4]));
+
+        let json = serde_json::to_string(&list).unwrap();
+        let mut deserialized: DisplayList = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, list);
+        assert_eq!(deserialized.transform(), list.transform());
+        deserialized.restore();
+        assert_eq!(deserialized.transform(), Mat3::identity());
+    }
+}
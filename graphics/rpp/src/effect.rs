@@ -0,0 +1,180 @@
+//! Shadows and blurs shared by the backends.
+
+use crate::{
+    geometry::{CornerRadii, Float, Point, Rect, RoundedRect, Vector},
+    paint::ColorF,
+};
+
+/// Largest number of texels sampled on each side of a blurred texel.
+pub(crate) const MAX_KERNEL_RADIUS: u32 = 64;
+
+/// Standard deviation of the Gaussian for a CSS style blur radius.
+#[inline]
+pub(crate) fn blur_sigma(blur_radius: Float) -> Float {
+    blur_radius.max(0.0) * 0.5
+}
+
+/// Number of texels sampled on each side of a blurred texel.
+#[inline]
+pub(crate) fn kernel_radius(sigma: Float) -> u32 {
+    ((sigma * 3.0).ceil().max(0.0) as u32).min(MAX_KERNEL_RADIUS)
+}
+
+/// Normalized weights of a Gaussian kernel, from `-radius` to `radius`.
+pub(crate) fn gaussian_kernel(sigma: Float) -> Vec<Float> {
+    let radius = kernel_radius(sigma) as i32;
+    if radius == 0 {
+        return vec![1.0];
+    }
+    let mut weights: Vec<Float> = (-radius..=radius)
+        .map(|i| (-((i * i) as Float) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: Float = weights.iter().sum();
+    weights.iter_mut().for_each(|w| *w /= sum);
+    weights
+}
+
+/// Error function, within `1.5e-7` of the exact value (Abramowitz and
+/// Stegun 7.1.26). The WGSL shader uses the same approximation.
+pub(crate) fn erf(x: Float) -> Float {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let y = 1.0
+        - (((((1.061_405_4 * t - 1.453_152_1) * t) + 1.421_413_7) * t - 0.284_496_74) * t
+            + 0.254_829_6)
+            * t
+            * (-x * x).exp();
+    y.copysign(x)
+}
+
+/// Anti-aliased coverage for a signed distance, over one pixel.
+#[inline]
+pub(crate) fn coverage(distance: Float) -> Float {
+    (0.5 - distance).clamp(0.0, 1.0)
+}
+
+/// Shadow cast by a rounded rectangle, as CSS `box-shadow`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BoxShadow {
+    pub offset: Vector,
+    /// Blur radius, twice the standard deviation of the Gaussian.
+    pub blur_radius: Float,
+    /// Grows drop shadows, or shrinks the hole of inner shadows.
+    pub spread: Float,
+    pub color: ColorF,
+    /// Inner shadows are drawn inside the shape instead of around it.
+    pub inset: bool,
+}
+
+impl BoxShadow {
+    pub fn drop(
+        offset: Vector,
+        blur_radius: Float,
+        spread: Float,
+        color: impl Into<ColorF>,
+    ) -> Self {
+        BoxShadow {
+            offset,
+            blur_radius,
+            spread,
+            color: color.into(),
+            inset: false,
+        }
+    }
+
+    pub fn inner(
+        offset: Vector,
+        blur_radius: Float,
+        spread: Float,
+        color: impl Into<ColorF>,
+    ) -> Self {
+        BoxShadow {
+            inset: true,
+            ..Self::drop(offset, blur_radius, spread, color)
+        }
+    }
+
+    pub(crate) fn sigma(&self) -> Float {
+        blur_sigma(self.blur_radius)
+    }
+
+    /// Shape whose blurred coverage forms the shadow of `rrect`: the drop
+    /// shadow itself, or the hole of an inner shadow.
+    pub(crate) fn shape(&self, rrect: RoundedRect) -> RoundedRect {
+        let spread = if self.inset {
+            -self.spread
+        } else {
+            self.spread
+        };
+        let mut rect = (rrect.rect() + self.offset).outset(spread);
+        // Shapes shrunk past their center collapse to it.
+        for axis in 0..2 {
+            if rect.r[0][axis] > rect.r[1][axis] {
+                let center = (rect.r[0][axis] + rect.r[1][axis]) * 0.5;
+                rect.r[0][axis] = center;
+                rect.r[1][axis] = center;
+            }
+        }
+        // As in CSS, square corners stay square.
+        let radii = rrect
+            .radii()
+            .to_array()
+            .map(|r| if r > 0.0 { (r + spread).max(0.0) } else { 0.0 });
+        RoundedRect::new(
+            rect,
+            CornerRadii::new(radii[0], radii[1], radii[2], radii[3]),
+        )
+    }
+
+    /// Area the shadow of `rrect` may cover.
+    pub(crate) fn bounds(&self, rrect: RoundedRect) -> Rect {
+        let rect = if self.inset {
+            rrect.rect()
+        } else {
+            // Three sigmas, uncapped as the blur is analytic.
+            let margin = (3.0 * self.sigma()).ceil();
+            self.shape(rrect).rect().outset(margin)
+        };
+        rect.outset(1.0)
+    }
+
+    /// Opacity of the shadow of `rrect` at `point`.
+    ///
+    /// The blur is approximated from the distance to the shadow shape, which
+    /// is exact along straight edges. Drop shadows are only drawn outside
+    /// `rrect`, and inner shadows only inside it.
+    pub(crate) fn alpha_at(&self, rrect: RoundedRect, point: Point) -> Float {
+        let d = self.shape(rrect).signed_distance(point);
+        let sigma = self.sigma();
+        let shadow = if sigma > 1e-3 {
+            0.5 - 0.5 * erf(d / (sigma * std::f32::consts::SQRT_2))
+        } else {
+            coverage(d)
+        };
+        let inside = coverage(rrect.signed_distance(point));
+        if self.inset {
+            (1.0 - shadow) * inside
+        } else {
+            shadow * (1.0 - inside)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drop_shadows_fade_out_inside_their_bounds() {
+        let rrect = RoundedRect::new(
+            Rect::from_float(0.0, 0.0, 100.0, 100.0),
+            CornerRadii::new(10.0, 10.0, 10.0, 10.0),
+        );
+        for blur_radius in [0.0, 10.0, 100.0, 300.0] {
+            let shadow = BoxShadow::drop(Vector::new(5.0, 5.0), blur_radius, 2.0, [0.0; 4]);
+            let bounds = shadow.bounds(rrect);
+            let edge = Point::new(bounds.max().x(), 55.0);
+            assert!(shadow.alpha_at(rrect, edge) < 0.002, "{blur_radius}");
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
 pub type Float = f32;
 
 pub trait ZeroValue {
@@ -11,6 +13,9 @@ impl ZeroValue for Float {
     }
 }
 
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Point {
     pub p: [Float; 2],
 }
@@ -60,8 +65,215 @@ impl From<Point> for [Float; 2] {
     }
 }
 
+impl From<Vector> for Point {
+    #[inline(always)]
+    fn from(value: Vector) -> Self {
+        Point { p: value.v }
+    }
+}
+
+impl Add<Vector> for Point {
+    type Output = Point;
+
+    #[inline(always)]
+    fn add(self, rhs: Vector) -> Point {
+        Point::new(self.x() + rhs.x(), self.y() + rhs.y())
+    }
+}
+
+impl AddAssign<Vector> for Point {
+    #[inline(always)]
+    fn add_assign(&mut self, rhs: Vector) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub<Vector> for Point {
+    type Output = Point;
+
+    #[inline(always)]
+    fn sub(self, rhs: Vector) -> Point {
+        Point::new(self.x() - rhs.x(), self.y() - rhs.y())
+    }
+}
+
+impl SubAssign<Vector> for Point {
+    #[inline(always)]
+    fn sub_assign(&mut self, rhs: Vector) {
+        *self = *self - rhs;
+    }
+}
+
+/// Offset from `rhs` to `self`.
+impl Sub for Point {
+    type Output = Vector;
+
+    #[inline(always)]
+    fn sub(self, rhs: Point) -> Vector {
+        Vector::new(self.x() - rhs.x(), self.y() - rhs.y())
+    }
+}
+
+/// Displacement between two [`Point`]s.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vector {
+    pub v: [Float; 2],
+}
+
+impl Vector {
+    #[inline(always)]
+    pub fn new(x: Float, y: Float) -> Self {
+        Vector { v: [x, y] }
+    }
+
+    #[inline(always)]
+    pub fn x(&self) -> Float {
+        self.v[0]
+    }
+
+    #[inline(always)]
+    pub fn y(&self) -> Float {
+        self.v[1]
+    }
+
+    #[inline(always)]
+    pub fn length(&self) -> Float {
+        self.dot(*self).sqrt()
+    }
+
+    #[inline(always)]
+    pub fn dot(&self, other: Vector) -> Float {
+        self.x() * other.x() + self.y() * other.y()
+    }
+
+    /// Z component of the 3D cross product, positive when `other` is
+    /// clockwise from `self` since the y axis points down.
+    #[inline(always)]
+    pub fn cross(&self, other: Vector) -> Float {
+        self.x() * other.y() - self.y() * other.x()
+    }
+
+    /// Vector of length one in the same direction, or zero for zero.
+    pub fn normalize(&self) -> Vector {
+        let length = self.length();
+        if length > 0.0 {
+            *self / length
+        } else {
+            Vector::zero()
+        }
+    }
+}
+
+impl ZeroValue for Vector {
+    #[inline(always)]
+    fn zero() -> Self {
+        Vector {
+            v: [Float::zero(), Float::zero()],
+        }
+    }
+}
+
+impl From<[Float; 2]> for Vector {
+    #[inline(always)]
+    fn from(v: [Float; 2]) -> Self {
+        Vector { v }
+    }
+}
+
+impl From<Vector> for [Float; 2] {
+    #[inline(always)]
+    fn from(value: Vector) -> Self {
+        value.v
+    }
+}
+
+impl From<Point> for Vector {
+    #[inline(always)]
+    fn from(value: Point) -> Self {
+        Vector { v: value.p }
+    }
+}
+
+impl From<Size> for Vector {
+    #[inline(always)]
+    fn from(value: Size) -> Self {
+        Vector { v: value.s }
+    }
+}
+
+impl Add for Vector {
+    type Output = Vector;
+
+    #[inline(always)]
+    fn add(self, rhs: Vector) -> Vector {
+        Vector::new(self.x() + rhs.x(), self.y() + rhs.y())
+    }
+}
+
+impl AddAssign for Vector {
+    #[inline(always)]
+    fn add_assign(&mut self, rhs: Vector) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Vector {
+    type Output = Vector;
+
+    #[inline(always)]
+    fn sub(self, rhs: Vector) -> Vector {
+        Vector::new(self.x() - rhs.x(), self.y() - rhs.y())
+    }
+}
+
+impl SubAssign for Vector {
+    #[inline(always)]
+    fn sub_assign(&mut self, rhs: Vector) {
+        *self = *self - rhs;
+    }
+}
+
+impl Neg for Vector {
+    type Output = Vector;
+
+    #[inline(always)]
+    fn neg(self) -> Vector {
+        Vector::new(-self.x(), -self.y())
+    }
+}
+
+impl Mul<Float> for Vector {
+    type Output = Vector;
+
+    #[inline(always)]
+    fn mul(self, rhs: Float) -> Vector {
+        Vector::new(self.x() * rhs, self.y() * rhs)
+    }
+}
+
+impl Mul<Vector> for Float {
+    type Output = Vector;
+
+    #[inline(always)]
+    fn mul(self, rhs: Vector) -> Vector {
+        rhs * self
+    }
+}
+
+impl Div<Float> for Vector {
+    type Output = Vector;
+
+    #[inline(always)]
+    fn div(self, rhs: Float) -> Vector {
+        Vector::new(self.x() / rhs, self.y() / rhs)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rect {
     pub r: [[Float; 2]; 2],
 }
@@ -88,6 +300,136 @@ impl Rect {
     pub fn max(&self) -> Point {
         self.r[1].into()
     }
+
+    #[inline(always)]
+    pub fn from_origin_size(origin: Point, size: Size) -> Self {
+        Rect::new(origin, origin + Vector::from(size))
+    }
+
+    /// Top left corner.
+    #[inline(always)]
+    pub fn origin(&self) -> Point {
+        self.min()
+    }
+
+    #[inline(always)]
+    pub fn width(&self) -> Float {
+        self.r[1][0] - self.r[0][0]
+    }
+
+    #[inline(always)]
+    pub fn height(&self) -> Float {
+        self.r[1][1] - self.r[0][1]
+    }
+
+    #[inline(always)]
+    pub fn size(&self) -> Size {
+        Size::new(self.width(), self.height())
+    }
+
+    #[inline(always)]
+    pub fn center(&self) -> Point {
+        Point::new(
+            (self.r[0][0] + self.r[1][0]) * 0.5,
+            (self.r[0][1] + self.r[1][1]) * 0.5,
+        )
+    }
+
+    /// Whether the rectangle has no area.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        !(self.r[0][0] < self.r[1][0] && self.r[0][1] < self.r[1][1])
+    }
+
+    /// Whether `point` is inside, including the top and left edges but not
+    /// the bottom and right ones, so that adjacent rects never both contain
+    /// a point.
+    #[inline(always)]
+    pub fn contains(&self, point: Point) -> bool {
+        point.x() >= self.r[0][0]
+            && point.x() < self.r[1][0]
+            && point.y() >= self.r[0][1]
+            && point.y() < self.r[1][1]
+    }
+
+    /// Whether both rectangles overlap with a non empty area.
+    #[inline(always)]
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// Overlap of both rectangles, or `None` if it is empty.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let min = [
+            self.r[0][0].max(other.r[0][0]),
+            self.r[0][1].max(other.r[0][1]),
+        ];
+        let max = [
+            self.r[1][0].min(other.r[1][0]),
+            self.r[1][1].min(other.r[1][1]),
+        ];
+        (min[0] < max[0] && min[1] < max[1]).then_some(Rect { r: [min, max] })
+    }
+
+    /// Smallest rectangle containing both. Empty rectangles are ignored.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        Rect::from_float(
+            self.r[0][0].min(other.r[0][0]),
+            self.r[0][1].min(other.r[0][1]),
+            self.r[1][0].max(other.r[1][0]),
+            self.r[1][1].max(other.r[1][1]),
+        )
+    }
+
+    /// Shrinks every side by `amount`. The result may be inverted, see
+    /// [`Rect::is_empty`].
+    #[inline(always)]
+    pub fn inset(&self, amount: Float) -> Rect {
+        self.outset(-amount)
+    }
+
+    /// Grows every side by `amount`.
+    #[inline(always)]
+    pub fn outset(&self, amount: Float) -> Rect {
+        Rect::from_float(
+            self.r[0][0] - amount,
+            self.r[0][1] - amount,
+            self.r[1][0] + amount,
+            self.r[1][1] + amount,
+        )
+    }
+}
+
+impl Add<Vector> for Rect {
+    type Output = Rect;
+
+    #[inline(always)]
+    fn add(self, rhs: Vector) -> Rect {
+        Rect::new(self.min() + rhs, self.max() + rhs)
+    }
+}
+
+impl Sub<Vector> for Rect {
+    type Output = Rect;
+
+    #[inline(always)]
+    fn sub(self, rhs: Vector) -> Rect {
+        Rect::new(self.min() - rhs, self.max() - rhs)
+    }
+}
+
+/// Rectangle of `size` at the origin.
+impl From<Size> for Rect {
+    #[inline(always)]
+    fn from(size: Size) -> Self {
+        Rect::from_origin_size(Point::zero(), size)
+    }
 }
 
 impl ZeroValue for Rect {
@@ -130,8 +472,146 @@ impl From<Rect> for [Float; 4] {
     }
 }
 
+/// Radii of the corners of a [`RoundedRect`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CornerRadii {
+    pub top_left: Float,
+    pub top_right: Float,
+    pub bottom_right: Float,
+    pub bottom_left: Float,
+}
+
+impl CornerRadii {
+    #[inline(always)]
+    pub fn new(top_left: Float, top_right: Float, bottom_right: Float, bottom_left: Float) -> Self {
+        CornerRadii {
+            top_left,
+            top_right,
+            bottom_right,
+            bottom_left,
+        }
+    }
+
+    #[inline(always)]
+    pub fn uniform(radius: Float) -> Self {
+        Self::new(radius, radius, radius, radius)
+    }
+
+    /// Radii in the order top left, top right, bottom right, bottom left.
+    #[inline(always)]
+    pub fn to_array(self) -> [Float; 4] {
+        [
+            self.top_left,
+            self.top_right,
+            self.bottom_right,
+            self.bottom_left,
+        ]
+    }
+}
+
+impl From<Float> for CornerRadii {
+    #[inline(always)]
+    fn from(radius: Float) -> Self {
+        Self::uniform(radius)
+    }
+}
+
+/// Rectangle with elliptical corners of equal width and height.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "RoundedRectFields")
+)]
+pub struct RoundedRect {
+    rect: Rect,
+    radii: CornerRadii,
+}
+
+impl RoundedRect {
+    /// Negative radii are treated as zero. As in CSS, all radii are scaled
+    /// down when adjacent corners would overlap.
+    pub fn new(rect: Rect, radii: impl Into<CornerRadii>) -> Self {
+        let [tl, tr, br, bl] = radii.into().to_array().map(|r| r.max(0.0));
+        let (min, max) = (rect.min(), rect.max());
+        let width = (max.x() - min.x()).max(0.0);
+        let height = (max.y() - min.y()).max(0.0);
+        let mut scale: Float = 1.0;
+        for (sum, side) in [
+            (tl + tr, width),
+            (bl + br, width),
+            (tl + bl, height),
+            (tr + br, height),
+        ] {
+            if sum > side {
+                scale = scale.min(side / sum);
+            }
+        }
+        RoundedRect {
+            rect,
+            radii: CornerRadii::new(tl * scale, tr * scale, br * scale, bl * scale),
+        }
+    }
+
+    #[inline(always)]
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    #[inline(always)]
+    pub fn radii(&self) -> CornerRadii {
+        self.radii
+    }
+
+    /// Distance from `point` to the outline, negative inside.
+    pub fn signed_distance(&self, point: Point) -> Float {
+        let (min, max) = (self.rect.min(), self.rect.max());
+        let half = [(max.x() - min.x()) * 0.5, (max.y() - min.y()) * 0.5];
+        let p = [
+            point.x() - (min.x() + half[0]),
+            point.y() - (min.y() + half[1]),
+        ];
+        let radius = match (p[0] < 0.0, p[1] < 0.0) {
+            (true, true) => self.radii.top_left,
+            (false, true) => self.radii.top_right,
+            (false, false) => self.radii.bottom_right,
+            (true, false) => self.radii.bottom_left,
+        };
+        let q = [p[0].abs() - half[0] + radius, p[1].abs() - half[1] + radius];
+        let outside = (q[0].max(0.0).powi(2) + q[1].max(0.0).powi(2)).sqrt();
+        q[0].max(q[1]).min(0.0) + outside - radius
+    }
+}
+
+/// Fields of a deserialized [`RoundedRect`], fitted by [`RoundedRect::new`].
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct RoundedRectFields {
+    rect: Rect,
+    radii: CornerRadii,
+}
+
+#[cfg(feature = "serde")]
+impl From<RoundedRectFields> for RoundedRect {
+    fn from(fields: RoundedRectFields) -> Self {
+        RoundedRect::new(fields.rect, fields.radii)
+    }
+}
+
+impl From<Rect> for RoundedRect {
+    #[inline(always)]
+    fn from(rect: Rect) -> Self {
+        RoundedRect {
+            rect,
+            radii: CornerRadii::default(),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Size {
     pub s: [Float; 2],
 }
@@ -151,22 +631,317 @@ impl Size {
     pub fn height(&self) -> Float {
         self.s[1]
     }
+
+    #[inline(always)]
+    pub fn area(&self) -> Float {
+        self.width() * self.height()
+    }
+}
+
+impl ZeroValue for Size {
+    #[inline(always)]
+    fn zero() -> Self {
+        Size {
+            s: [Float::zero(), Float::zero()],
+        }
+    }
+}
+
+impl From<[Float; 2]> for Size {
+    #[inline(always)]
+    fn from(s: [Float; 2]) -> Self {
+        Size { s }
+    }
+}
+
+impl From<Size> for [Float; 2] {
+    #[inline(always)]
+    fn from(value: Size) -> Self {
+        value.s
+    }
+}
+
+impl From<Vector> for Size {
+    #[inline(always)]
+    fn from(value: Vector) -> Self {
+        Size { s: value.v }
+    }
+}
+
+impl Mul<Float> for Size {
+    type Output = Size;
+
+    #[inline(always)]
+    fn mul(self, rhs: Float) -> Size {
+        Size::new(self.width() * rhs, self.height() * rhs)
+    }
+}
+
+impl Div<Float> for Size {
+    type Output = Size;
+
+    #[inline(always)]
+    fn div(self, rhs: Float) -> Size {
+        Size::new(self.width() / rhs, self.height() / rhs)
+    }
 }
 
 /// Matrix 3x3
+///
+/// Stored column-major, transforming column vectors `[x, y, 1]`.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Mat3 {
     pub m: [[Float; 3]; 3],
 }
 
+impl Mat3 {
+    #[inline(always)]
+    pub fn identity() -> Self {
+        [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]].into()
+    }
+
+    pub fn translate(dx: Float, dy: Float) -> Self {
+        [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [dx, dy, 1.0]].into()
+    }
+
+    pub fn scale(sx: Float, sy: Float) -> Self {
+        [[sx, 0.0, 0.0], [0.0, sy, 0.0], [0.0, 0.0, 1.0]].into()
+    }
+
+    /// Rotation by `angle` radians, clockwise since the y axis points down.
+    pub fn rotate(angle: Float) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        [[cos, sin, 0.0], [-sin, cos, 0.0], [0.0, 0.0, 1.0]].into()
+    }
+
+    /// Skew by `angle_x` radians along the x axis and `angle_y` radians
+    /// along the y axis, as CSS `skew()`.
+    pub fn skew(angle_x: Float, angle_y: Float) -> Self {
+        [
+            [1.0, angle_y.tan(), 0.0],
+            [angle_x.tan(), 1.0, 0.0],
+            [0.0, 0.0, 1.0],
+        ]
+        .into()
+    }
+
+    /// Returns `self * other`, which applies `other` first.
+    pub fn multiply(&self, other: &Mat3) -> Self {
+        let (a, b) = (&self.m, &other.m);
+        let mut m = [[0.0; 3]; 3];
+        for (col, out) in m.iter_mut().enumerate() {
+            for (row, v) in out.iter_mut().enumerate() {
+                *v = a[0][row] * b[col][0] + a[1][row] * b[col][1] + a[2][row] * b[col][2];
+            }
+        }
+        m.into()
+    }
+
+    /// Whether the matrix maps axis aligned rectangles to axis aligned
+    /// rectangles, which holds for translations and scales.
+    pub fn is_axis_aligned(&self) -> bool {
+        let m = &self.m;
+        m[0][1] == 0.0 && m[1][0] == 0.0 && m[0][2] == 0.0 && m[1][2] == 0.0
+    }
+
+    /// Largest factor lengths are scaled by.
+    pub(crate) fn max_scale(&self) -> Float {
+        let m = &self.m;
+        let (a, b, c, d) = (m[0][0], m[0][1], m[1][0], m[1][1]);
+        // Largest singular value of the linear part.
+        let s = a * a + b * b + c * c + d * d;
+        let det = a * d - b * c;
+        ((s + (s * s - 4.0 * det * det).max(0.0).sqrt()) * 0.5).sqrt()
+    }
+
+    pub fn determinant(&self) -> Float {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[2][1] * m[1][2])
+            - m[1][0] * (m[0][1] * m[2][2] - m[2][1] * m[0][2])
+            + m[2][0] * (m[0][1] * m[1][2] - m[1][1] * m[0][2])
+    }
+
+    /// Returns `None` if the matrix is singular.
+    ///
+    /// Only an exactly zero or non-finite determinant counts as singular:
+    /// a tolerance would reject small but valid scales.
+    pub fn invert(&self) -> Option<Self> {
+        let det = self.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        let m = &self.m;
+        let inv_det = 1.0 / det;
+        let cofactor = |a: usize, b: usize, c: usize, d: usize| {
+            (m[a][b] * m[c][d] - m[c][b] * m[a][d]) * inv_det
+        };
+        let inverse: Mat3 = [
+            [
+                cofactor(1, 1, 2, 2),
+                -cofactor(0, 1, 2, 2),
+                cofactor(0, 1, 1, 2),
+            ],
+            [
+                -cofactor(1, 0, 2, 2),
+                cofactor(0, 0, 2, 2),
+                -cofactor(0, 0, 1, 2),
+            ],
+            [
+                cofactor(1, 0, 2, 1),
+                -cofactor(0, 0, 2, 1),
+                cofactor(0, 0, 1, 1),
+            ],
+        ]
+        .into();
+        inverse
+            .m
+            .iter()
+            .flatten()
+            .all(|v| v.is_finite())
+            .then_some(inverse)
+    }
+
+    #[inline(always)]
+    pub fn transform_point(&self, point: Point) -> Point {
+        let m = &self.m;
+        let (x, y) = (point.x(), point.y());
+        Point::new(
+            m[0][0] * x + m[1][0] * y + m[2][0],
+            m[0][1] * x + m[1][1] * y + m[2][1],
+        )
+    }
+
+    /// Transforms `vector`, ignoring the translation.
+    #[inline(always)]
+    pub fn transform_vector(&self, vector: Vector) -> Vector {
+        let m = &self.m;
+        let (x, y) = (vector.x(), vector.y());
+        Vector::new(m[0][0] * x + m[1][0] * y, m[0][1] * x + m[1][1] * y)
+    }
+
+    /// Bounding box of the transformed corners of `rect`.
+    pub fn transform_rect(&self, rect: Rect) -> Rect {
+        let (min, max) = (rect.min(), rect.max());
+        let corners = [
+            self.transform_point(min),
+            self.transform_point(Point::new(max.x(), min.y())),
+            self.transform_point(max),
+            self.transform_point(Point::new(min.x(), max.y())),
+        ];
+        let (mut lo, mut hi) = ([Float::INFINITY; 2], [Float::NEG_INFINITY; 2]);
+        for corner in corners {
+            let p: [Float; 2] = corner.into();
+            for axis in 0..2 {
+                lo[axis] = lo[axis].min(p[axis]);
+                hi[axis] = hi[axis].max(p[axis]);
+            }
+        }
+        Rect { r: [lo, hi] }
+    }
+}
+
+impl Mul for Mat3 {
+    type Output = Mat3;
+
+    fn mul(self, rhs: Mat3) -> Mat3 {
+        self.multiply(&rhs)
+    }
+}
+
+impl From<[[Float; 3]; 3]> for Mat3 {
+    fn from(m: [[Float; 3]; 3]) -> Self {
+        Mat3 { m }
+    }
+}
+
+impl From<Mat3> for [[Float; 3]; 3] {
+    fn from(value: Mat3) -> Self {
+        value.m
+    }
+}
+
 /// Matrix 4x4
+///
+/// Stored column-major, like [`Mat3`].
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Mat4 {
     pub m: [[Float; 4]; 4],
 }
 
+impl Mat4 {
+    #[inline(always)]
+    pub fn identity() -> Self {
+        [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]
+        .into()
+    }
+
+    /// Returns `self * other`, which applies `other` first.
+    pub fn multiply(&self, other: &Mat4) -> Self {
+        let (a, b) = (&self.m, &other.m);
+        let mut m = [[0.0; 4]; 4];
+        for (col, out) in m.iter_mut().enumerate() {
+            for (row, v) in out.iter_mut().enumerate() {
+                *v = (0..4).map(|k| a[k][row] * b[col][k]).sum();
+            }
+        }
+        m.into()
+    }
+
+    /// Returns `None` if the matrix is singular.
+    pub fn invert(&self) -> Option<Self> {
+        // Gauss-Jordan elimination with partial pivoting, on rows of the
+        // transposed matrix: the inverse of the transpose is the transpose
+        // of the inverse, so the result comes out column-major.
+        let mut a = self.m;
+        let mut inv = Mat4::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))
+                .unwrap_or(col);
+            if a[pivot][col] == 0.0 || !a[pivot][col].is_finite() {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let scale = 1.0 / a[col][col];
+            for k in 0..4 {
+                a[col][k] *= scale;
+                inv[col][k] *= scale;
+            }
+            for row in 0..4 {
+                if row == col {
+                    continue;
+                }
+                let factor = a[row][col];
+                for k in 0..4 {
+                    a[row][k] -= factor * a[col][k];
+                    inv[row][k] -= factor * inv[col][k];
+                }
+            }
+        }
+        inv.iter()
+            .flatten()
+            .all(|v| v.is_finite())
+            .then(|| inv.into())
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        self.multiply(&rhs)
+    }
+}
+
 impl From<[[Float; 4]; 4]> for Mat4 {
     fn from(m: [[Float; 4]; 4]) -> Self {
         Mat4 { m }
@@ -179,6 +954,12 @@ impl From<Mat4> for [[Float; 4]; 4] {
     }
 }
 
+/// Area drawn into, in logical units.
+///
+/// Everything drawn is in logical units, which the scale factor maps to the
+/// pixels of the render target: a 100 x 50 viewport with a scale factor of
+/// 2 covers a 200 x 100 pixels target.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Viewport {
     pub width: Float,
     pub height: Float,
@@ -186,22 +967,78 @@ pub struct Viewport {
 }
 
 impl Viewport {
+    /// Creates a viewport of `width` x `height` logical units, with a scale
+    /// factor of 1.
+    pub fn new(width: Float, height: Float) -> Self {
+        Self::with_scale_factor(width, height, 1.0)
+    }
+
+    /// Creates a viewport of `width` x `height` logical units, each
+    /// `scale_factor` pixels wide. Non-positive scale factors are replaced
+    /// by 1.
+    pub fn with_scale_factor(width: Float, height: Float, scale_factor: Float) -> Self {
+        let mut viewport = Viewport {
+            width,
+            height,
+            scale_factor: 1.0,
+        };
+        viewport.set_scale_factor(scale_factor);
+        viewport
+    }
+
+    /// Creates a viewport covering a target of `width` x `height` pixels,
+    /// such as a window, with `scale_factor` pixels per logical unit.
+    pub fn from_physical(width: u32, height: u32, scale_factor: Float) -> Self {
+        let mut viewport = Self::new(0.0, 0.0);
+        viewport.set_scale_factor(scale_factor);
+        viewport.width = width as Float / viewport.scale_factor;
+        viewport.height = height as Float / viewport.scale_factor;
+        viewport
+    }
+
+    /// Pixels per logical unit.
+    pub fn scale_factor(&self) -> Float {
+        self.scale_factor
+    }
+
+    /// Sets the pixels per logical unit, keeping the logical size.
+    /// Non-positive scale factors are replaced by 1.
+    pub fn set_scale_factor(&mut self, scale_factor: Float) {
+        self.scale_factor = if scale_factor > 0.0 {
+            scale_factor
+        } else {
+            1.0
+        };
+    }
+
+    /// Size in logical units.
+    pub fn size(&self) -> Size {
+        Size::new(self.width, self.height)
+    }
+
+    /// Size of the render target in pixels.
+    pub fn physical_size(&self) -> (u32, u32) {
+        (
+            (self.width * self.scale_factor).round() as u32,
+            (self.height * self.scale_factor).round() as u32,
+        )
+    }
+
+    /// Projection of the logical units of the viewport to clip space.
     pub fn to_ortho(&self) -> Mat4 {
-        let width = self.width * self.scale_factor;
-        let height = self.height * self.scale_factor;
         let top = Float::zero();
-        let bottom = height;
+        let bottom = self.height;
         let left = Float::zero();
-        let right = width;
+        let right = self.width;
         let far = 1.0;
         let near = -1.0;
 
         [
-            [2.0 / (left - right), 0.0, 0.0, 0.0],
+            [2.0 / (right - left), 0.0, 0.0, 0.0],
             [0.0, 2.0 / (top - bottom), 0.0, 0.0],
             [0.0, 0.0, -2.0 / (far - near), 0.0],
             [
-                -(left + right) / (left - right),
+                -(right + left) / (right - left),
                 -(top + bottom) / (top - bottom),
                 -(far + near) / (far - near),
                 1.0,
@@ -210,3 +1047,139 @@ impl Viewport {
         .into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(a: &[Float], b: &[Float]) {
+        for (x, y) in a.iter().zip(b) {
+            assert!((x - y).abs() < 1e-4, "{a:?} != {b:?}");
+        }
+    }
+
+    fn assert_mat3_near(a: Mat3, b: Mat3) {
+        assert_near(a.m.as_flattened(), b.m.as_flattened());
+    }
+
+    fn assert_mat4_near(a: Mat4, b: Mat4) {
+        assert_near(a.m.as_flattened(), b.m.as_flattened());
+    }
+
+    #[test]
+    fn mat3_invert_round_trips() {
+        let m = Mat3::translate(10.0, -4.0)
+            .multiply(&Mat3::rotate(0.7))
+            .multiply(&Mat3::scale(2.0, 0.5));
+        let inverse = m.invert().unwrap();
+        assert_mat3_near(m.multiply(&inverse), Mat3::identity());
+        assert_mat3_near(inverse.multiply(&m), Mat3::identity());
+    }
+
+    #[test]
+    fn mat3_invert_small_scale() {
+        let m = Mat3::translate(1000.0, 1000.0).multiply(&Mat3::scale(3e-4, 3e-4));
+        let inverse = m.invert().unwrap();
+        let p = inverse.transform_point(m.transform_point(Point::new(5.0, 7.0)));
+        assert_near(&p.p, &[5.0, 7.0]);
+    }
+
+    #[test]
+    fn mat3_transforms_vectors_without_translating() {
+        let m = Mat3::translate(10.0, -4.0).multiply(&Mat3::scale(2.0, 3.0));
+        let v = m.transform_vector(Vector::new(1.0, -1.0));
+        assert_near(&v.v, &[2.0, -3.0]);
+    }
+
+    #[test]
+    fn mat3_invert_singular() {
+        assert_eq!(Mat3::scale(0.0, 1.0).invert(), None);
+        assert_eq!(Mat3::scale(Float::NAN, 1.0).invert(), None);
+        assert_eq!(Mat3::scale(1e-30, 1e-30).invert(), None);
+    }
+
+    #[test]
+    fn mat4_invert_round_trips() {
+        let m: Mat4 = [
+            [2.0, 0.5, 0.0, 0.0],
+            [-1.0, 3.0, 0.0, 0.0],
+            [0.0, 0.0, 4.0, 0.0],
+            [7.0, -2.0, 1.0, 1.0],
+        ]
+        .into();
+        let inverse = m.invert().unwrap();
+        assert_mat4_near(m.multiply(&inverse), Mat4::identity());
+        assert_mat4_near(inverse.multiply(&m), Mat4::identity());
+    }
+
+    #[test]
+    fn mat4_invert_small_scale() {
+        let mut m = Mat4::identity();
+        m.m[0][0] = 3e-4;
+        m.m[1][1] = 3e-4;
+        let inverse = m.invert().unwrap();
+        assert!((inverse.m[0][0] - 1.0 / 3e-4).abs() < 1e-1);
+        assert_mat4_near(m.multiply(&inverse), Mat4::identity());
+    }
+
+    #[test]
+    fn mat4_invert_singular() {
+        let mut m = Mat4::identity();
+        m.m[2][2] = 0.0;
+        assert_eq!(m.invert(), None);
+    }
+
+    #[test]
+    fn rect_intersection() {
+        let a = Rect::from_float(0.0, 0.0, 10.0, 10.0);
+        let b = Rect::from_float(5.0, -5.0, 15.0, 5.0);
+        assert_eq!(
+            a.intersection(&b),
+            Some(Rect::from_float(5.0, 0.0, 10.0, 5.0))
+        );
+        let touching = Rect::from_float(10.0, 0.0, 20.0, 10.0);
+        assert_eq!(a.intersection(&touching), None);
+        assert!(!a.intersects(&touching));
+    }
+
+    #[test]
+    fn ortho_maps_logical_viewport_to_clip_space() {
+        let viewport = Viewport::with_scale_factor(200.0, 100.0, 2.0);
+        let ortho = viewport.to_ortho();
+        let clip = |x: Float, y: Float| {
+            let m = &ortho.m;
+            [
+                m[0][0] * x + m[1][0] * y + m[3][0],
+                m[0][1] * x + m[1][1] * y + m[3][1],
+            ]
+        };
+        // Top left is (-1, 1) and x grows to the right, independent of the
+        // scale factor.
+        assert_near(&clip(0.0, 0.0), &[-1.0, 1.0]);
+        assert_near(&clip(200.0, 100.0), &[1.0, -1.0]);
+        assert_near(&clip(50.0, 25.0), &[-0.5, 0.5]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialized_rounded_rect_is_fitted() {
+        let json = r#"{
+            "rect": {"r": [[0.0, 0.0], [10.0, 10.0]]},
+            "radii": {"top_left": 20.0, "top_right": 20.0, "bottom_right": -1.0, "bottom_left": 0.0}
+        }"#;
+        let rrect: RoundedRect = serde_json::from_str(json).unwrap();
+        let radii = rrect.radii();
+        assert_eq!(
+            [
+                radii.top_left,
+                radii.top_right,
+                radii.bottom_right,
+                radii.bottom_left
+            ],
+            [5.0, 5.0, 0.0, 0.0]
+        );
+
+        let json = serde_json::to_string(&rrect).unwrap();
+        assert_eq!(serde_json::from_str::<RoundedRect>(&json).unwrap(), rrect);
+    }
+}
@@ -0,0 +1,392 @@
+//! Decoded images and the options to draw them.
+
+use std::{error, fmt};
+
+use crate::geometry::{Float, Point, Rect};
+
+/// Identifier of an image uploaded to a backend.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImageId {
+    id: usize,
+}
+
+impl ImageId {
+    pub fn new(id: usize) -> Self {
+        ImageId { id }
+    }
+
+    pub fn value(&self) -> usize {
+        self.id
+    }
+}
+
+/// How texels are sampled when an image is scaled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Sampling {
+    /// The nearest texel, for pixel art.
+    Nearest,
+    /// Bilinear interpolation of the four nearest texels.
+    #[default]
+    Linear,
+    /// Linear interpolation between mipmap levels, for downscaled images.
+    /// Same as [`Sampling::Linear`] for images uploaded without mipmaps.
+    Mipmapped,
+}
+
+#[cfg(feature = "wgpu")]
+impl Sampling {
+    pub(crate) const COUNT: usize = 3;
+
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Insets in texels of the source rect splitting an image into nine parts.
+///
+/// Corners keep their size, edges stretch along one axis and the center
+/// along both. Corners shrink proportionally when the destination is
+/// smaller than them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NineSlice {
+    pub left: Float,
+    pub top: Float,
+    pub right: Float,
+    pub bottom: Float,
+}
+
+impl NineSlice {
+    pub fn new(left: Float, top: Float, right: Float, bottom: Float) -> Self {
+        NineSlice {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    /// Same inset on every side.
+    pub fn uniform(inset: Float) -> Self {
+        NineSlice::new(inset, inset, inset, inset)
+    }
+}
+
+/// Glyph of a text run drawn by
+/// [`Context::draw_glyphs`](crate::Context::draw_glyphs): the coverage mask
+/// inside `src_rect` of an image, such as a glyph atlas, stretched to
+/// `dst_rect`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Glyph {
+    pub image: ImageId,
+    /// Texels of the mask.
+    pub src_rect: Rect,
+    pub dst_rect: Rect,
+}
+
+/// Options of [`Context::draw_image`](crate::Context::draw_image).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ImageOptions {
+    pub sampling: Sampling,
+    pub nine_slice: Option<NineSlice>,
+}
+
+impl ImageOptions {
+    pub fn with_sampling(mut self, sampling: Sampling) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    pub fn with_nine_slice(mut self, nine_slice: NineSlice) -> Self {
+        self.nine_slice = Some(nine_slice);
+        self
+    }
+}
+
+/// Pairs of source and destination rects drawing `src` into `dst`: a
+/// single pair, or the non-empty parts of a nine-slice.
+pub(crate) fn slices(src: Rect, dst: Rect, nine_slice: Option<NineSlice>) -> Vec<(Rect, Rect)> {
+    let Some(n) = nine_slice else {
+        return vec![(src, dst)];
+    };
+    // Splits `0..src_len` at the insets and `0..dst_len` at the insets,
+    // scaled down if they do not fit.
+    let split = |start: Float, end: Float, src_len: Float, dst_len: Float| {
+        let (start, end) = (start.clamp(0.0, src_len), end.clamp(0.0, src_len));
+        let scale = if start + end > dst_len && start + end > 0.0 {
+            dst_len / (start + end)
+        } else {
+            1.0
+        };
+        (
+            [0.0, start, src_len - end, src_len],
+            [0.0, start * scale, dst_len - end * scale, dst_len],
+        )
+    };
+    let (src_x, dst_x) = split(n.left, n.right, src.width(), dst.width());
+    let (src_y, dst_y) = split(n.top, n.bottom, src.height(), dst.height());
+    let cell = |origin: Point, xs: &[Float; 4], ys: &[Float; 4], i: usize, j: usize| {
+        Rect::from_float(
+            origin.x() + xs[i],
+            origin.y() + ys[j],
+            origin.x() + xs[i + 1],
+            origin.y() + ys[j + 1],
+        )
+    };
+    let mut slices = Vec::with_capacity(9);
+    for j in 0..3 {
+        for i in 0..3 {
+            let s = cell(src.origin(), &src_x, &src_y, i, j);
+            let d = cell(dst.origin(), &dst_x, &dst_y, i, j);
+            if !s.is_empty() && !d.is_empty() {
+                slices.push((s, d));
+            }
+        }
+    }
+    slices
+}
+
+/// Decoded image of straight alpha, sRGB encoded RGBA8 texels.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Image {
+    /// Creates an image from tightly packed RGBA8 texels, row by row.
+    pub fn new(width: u32, height: u32, data: Vec<u8>) -> Result<Self, ImageError> {
+        if width == 0 || height == 0 {
+            return Err(ImageError::InvalidSize);
+        }
+        if data.len() != width as usize * height as usize * 4 {
+            return Err(ImageError::InvalidData);
+        }
+        Ok(Image {
+            width,
+            height,
+            data,
+        })
+    }
+
+    /// Decodes a PNG or JPEG image, depending on the enabled features.
+    pub fn decode(bytes: &[u8]) -> Result<Self, ImageError> {
+        #[cfg(feature = "png")]
+        if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+            return Self::decode_png(bytes);
+        }
+        #[cfg(feature = "jpeg")]
+        if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+            return Self::decode_jpeg(bytes);
+        }
+        let _ = bytes;
+        Err(ImageError::UnsupportedFormat)
+    }
+
+    #[cfg(feature = "png")]
+    pub fn decode_png(bytes: &[u8]) -> Result<Self, ImageError> {
+        let mut decoder = png::Decoder::new(std::io::Cursor::new(bytes));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size().ok_or(ImageError::InvalidSize)?];
+        let info = reader.next_frame(&mut buffer)?;
+        buffer.truncate(info.buffer_size());
+        let data = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => rgba_from(&buffer, 3, |t| [t[0], t[1], t[2], 255]),
+            png::ColorType::GrayscaleAlpha => rgba_from(&buffer, 2, |t| [t[0], t[0], t[0], t[1]]),
+            png::ColorType::Grayscale => rgba_from(&buffer, 1, |t| [t[0], t[0], t[0], 255]),
+            png::ColorType::Indexed => return Err(ImageError::UnsupportedFormat),
+        };
+        Image::new(info.width, info.height, data)
+    }
+
+    #[cfg(feature = "jpeg")]
+    pub fn decode_jpeg(bytes: &[u8]) -> Result<Self, ImageError> {
+        let mut decoder = jpeg_decoder::Decoder::new(bytes);
+        let pixels = decoder.decode()?;
+        let info = decoder.info().ok_or(ImageError::InvalidData)?;
+        let data = match info.pixel_format {
+            jpeg_decoder::PixelFormat::RGB24 => rgba_from(&pixels, 3, |t| [t[0], t[1], t[2], 255]),
+            jpeg_decoder::PixelFormat::L8 => rgba_from(&pixels, 1, |t| [t[0], t[0], t[0], 255]),
+            // Big endian 16 bit luminance.
+            jpeg_decoder::PixelFormat::L16 => rgba_from(&pixels, 2, |t| [t[0], t[0], t[0], 255]),
+            jpeg_decoder::PixelFormat::CMYK32 => rgba_from(&pixels, 4, |t| {
+                let k = 255 - t[3] as u32;
+                let c = |v: u8| ((255 - v as u32) * k / 255) as u8;
+                [c(t[0]), c(t[1]), c(t[2]), 255]
+            }),
+        };
+        Image::new(info.width as u32, info.height as u32, data)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Straight alpha RGBA8 texels, row by row.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+/// Expands texels of `channels` bytes to RGBA8.
+#[cfg(any(feature = "png", feature = "jpeg"))]
+fn rgba_from(data: &[u8], channels: usize, f: impl Fn(&[u8]) -> [u8; 4]) -> Vec<u8> {
+    data.chunks_exact(channels).flat_map(f).collect()
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    /// The image is empty or too large.
+    InvalidSize,
+    /// The texel data does not match the image size.
+    InvalidData,
+    /// The data is not in a format of an enabled decoder.
+    UnsupportedFormat,
+    #[cfg(feature = "png")]
+    Png(png::DecodingError),
+    #[cfg(feature = "jpeg")]
+    Jpeg(jpeg_decoder::Error),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::InvalidSize => write!(f, "image size is zero or too large"),
+            ImageError::InvalidData => write!(f, "texel data length does not match image size"),
+            ImageError::UnsupportedFormat => write!(f, "image format is not supported"),
+            #[cfg(feature = "png")]
+            ImageError::Png(err) => write!(f, "failed to decode png: {err}"),
+            #[cfg(feature = "jpeg")]
+            ImageError::Jpeg(err) => write!(f, "failed to decode jpeg: {err}"),
+        }
+    }
+}
+
+impl error::Error for ImageError {}
+
+#[cfg(feature = "png")]
+impl From<png::DecodingError> for ImageError {
+    fn from(err: png::DecodingError) -> Self {
+        ImageError::Png(err)
+    }
+}
+
+#[cfg(feature = "jpeg")]
+impl From<jpeg_decoder::Error> for ImageError {
+    fn from(err: jpeg_decoder::Error) -> Self {
+        ImageError::Jpeg(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(minx: Float, miny: Float, maxx: Float, maxy: Float) -> Rect {
+        Rect::from_float(minx, miny, maxx, maxy)
+    }
+
+    #[test]
+    fn images_without_insets_are_one_slice() {
+        let (src, dst) = (rect(0.0, 0.0, 4.0, 4.0), rect(10.0, 10.0, 30.0, 20.0));
+        assert_eq!(slices(src, dst, None), [(src, dst)]);
+        let center = slices(src, dst, Some(NineSlice::uniform(0.0)));
+        assert_eq!(center, [(src, dst)]);
+    }
+
+    #[test]
+    fn corners_keep_their_size() {
+        let slices = slices(
+            rect(0.0, 0.0, 30.0, 30.0),
+            rect(100.0, 100.0, 200.0, 160.0),
+            Some(NineSlice::uniform(10.0)),
+        );
+        assert_eq!(slices.len(), 9);
+        assert_eq!(
+            slices[0],
+            (rect(0.0, 0.0, 10.0, 10.0), rect(100.0, 100.0, 110.0, 110.0))
+        );
+        assert_eq!(
+            slices[4],
+            (
+                rect(10.0, 10.0, 20.0, 20.0),
+                rect(110.0, 110.0, 190.0, 150.0)
+            )
+        );
+        assert_eq!(
+            slices[8],
+            (
+                rect(20.0, 20.0, 30.0, 30.0),
+                rect(190.0, 150.0, 200.0, 160.0)
+            )
+        );
+    }
+
+    #[test]
+    fn corners_shrink_in_small_destinations() {
+        let slices = slices(
+            rect(0.0, 0.0, 30.0, 30.0),
+            rect(0.0, 0.0, 10.0, 40.0),
+            Some(NineSlice::new(10.0, 10.0, 10.0, 10.0)),
+        );
+        // The middle column is empty: the corners halve to fill the width.
+        assert_eq!(slices.len(), 6);
+        assert_eq!(
+            slices[0],
+            (rect(0.0, 0.0, 10.0, 10.0), rect(0.0, 0.0, 5.0, 10.0))
+        );
+        assert_eq!(
+            slices[1],
+            (rect(20.0, 0.0, 30.0, 10.0), rect(5.0, 0.0, 10.0, 10.0))
+        );
+        assert_eq!(
+            slices[2],
+            (rect(0.0, 10.0, 10.0, 20.0), rect(0.0, 10.0, 5.0, 30.0))
+        );
+        assert_eq!(
+            slices[5],
+            (rect(20.0, 20.0, 30.0, 30.0), rect(5.0, 30.0, 10.0, 40.0))
+        );
+    }
+
+    #[test]
+    fn unknown_formats_are_not_decoded() {
+        assert!(matches!(
+            Image::decode(b"GIF89a"),
+            Err(ImageError::UnsupportedFormat)
+        ));
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn pngs_decode_to_straight_alpha() {
+        // 2x1 RGBA: opaque red, half transparent blue.
+        const PNG: &[u8] = &[
+            0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48,
+            0x44, 0x52, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00,
+            0x00, 0xf4, 0x22, 0x7f, 0x8a, 0x00, 0x00, 0x00, 0x0e, 0x49, 0x44, 0x41, 0x54, 0x78,
+            0xda, 0x63, 0xf8, 0xcf, 0xc0, 0x00, 0x42, 0x0d, 0x00, 0x0f, 0x7a, 0x03, 0x7e, 0x6a,
+            0x81, 0x31, 0xe1, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e, 0x44, 0xae, 0x42, 0x60,
+            0x82,
+        ];
+        let image = Image::decode(PNG).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        assert_eq!(image.data(), [255, 0, 0, 255, 0, 0, 255, 128]);
+        assert!(Image::decode(&PNG[..40]).is_err());
+    }
+}
@@ -1,9 +1,31 @@
+pub mod atlas;
+pub mod color;
 pub mod context;
+pub mod cpu;
+pub mod damage;
+pub mod display_list;
+pub mod effect;
 pub mod geometry;
+pub mod image;
+pub mod paint;
+pub mod path;
 pub mod shader;
+#[cfg(feature = "svg")]
+pub mod svg;
+mod tessellator;
 #[cfg(feature = "wgpu")]
 pub mod wgpu;
 
+pub use atlas::*;
+pub use color::*;
 pub use context::*;
+pub use damage::*;
+pub use display_list::*;
+pub use effect::*;
 pub use geometry::*;
+pub use image::*;
+pub use paint::*;
+pub use path::*;
 pub use shader::*;
+#[cfg(feature = "svg")]
+pub use svg::*;
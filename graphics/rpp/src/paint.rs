@@ -0,0 +1,489 @@
+use crate::{
+    geometry::{Float, Mat3, Point},
+    shader::ShaderId,
+};
+
+/// Straight alpha RGBA color with components in `0.0..=1.0`. See
+/// [`Color`](crate::Color) for conversions.
+///
+/// Backends premultiply colors before blending.
+pub type ColorF = [Float; 4];
+
+/// Multiplies the color components of a straight alpha color by its alpha.
+#[inline]
+pub fn premultiply(color: ColorF) -> ColorF {
+    let a = color[3];
+    [color[0] * a, color[1] * a, color[2] * a, a]
+}
+
+/// Divides the color components of a premultiplied color by its alpha.
+/// Fully transparent colors become transparent black.
+#[inline]
+pub fn unpremultiply(color: ColorF) -> ColorF {
+    let a = color[3];
+    if a <= 0.0 {
+        return [0.0; 4];
+    }
+    [color[0] / a, color[1] / a, color[2] / a, a]
+}
+
+/// How a gradient is extended outside of its `0.0..=1.0` range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SpreadMode {
+    /// The end colors are extended.
+    #[default]
+    Pad,
+    /// The gradient is repeated.
+    Repeat,
+    /// The gradient is repeated, mirroring every other repetition.
+    Reflect,
+}
+
+impl SpreadMode {
+    /// Maps `t` into `0.0..=1.0`.
+    #[inline]
+    pub fn apply(self, t: Float) -> Float {
+        match self {
+            SpreadMode::Pad => t.clamp(0.0, 1.0),
+            SpreadMode::Repeat => t - t.floor(),
+            SpreadMode::Reflect => {
+                let t = (t * 0.5 - (t * 0.5).floor()) * 2.0;
+                1.0 - (t - 1.0).abs()
+            }
+        }
+    }
+}
+
+/// Border drawn inside the outline of a shape.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Border {
+    pub width: Float,
+    pub color: ColorF,
+}
+
+impl Border {
+    pub fn new(width: Float, color: impl Into<ColorF>) -> Self {
+        Border {
+            width,
+            color: color.into(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GradientStop {
+    pub offset: Float,
+    pub color: ColorF,
+}
+
+impl GradientStop {
+    pub fn new(offset: Float, color: impl Into<ColorF>) -> Self {
+        GradientStop {
+            offset,
+            color: color.into(),
+        }
+    }
+}
+
+/// Color stops, spread mode and transform shared by all gradient kinds.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "GradientFields")
+)]
+pub struct Gradient {
+    stops: Vec<GradientStop>,
+    pub spread: SpreadMode,
+    /// Maps the gradient geometry into user space.
+    pub transform: Mat3,
+}
+
+impl Gradient {
+    /// Stops are sorted by offset; offsets are clamped to `0.0..=1.0`.
+    pub fn new(stops: impl Into<Vec<GradientStop>>) -> Self {
+        let mut stops = stops.into();
+        for stop in &mut stops {
+            stop.offset = stop.offset.clamp(0.0, 1.0);
+        }
+        stops.sort_by(|a, b| a.offset.total_cmp(&b.offset));
+        Gradient {
+            stops,
+            spread: SpreadMode::default(),
+            transform: Mat3::identity(),
+        }
+    }
+
+    pub fn with_spread(mut self, spread: SpreadMode) -> Self {
+        self.spread = spread;
+        self
+    }
+
+    pub fn with_transform(mut self, transform: Mat3) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    pub fn stops(&self) -> &[GradientStop] {
+        &self.stops
+    }
+
+    /// Color at `t` after the spread mode has been applied.
+    pub fn color_at(&self, t: Float) -> ColorF {
+        self.interpolate(self.spread.apply(t))
+    }
+
+    /// Color at `t` in `0.0..=1.0`, ignoring the spread mode. Stops are
+    /// interpolated premultiplied, as in CSS, so that fading to a
+    /// transparent stop does not darken the color.
+    pub(crate) fn interpolate(&self, t: Float) -> ColorF {
+        let (first, last) = match (self.stops.first(), self.stops.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return [0.0; 4],
+        };
+        if t <= first.offset {
+            return first.color;
+        }
+        if t >= last.offset {
+            return last.color;
+        }
+        for pair in self.stops.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            if t <= b.offset {
+                let span = b.offset - a.offset;
+                let f = if span > 0.0 {
+                    (t - a.offset) / span
+                } else {
+                    1.0
+                };
+                let (a, b) = (premultiply(a.color), premultiply(b.color));
+                return unpremultiply(std::array::from_fn(|i| a[i] + (b[i] - a[i]) * f));
+            }
+        }
+        last.color
+    }
+}
+
+/// Fields of a deserialized [`Gradient`], sorted by [`Gradient::new`].
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct GradientFields {
+    stops: Vec<GradientStop>,
+    spread: SpreadMode,
+    transform: Mat3,
+}
+
+#[cfg(feature = "serde")]
+impl From<GradientFields> for Gradient {
+    fn from(fields: GradientFields) -> Self {
+        Gradient::new(fields.stops)
+            .with_spread(fields.spread)
+            .with_transform(fields.transform)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinearGradient {
+    pub start: Point,
+    pub end: Point,
+    pub gradient: Gradient,
+}
+
+/// Gradient radiating from `center`, reaching the last stop at `radius`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RadialGradient {
+    pub center: Point,
+    pub radius: Float,
+    pub gradient: Gradient,
+}
+
+/// Gradient sweeping around `center`, starting at `angle` radians from the
+/// positive x axis.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConicGradient {
+    pub center: Point,
+    pub angle: Float,
+    pub gradient: Gradient,
+}
+
+/// Paint computed by a custom shader, such as one registered with
+/// `rpp::wgpu::RenderPipeline::register_shader`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShaderPaint {
+    pub shader: ShaderId,
+    /// Passed to the shader as `input.params`.
+    pub params: [[Float; 4]; 4],
+    /// Color painted by backends that can not run the shader, such as
+    /// [`CpuContext`](crate::cpu::CpuContext).
+    pub fallback: ColorF,
+}
+
+/// Source of colors for fills and strokes.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Paint {
+    Solid(ColorF),
+    LinearGradient(LinearGradient),
+    RadialGradient(RadialGradient),
+    ConicGradient(ConicGradient),
+    Shader(ShaderPaint),
+}
+
+impl Paint {
+    pub fn solid(color: impl Into<ColorF>) -> Self {
+        Paint::Solid(color.into())
+    }
+
+    pub fn linear_gradient(start: Point, end: Point, gradient: Gradient) -> Self {
+        Paint::LinearGradient(LinearGradient {
+            start,
+            end,
+            gradient,
+        })
+    }
+
+    pub fn radial_gradient(center: Point, radius: Float, gradient: Gradient) -> Self {
+        Paint::RadialGradient(RadialGradient {
+            center,
+            radius,
+            gradient,
+        })
+    }
+
+    pub fn conic_gradient(center: Point, angle: Float, gradient: Gradient) -> Self {
+        Paint::ConicGradient(ConicGradient {
+            center,
+            angle,
+            gradient,
+        })
+    }
+
+    pub fn shader(shader: ShaderId, params: [[Float; 4]; 4], fallback: impl Into<ColorF>) -> Self {
+        Paint::Shader(ShaderPaint {
+            shader,
+            params,
+            fallback: fallback.into(),
+        })
+    }
+
+    pub fn gradient(&self) -> Option<&Gradient> {
+        match self {
+            Paint::Solid(..) | Paint::Shader(..) => None,
+            Paint::LinearGradient(g) => Some(&g.gradient),
+            Paint::RadialGradient(g) => Some(&g.gradient),
+            Paint::ConicGradient(g) => Some(&g.gradient),
+        }
+    }
+
+    /// Gradient parameter at `point` in gradient space, before the spread
+    /// mode is applied.
+    pub(crate) fn gradient_offset(&self, point: Point) -> Float {
+        match self {
+            Paint::Solid(..) | Paint::Shader(..) => 0.0,
+            Paint::LinearGradient(g) => {
+                let (dx, dy) = (g.end.x() - g.start.x(), g.end.y() - g.start.y());
+                let len2 = dx * dx + dy * dy;
+                if len2 <= Float::EPSILON {
+                    return 0.0;
+                }
+                ((point.x() - g.start.x()) * dx + (point.y() - g.start.y()) * dy) / len2
+            }
+            Paint::RadialGradient(g) => {
+                if g.radius <= Float::EPSILON {
+                    return 1.0;
+                }
+                point.distance_from(&g.center) / g.radius
+            }
+            Paint::ConicGradient(g) => {
+                let angle = (point.y() - g.center.y()).atan2(point.x() - g.center.x()) - g.angle;
+                let turns = angle / std::f32::consts::TAU;
+                turns - turns.floor()
+            }
+        }
+    }
+
+    /// Color of the paint at `point` in user space.
+    pub fn color_at(&self, point: Point) -> ColorF {
+        PaintSampler::new(self).sample(point)
+    }
+}
+
+/// Evaluates a paint at many points, inverting the gradient transform once.
+pub(crate) struct PaintSampler<'a> {
+    paint: &'a Paint,
+    inverse: Option<Mat3>,
+}
+
+impl<'a> PaintSampler<'a> {
+    pub(crate) fn new(paint: &'a Paint) -> Self {
+        Self::transformed(paint, &Mat3::identity())
+    }
+
+    /// Samples `paint` drawn with `transform`, at points on the target.
+    pub(crate) fn transformed(paint: &'a Paint, transform: &Mat3) -> Self {
+        let inverse = paint
+            .gradient()
+            .and_then(|g| (*transform * g.transform).invert());
+        PaintSampler { paint, inverse }
+    }
+
+    pub(crate) fn sample(&self, point: Point) -> ColorF {
+        match (self.paint, self.paint.gradient(), &self.inverse) {
+            (Paint::Solid(color), ..) => *color,
+            (Paint::Shader(paint), ..) => paint.fallback,
+            (paint, Some(gradient), Some(inverse)) => {
+                gradient.color_at(paint.gradient_offset(inverse.transform_point(point)))
+            }
+            // Singular gradient transforms paint nothing.
+            _ => [0.0; 4],
+        }
+    }
+}
+
+/// How a layer is combined with the content below it, as the separable
+/// blend modes of CSS `mix-blend-mode`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlendMode {
+    /// Source over.
+    #[default]
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+}
+
+impl BlendMode {
+    /// Index of the mode in `composite.wgsl`.
+    #[cfg(feature = "wgpu")]
+    pub(crate) fn index(self) -> u32 {
+        self as u32
+    }
+
+    /// Blends straight color channels `cb` below and `cs` above.
+    pub(crate) fn blend_channel(self, cb: Float, cs: Float) -> Float {
+        match self {
+            BlendMode::Normal => cs,
+            BlendMode::Multiply => cb * cs,
+            BlendMode::Screen => cb + cs - cb * cs,
+            BlendMode::Overlay => BlendMode::HardLight.blend_channel(cs, cb),
+            BlendMode::Darken => cb.min(cs),
+            BlendMode::Lighten => cb.max(cs),
+            BlendMode::ColorDodge => {
+                if cb <= 0.0 {
+                    0.0
+                } else if cs >= 1.0 {
+                    1.0
+                } else {
+                    (cb / (1.0 - cs)).min(1.0)
+                }
+            }
+            BlendMode::ColorBurn => {
+                if cb >= 1.0 {
+                    1.0
+                } else if cs <= 0.0 {
+                    0.0
+                } else {
+                    1.0 - ((1.0 - cb) / cs).min(1.0)
+                }
+            }
+            BlendMode::HardLight => {
+                if cs <= 0.5 {
+                    cb * 2.0 * cs
+                } else {
+                    BlendMode::Screen.blend_channel(cb, 2.0 * cs - 1.0)
+                }
+            }
+            BlendMode::SoftLight => {
+                if cs <= 0.5 {
+                    cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb)
+                } else {
+                    let d = if cb <= 0.25 {
+                        ((16.0 * cb - 12.0) * cb + 4.0) * cb
+                    } else {
+                        cb.sqrt()
+                    };
+                    cb + (2.0 * cs - 1.0) * (d - cb)
+                }
+            }
+            BlendMode::Difference => (cb - cs).abs(),
+            BlendMode::Exclusion => cb + cs - 2.0 * cb * cs,
+        }
+    }
+
+    /// Composites premultiplied `src` over premultiplied `dst`.
+    pub(crate) fn composite(self, src: [Float; 4], dst: [Float; 4]) -> [Float; 4] {
+        let (a_s, a_b) = (src[3], dst[3]);
+        let mut out = [0.0; 4];
+        for i in 0..3 {
+            let blended = if a_s > 0.0 && a_b > 0.0 {
+                a_s * a_b * self.blend_channel(dst[i] / a_b, src[i] / a_s)
+            } else {
+                0.0
+            };
+            out[i] = src[i] * (1.0 - a_b) + dst[i] * (1.0 - a_s) + blended;
+        }
+        out[3] = a_s + a_b * (1.0 - a_s);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_are_interpolated_premultiplied() {
+        let gradient = Gradient::new([
+            GradientStop::new(0.0, [1.0, 0.0, 0.0, 1.0]),
+            GradientStop::new(1.0, [0.0, 0.0, 0.0, 0.0]),
+        ]);
+        assert_eq!(gradient.color_at(0.5), [1.0, 0.0, 0.0, 0.5]);
+        assert_eq!(gradient.color_at(1.0), [0.0; 4]);
+    }
+
+    #[test]
+    fn gradient_stops_are_clamped_and_sorted() {
+        let gradient = Gradient::new([
+            GradientStop::new(1.5, [1.0; 4]),
+            GradientStop::new(-1.0, [0.0; 4]),
+        ]);
+        let offsets: Vec<_> = gradient.stops().iter().map(|s| s.offset).collect();
+        assert_eq!(offsets, [0.0, 1.0]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn deserialized_gradient_is_normalized() {
+        let json = r#"{
+            "stops": [
+                {"offset": 2.0, "color": [1.0, 1.0, 1.0, 1.0]},
+                {"offset": 0.25, "color": [0.0, 0.0, 0.0, 1.0]}
+            ],
+            "spread": "Repeat",
+            "transform": {"m": [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]}
+        }"#;
+        let gradient: Gradient = serde_json::from_str(json).unwrap();
+        let offsets: Vec<_> = gradient.stops().iter().map(|s| s.offset).collect();
+        assert_eq!(offsets, [0.25, 1.0]);
+        assert_eq!(gradient.spread, SpreadMode::Repeat);
+
+        let json = serde_json::to_string(&gradient).unwrap();
+        assert_eq!(serde_json::from_str::<Gradient>(&json).unwrap(), gradient);
+    }
+}
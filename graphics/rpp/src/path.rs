@@ -0,0 +1,368 @@
+use crate::geometry::{Float, Mat3, Point, Rect, RoundedRect, ZeroValue};
+
+/// Tolerance used to flatten curves, in pixels.
+pub const DEFAULT_TOLERANCE: Float = 0.25;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FillRule {
+    #[default]
+    NonZero,
+    EvenOdd,
+}
+
+impl FillRule {
+    #[inline(always)]
+    pub fn is_inside(self, winding: i32) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+/// Default miter limit, as in SVG.
+pub const DEFAULT_MITER_LIMIT: Float = 4.0;
+
+/// How a path is stroked. Ends have butt caps, and segments are joined
+/// with miters.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StrokeStyle {
+    /// Width in user space. Ignored by hairlines.
+    pub width: Float,
+    /// Lengths in user space of alternating dashes and gaps, starting with
+    /// a dash. Lists of odd length are repeated, as in SVG. The stroke is
+    /// solid if empty, or if the lengths are negative or add up to zero.
+    pub dashes: Vec<Float>,
+    /// Distance into the dash pattern the stroke starts at. The pattern
+    /// restarts at each subpath.
+    pub dash_offset: Float,
+    /// Largest ratio of the miter length to the width. Sharper joins are
+    /// beveled.
+    pub miter_limit: Float,
+    /// Strokes one pixel wide whatever the transform.
+    pub hairline: bool,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        StrokeStyle::new(1.0)
+    }
+}
+
+impl StrokeStyle {
+    /// Solid stroke of `width`.
+    pub fn new(width: Float) -> Self {
+        StrokeStyle {
+            width,
+            dashes: Vec::new(),
+            dash_offset: 0.0,
+            miter_limit: DEFAULT_MITER_LIMIT,
+            hairline: false,
+        }
+    }
+
+    /// Solid stroke one pixel wide.
+    pub fn hairline() -> Self {
+        StrokeStyle {
+            hairline: true,
+            ..StrokeStyle::new(1.0)
+        }
+    }
+
+    pub fn with_dashes(mut self, dashes: impl Into<Vec<Float>>, offset: Float) -> Self {
+        self.dashes = dashes.into();
+        self.dash_offset = offset;
+        self
+    }
+
+    pub fn with_miter_limit(mut self, miter_limit: Float) -> Self {
+        self.miter_limit = miter_limit;
+        self
+    }
+
+    /// Returns `true` if the stroke is dashed.
+    pub fn is_dashed(&self) -> bool {
+        self.dashes.iter().all(|d| *d >= 0.0) && self.dashes.iter().sum::<Float>() > 0.0
+    }
+
+    /// Distance in user space the stroke may reach from the path, or in
+    /// pixels for hairlines.
+    pub fn outset(&self) -> Float {
+        let width = if self.hairline { 1.0 } else { self.width };
+        width.max(0.0) * 0.5 * self.miter_limit.max(1.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PathElement {
+    MoveTo(Point),
+    LineTo(Point),
+    QuadTo(Point, Point),
+    CubicTo(Point, Point, Point),
+    Close,
+}
+
+/// Flattened subpath.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Polyline {
+    pub points: Vec<Point>,
+    pub closed: bool,
+}
+
+impl Polyline {
+    pub fn transform(&mut self, transform: &Mat3) {
+        for p in &mut self.points {
+            *p = transform.transform_point(*p);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Path {
+    elements: Vec<PathElement>,
+    fill_rule: FillRule,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_rect(rect: Rect) -> Self {
+        let mut path = Path::new();
+        path.add_rect(rect);
+        path
+    }
+
+    pub fn from_rounded_rect(rrect: RoundedRect) -> Self {
+        let mut path = Path::new();
+        path.add_rounded_rect(rrect);
+        path
+    }
+
+    pub fn elements(&self) -> &[PathElement] {
+        &self.elements
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// Bounding box of the points and control points, containing the
+    /// path. `None` if the path has no points.
+    pub fn bounds(&self) -> Option<Rect> {
+        let mut points = self.elements.iter().flat_map(|element| match *element {
+            PathElement::MoveTo(p) | PathElement::LineTo(p) => vec![p],
+            PathElement::QuadTo(c, p) => vec![c, p],
+            PathElement::CubicTo(c1, c2, p) => vec![c1, c2, p],
+            PathElement::Close => vec![],
+        });
+        let first = points.next()?;
+        Some(points.fold(Rect::new(first, first), |bounds, p| {
+            Rect::from_float(
+                bounds.min().x().min(p.x()),
+                bounds.min().y().min(p.y()),
+                bounds.max().x().max(p.x()),
+                bounds.max().y().max(p.y()),
+            )
+        }))
+    }
+
+    pub fn fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+
+    pub fn set_fill_rule(&mut self, fill_rule: FillRule) {
+        self.fill_rule = fill_rule;
+    }
+
+    pub fn with_fill_rule(mut self, fill_rule: FillRule) -> Self {
+        self.fill_rule = fill_rule;
+        self
+    }
+
+    pub fn move_to(&mut self, p: Point) {
+        self.elements.push(PathElement::MoveTo(p));
+    }
+
+    pub fn line_to(&mut self, p: Point) {
+        self.elements.push(PathElement::LineTo(p));
+    }
+
+    pub fn quad_to(&mut self, c: Point, p: Point) {
+        self.elements.push(PathElement::QuadTo(c, p));
+    }
+
+    pub fn cubic_to(&mut self, c1: Point, c2: Point, p: Point) {
+        self.elements.push(PathElement::CubicTo(c1, c2, p));
+    }
+
+    pub fn close(&mut self) {
+        self.elements.push(PathElement::Close);
+    }
+
+    pub fn add_rect(&mut self, rect: Rect) {
+        let (min, max) = (rect.min(), rect.max());
+        self.move_to(min);
+        self.line_to(Point::new(max.x(), min.y()));
+        self.line_to(max);
+        self.line_to(Point::new(min.x(), max.y()));
+        self.close();
+    }
+
+    /// Adds the outline of `rrect`, clockwise from the end of the top left
+    /// corner. Corners are approximated with cubic curves.
+    pub fn add_rounded_rect(&mut self, rrect: RoundedRect) {
+        // Distance of the control points from the corner ends, relative to
+        // the radius.
+        const KAPPA: Float = 0.552_284_8;
+        let (min, max) = (rrect.rect().min(), rrect.rect().max());
+        let [tl, tr, br, bl] = rrect.radii().to_array();
+        let k = 1.0 - KAPPA;
+
+        self.move_to(Point::new(min.x() + tl, min.y()));
+        self.line_to(Point::new(max.x() - tr, min.y()));
+        if tr > 0.0 {
+            self.cubic_to(
+                Point::new(max.x() - tr * k, min.y()),
+                Point::new(max.x(), min.y() + tr * k),
+                Point::new(max.x(), min.y() + tr),
+            );
+        }
+        self.line_to(Point::new(max.x(), max.y() - br));
+        if br > 0.0 {
+            self.cubic_to(
+                Point::new(max.x(), max.y() - br * k),
+                Point::new(max.x() - br * k, max.y()),
+                Point::new(max.x() - br, max.y()),
+            );
+        }
+        self.line_to(Point::new(min.x() + bl, max.y()));
+        if bl > 0.0 {
+            self.cubic_to(
+                Point::new(min.x() + bl * k, max.y()),
+                Point::new(min.x(), max.y() - bl * k),
+                Point::new(min.x(), max.y() - bl),
+            );
+        }
+        self.line_to(Point::new(min.x(), min.y() + tl));
+        if tl > 0.0 {
+            self.cubic_to(
+                Point::new(min.x(), min.y() + tl * k),
+                Point::new(min.x() + tl * k, min.y()),
+                Point::new(min.x() + tl, min.y()),
+            );
+        }
+        self.close();
+    }
+
+    /// Flattens the path in user space, precisely enough to be drawn with
+    /// `transform` within [`DEFAULT_TOLERANCE`].
+    pub(crate) fn flatten_for(&self, transform: &Mat3) -> Vec<Polyline> {
+        self.flatten(DEFAULT_TOLERANCE / transform.max_scale().max(1e-3))
+    }
+
+    /// Converts the path into polylines whose distance from the curves is
+    /// at most `tolerance`.
+    pub fn flatten(&self, tolerance: Float) -> Vec<Polyline> {
+        let tolerance = tolerance.max(1e-3);
+        let mut polylines = Vec::new();
+        let mut current = Polyline::default();
+        let mut last = Point::zero();
+
+        for element in &self.elements {
+            match *element {
+                PathElement::MoveTo(p) => {
+                    if current.points.len() > 1 {
+                        polylines.push(std::mem::take(&mut current));
+                    }
+                    current.points.clear();
+                    current.closed = false;
+                    current.points.push(p);
+                    last = p;
+                }
+                PathElement::LineTo(p) => {
+                    start_if_empty(&mut current, last);
+                    current.points.push(p);
+                    last = p;
+                }
+                PathElement::QuadTo(c, p) => {
+                    start_if_empty(&mut current, last);
+                    flatten_quad(last, c, p, tolerance, &mut current.points);
+                    last = p;
+                }
+                PathElement::CubicTo(c1, c2, p) => {
+                    start_if_empty(&mut current, last);
+                    flatten_cubic(last, c1, c2, p, tolerance, &mut current.points);
+                    last = p;
+                }
+                PathElement::Close => {
+                    if let Some(&first) = current.points.first() {
+                        if current.points.len() > 1 {
+                            current.closed = true;
+                            polylines.push(std::mem::take(&mut current));
+                        }
+                        current.points.clear();
+                        last = first;
+                    }
+                }
+            }
+        }
+        if current.points.len() > 1 {
+            polylines.push(current);
+        }
+        polylines
+    }
+}
+
+fn start_if_empty(polyline: &mut Polyline, last: Point) {
+    if polyline.points.is_empty() {
+        polyline.points.push(last);
+    }
+}
+
+#[inline]
+fn lerp(a: Point, b: Point, t: Float) -> Point {
+    Point::new(a.x() + (b.x() - a.x()) * t, a.y() + (b.y() - a.y()) * t)
+}
+
+#[inline]
+fn second_difference(a: Point, b: Point, c: Point) -> Float {
+    let x = a.x() - 2.0 * b.x() + c.x();
+    let y = a.y() - 2.0 * b.y() + c.y();
+    (x * x + y * y).sqrt()
+}
+
+fn segment_count(dd: Float, tolerance: Float) -> usize {
+    ((dd / tolerance).sqrt().ceil() as usize).clamp(1, 1024)
+}
+
+fn flatten_quad(p0: Point, c: Point, p1: Point, tolerance: Float, out: &mut Vec<Point>) {
+    let n = segment_count(second_difference(p0, c, p1) * 0.25, tolerance);
+    for i in 1..=n {
+        let t = i as Float / n as Float;
+        out.push(lerp(lerp(p0, c, t), lerp(c, p1, t), t));
+    }
+}
+
+fn flatten_cubic(
+    p0: Point,
+    c1: Point,
+    c2: Point,
+    p1: Point,
+    tolerance: Float,
+    out: &mut Vec<Point>,
+) {
+    let dd = second_difference(p0, c1, c2).max(second_difference(c1, c2, p1));
+    let n = segment_count(dd * 0.75, tolerance);
+    for i in 1..=n {
+        let t = i as Float / n as Float;
+        let a = lerp(lerp(p0, c1, t), lerp(c1, c2, t), t);
+        let b = lerp(lerp(c1, c2, t), lerp(c2, p1, t), t);
+        out.push(lerp(a, b, t));
+    }
+}
@@ -1,3 +1,15 @@
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ShaderId {
     id: usize,
 }
+
+impl ShaderId {
+    pub const fn new(id: usize) -> Self {
+        ShaderId { id }
+    }
+
+    pub fn value(&self) -> usize {
+        self.id
+    }
+}
@@ -0,0 +1,1112 @@
+//! Parsing of a practical SVG subset into paths and paints, for icons.
+//!
+//! Supported are `path`, `rect`, `circle`, `ellipse`, `line`, `polyline`
+//! and `polygon` shapes in nested `g` groups, with `transform`, `fill`,
+//! `stroke`, `stroke-width`, `stroke-dasharray`, `stroke-dashoffset`,
+//! `stroke-miterlimit`, `fill-rule`, opacities and `color`, as attributes
+//! or in `style`. Paints are colors or references to
+//! `linearGradient` and `radialGradient` elements. Anything else, such as
+//! text, images, clip paths, masks and filters, is skipped.
+
+use std::{collections::HashMap, error, f32::consts::PI, fmt};
+
+use crate::{
+    color::Color,
+    context::Context,
+    geometry::{Float, Mat3, Point, Rect, Size},
+    paint::{ColorF, Gradient, GradientStop, Paint, SpreadMode},
+    path::{FillRule, Path, StrokeStyle},
+};
+
+/// Stroke of an [`SvgShape`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgStroke {
+    pub paint: Paint,
+    pub style: StrokeStyle,
+}
+
+/// Filled and stroked path of an [`Svg`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SvgShape {
+    /// Outline in the coordinates of `transform`.
+    pub path: Path,
+    /// Maps the path into the coordinates of the view box.
+    pub transform: Mat3,
+    pub fill: Option<Paint>,
+    pub stroke: Option<SvgStroke>,
+}
+
+/// Parsed SVG document, drawn with [`Svg::draw`].
+///
+/// Group opacity is applied to each shape of the group separately, and
+/// the focal point of radial gradients is ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Svg {
+    view_box: Rect,
+    size: Size,
+    shapes: Vec<SvgShape>,
+}
+
+impl Svg {
+    pub fn parse(text: &str) -> Result<Self, SvgError> {
+        let document = roxmltree::Document::parse(text)?;
+        let root = document.root_element();
+        if root.tag_name().name() != "svg" {
+            return Err(SvgError::NotSvg);
+        }
+        let view_box = match root.attribute("viewBox") {
+            Some(value) => {
+                let v = numbers(value);
+                if v.len() != 4 || v[2] <= 0.0 || v[3] <= 0.0 {
+                    return Err(SvgError::InvalidSize);
+                }
+                Rect::from_float(v[0], v[1], v[0] + v[2], v[1] + v[3])
+            }
+            None => {
+                let width = root.attribute("width").and_then(absolute_length);
+                let height = root.attribute("height").and_then(absolute_length);
+                match (width, height) {
+                    (Some(w), Some(h)) if w > 0.0 && h > 0.0 => Rect::from_float(0.0, 0.0, w, h),
+                    _ => return Err(SvgError::InvalidSize),
+                }
+            }
+        };
+        // Percentages and missing sizes fall back to the view box.
+        let size = Size::new(
+            root.attribute("width")
+                .and_then(absolute_length)
+                .unwrap_or(view_box.width()),
+            root.attribute("height")
+                .and_then(absolute_length)
+                .unwrap_or(view_box.height()),
+        );
+
+        let mut parser = Parser {
+            view_box,
+            gradients: HashMap::new(),
+            shapes: Vec::new(),
+        };
+        for node in document.descendants() {
+            if matches!(node.tag_name().name(), "linearGradient" | "radialGradient")
+                && let Some(id) = node.attribute("id")
+            {
+                parser.gradients.insert(id.to_string(), node);
+            }
+        }
+        // Icon sets often style their shapes from the root element.
+        let style = Style::default().inherit(root);
+        parser.parse_children(root, &style, Mat3::identity());
+        Ok(Svg {
+            view_box,
+            size,
+            shapes: parser.shapes,
+        })
+    }
+
+    /// Area of the user space the document shows.
+    pub fn view_box(&self) -> Rect {
+        self.view_box
+    }
+
+    /// Intrinsic size, from the `width` and `height` attributes.
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn shapes(&self) -> &[SvgShape] {
+        &self.shapes
+    }
+
+    /// Transform fitting the view box into `rect`, keeping its aspect
+    /// ratio and centering it, as the default `preserveAspectRatio`.
+    pub fn fit_transform(&self, rect: Rect) -> Mat3 {
+        let vb = self.view_box;
+        let scale = (rect.width() / vb.width()).min(rect.height() / vb.height());
+        let dx = rect.min().x() + (rect.width() - vb.width() * scale) * 0.5;
+        let dy = rect.min().y() + (rect.height() - vb.height() * scale) * 0.5;
+        Mat3::translate(dx, dy)
+            .multiply(&Mat3::scale(scale, scale))
+            .multiply(&Mat3::translate(-vb.min().x(), -vb.min().y()))
+    }
+
+    /// Draws the document fitted into `rect`, see [`Svg::fit_transform`].
+    pub fn draw(&self, context: &mut impl Context, rect: Rect) {
+        let base = context.transform().multiply(&self.fit_transform(rect));
+        context.save();
+        for shape in &self.shapes {
+            context.set_transform(base.multiply(&shape.transform));
+            if let Some(fill) = &shape.fill {
+                context.fill_path(&shape.path, fill);
+            }
+            if let Some(stroke) = &shape.stroke {
+                context.stroke_path(&shape.path, &stroke.style, &stroke.paint);
+            }
+        }
+        context.restore();
+    }
+}
+
+#[derive(Debug)]
+pub enum SvgError {
+    Xml(roxmltree::Error),
+    /// The root element is not `svg`.
+    NotSvg,
+    /// The view box is missing or empty.
+    InvalidSize,
+}
+
+impl fmt::Display for SvgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SvgError::Xml(err) => write!(f, "failed to parse svg: {err}"),
+            SvgError::NotSvg => write!(f, "root element is not svg"),
+            SvgError::InvalidSize => write!(f, "svg has no valid view box nor size"),
+        }
+    }
+}
+
+impl error::Error for SvgError {}
+
+impl From<roxmltree::Error> for SvgError {
+    fn from(err: roxmltree::Error) -> Self {
+        SvgError::Xml(err)
+    }
+}
+
+/// Paint of a fill or stroke, before gradients are resolved.
+#[derive(Clone, Debug, PartialEq)]
+enum PaintRef {
+    None,
+    Color(ColorF),
+    CurrentColor,
+    Url(String),
+}
+
+/// Inherited presentation attributes.
+#[derive(Clone, Debug)]
+struct Style {
+    fill: PaintRef,
+    stroke: PaintRef,
+    stroke_style: StrokeStyle,
+    fill_rule: FillRule,
+    fill_opacity: Float,
+    stroke_opacity: Float,
+    /// Product of the `opacity` of the element and its ancestors.
+    opacity: Float,
+    color: ColorF,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style {
+            fill: PaintRef::Color(Color::BLACK.c),
+            stroke: PaintRef::None,
+            stroke_style: StrokeStyle::new(1.0),
+            fill_rule: FillRule::NonZero,
+            fill_opacity: 1.0,
+            stroke_opacity: 1.0,
+            opacity: 1.0,
+            color: Color::BLACK.c,
+        }
+    }
+}
+
+impl Style {
+    /// Style of `node`, inheriting from `self`. Declarations of the `style`
+    /// attribute override attributes.
+    fn inherit(&self, node: roxmltree::Node) -> Style {
+        let mut style = self.clone();
+        style.opacity = 1.0;
+        for attribute in node.attributes() {
+            style.set(attribute.name(), attribute.value());
+        }
+        if let Some(declarations) = node.attribute("style") {
+            for declaration in declarations.split(';') {
+                if let Some((name, value)) = declaration.split_once(':') {
+                    style.set(name.trim(), value.trim());
+                }
+            }
+        }
+        style.opacity *= self.opacity;
+        style
+    }
+
+    fn set(&mut self, name: &str, value: &str) {
+        let opacity = || number(value).map(|v| v.clamp(0.0, 1.0));
+        match name {
+            "fill" => {
+                if let Some(paint) = paint_ref(value) {
+                    self.fill = paint;
+                }
+            }
+            "stroke" => {
+                if let Some(paint) = paint_ref(value) {
+                    self.stroke = paint;
+                }
+            }
+            "stroke-width" => {
+                self.stroke_style.width = number(value).unwrap_or(self.stroke_style.width)
+            }
+            "stroke-dasharray" => {
+                self.stroke_style.dashes = match value {
+                    "none" => Vec::new(),
+                    _ => numbers(value),
+                }
+            }
+            "stroke-dashoffset" => {
+                self.stroke_style.dash_offset =
+                    number(value).unwrap_or(self.stroke_style.dash_offset)
+            }
+            "stroke-miterlimit" => {
+                self.stroke_style.miter_limit = number(value)
+                    .filter(|v| *v >= 1.0)
+                    .unwrap_or(self.stroke_style.miter_limit)
+            }
+            "fill-rule" => {
+                self.fill_rule = match value {
+                    "evenodd" => FillRule::EvenOdd,
+                    _ => FillRule::NonZero,
+                }
+            }
+            "fill-opacity" => self.fill_opacity = opacity().unwrap_or(self.fill_opacity),
+            "stroke-opacity" => self.stroke_opacity = opacity().unwrap_or(self.stroke_opacity),
+            "opacity" => self.opacity = opacity().unwrap_or(self.opacity),
+            "color" => self.color = color(value).unwrap_or(self.color),
+            _ => {}
+        }
+    }
+}
+
+struct Parser<'a, 'input> {
+    view_box: Rect,
+    gradients: HashMap<String, roxmltree::Node<'a, 'input>>,
+    shapes: Vec<SvgShape>,
+}
+
+impl<'a, 'input> Parser<'a, 'input> {
+    fn parse_children(&mut self, node: roxmltree::Node, style: &Style, transform: Mat3) {
+        for child in node.children().filter(|n| n.is_element()) {
+            self.parse_element(child, style, transform);
+        }
+    }
+
+    fn parse_element(&mut self, node: roxmltree::Node, parent: &Style, transform: Mat3) {
+        let name = node.tag_name().name();
+        if !matches!(
+            name,
+            "g" | "path" | "rect" | "circle" | "ellipse" | "line" | "polyline" | "polygon"
+        ) || node.attribute("display") == Some("none")
+        {
+            return;
+        }
+        let style = parent.inherit(node);
+        let transform = match node.attribute("transform") {
+            Some(value) => transform.multiply(&parse_transform(value)),
+            None => transform,
+        };
+        if name == "g" {
+            self.parse_children(node, &style, transform);
+            return;
+        }
+        let Some(mut path) = self.shape_path(node) else {
+            return;
+        };
+        path.set_fill_rule(style.fill_rule);
+        let Some(bounds) = path.bounds() else {
+            return;
+        };
+        let fill = self.paint(&style.fill, &style, style.fill_opacity, bounds);
+        let stroke = self
+            .paint(&style.stroke, &style, style.stroke_opacity, bounds)
+            .filter(|_| style.stroke_style.width > 0.0)
+            .map(|paint| SvgStroke {
+                paint,
+                style: style.stroke_style.clone(),
+            });
+        if fill.is_some() || stroke.is_some() {
+            self.shapes.push(SvgShape {
+                path,
+                transform,
+                fill,
+                stroke,
+            });
+        }
+    }
+
+    /// Outline of a shape element.
+    fn shape_path(&self, node: roxmltree::Node) -> Option<Path> {
+        let (vw, vh) = (self.view_box.width(), self.view_box.height());
+        let diagonal = (vw * vw + vh * vh).sqrt() / std::f32::consts::SQRT_2;
+        let x = |name| length(node.attribute(name), vw);
+        let y = |name| length(node.attribute(name), vh);
+        let r = |name| length(node.attribute(name), diagonal);
+        let mut path = Path::new();
+        match node.tag_name().name() {
+            "path" => path = parse_path_data(node.attribute("d")?),
+            "rect" => {
+                let (w, h) = (x("width"), y("height"));
+                if w <= 0.0 || h <= 0.0 {
+                    return None;
+                }
+                // A missing radius defaults to the other one.
+                let (rx, ry) = match (node.attribute("rx"), node.attribute("ry")) {
+                    (None, None) => (0.0, 0.0),
+                    (Some(_), None) => (x("rx"), x("rx")),
+                    (None, Some(_)) => (y("ry"), y("ry")),
+                    (Some(_), Some(_)) => (x("rx"), y("ry")),
+                };
+                let rect = Rect::from_float(x("x"), y("y"), x("x") + w, y("y") + h);
+                add_rounded_rect(&mut path, rect, rx.min(w * 0.5), ry.min(h * 0.5));
+            }
+            "circle" => {
+                let radius = r("r");
+                if radius <= 0.0 {
+                    return None;
+                }
+                add_ellipse(&mut path, Point::new(x("cx"), y("cy")), radius, radius);
+            }
+            "ellipse" => {
+                let (rx, ry) = (x("rx"), y("ry"));
+                if rx <= 0.0 || ry <= 0.0 {
+                    return None;
+                }
+                add_ellipse(&mut path, Point::new(x("cx"), y("cy")), rx, ry);
+            }
+            "line" => {
+                path.move_to(Point::new(x("x1"), y("y1")));
+                path.line_to(Point::new(x("x2"), y("y2")));
+            }
+            name @ ("polyline" | "polygon") => {
+                let values = numbers(node.attribute("points")?);
+                let mut points = values.chunks_exact(2).map(|p| Point::new(p[0], p[1]));
+                path.move_to(points.next()?);
+                points.for_each(|p| path.line_to(p));
+                if name == "polygon" {
+                    path.close();
+                }
+            }
+            _ => return None,
+        }
+        (!path.is_empty()).then_some(path)
+    }
+
+    /// Paint of `paint`, with its alpha multiplied by `opacity` and the
+    /// opacity of `style`. Gradients in bounding box units cover `bounds`.
+    fn paint(
+        &self,
+        paint: &PaintRef,
+        style: &Style,
+        opacity: Float,
+        bounds: Rect,
+    ) -> Option<Paint> {
+        let opacity = opacity * style.opacity;
+        let fade = |c: ColorF| [c[0], c[1], c[2], c[3] * opacity];
+        match paint {
+            PaintRef::None => None,
+            PaintRef::Color(c) => Some(Paint::Solid(fade(*c))),
+            PaintRef::CurrentColor => Some(Paint::Solid(fade(style.color))),
+            PaintRef::Url(id) => {
+                let node = *self.gradients.get(id)?;
+                self.gradient(node, style, bounds, fade)
+            }
+        }
+    }
+
+    fn gradient(
+        &self,
+        node: roxmltree::Node,
+        style: &Style,
+        bounds: Rect,
+        fade: impl Fn(ColorF) -> ColorF,
+    ) -> Option<Paint> {
+        // Attributes and stops missing from a gradient are taken from the
+        // gradient it references.
+        let mut chain = vec![node];
+        while chain.len() < 8
+            && let Some(href) = chain
+                .last()?
+                .attributes()
+                .find(|a| a.name() == "href")
+                .and_then(|a| a.value().strip_prefix('#'))
+            && let Some(next) = self.gradients.get(href)
+        {
+            chain.push(*next);
+        }
+        let attribute = |name: &str| chain.iter().find_map(|n| n.attribute(name));
+
+        let stops_node = chain
+            .iter()
+            .find(|n| n.children().any(|c| c.tag_name().name() == "stop"))?;
+        let mut stops = Vec::new();
+        let mut last_offset: Float = 0.0;
+        for stop in stops_node
+            .children()
+            .filter(|c| c.tag_name().name() == "stop")
+        {
+            let mut stop_color = None;
+            let mut stop_opacity = 1.0;
+            let mut apply = |name: &str, value: &str| match name {
+                "stop-color" if value == "currentColor" => stop_color = Some(style.color),
+                "stop-color" => stop_color = color(value),
+                "stop-opacity" => stop_opacity = number(value).unwrap_or(1.0).clamp(0.0, 1.0),
+                _ => {}
+            };
+            for a in stop.attributes() {
+                apply(a.name(), a.value());
+            }
+            if let Some(declarations) = stop.attribute("style") {
+                for declaration in declarations.split(';') {
+                    if let Some((name, value)) = declaration.split_once(':') {
+                        apply(name.trim(), value.trim());
+                    }
+                }
+            }
+            // Offsets never decrease.
+            let offset = length(stop.attribute("offset"), 1.0)
+                .clamp(0.0, 1.0)
+                .max(last_offset);
+            last_offset = offset;
+            let [r, g, b, a] = stop_color.unwrap_or(Color::BLACK.c);
+            stops.push(GradientStop::new(offset, fade([r, g, b, a * stop_opacity])));
+        }
+        if stops.len() == 1 {
+            return Some(Paint::Solid(stops[0].color));
+        }
+
+        let bounding_box = attribute("gradientUnits") != Some("userSpaceOnUse");
+        let units = if bounding_box {
+            Mat3::translate(bounds.min().x(), bounds.min().y())
+                .multiply(&Mat3::scale(bounds.width(), bounds.height()))
+        } else {
+            Mat3::identity()
+        };
+        let transform = units
+            .multiply(&attribute("gradientTransform").map_or(Mat3::identity(), parse_transform));
+        let spread = match attribute("spreadMethod") {
+            Some("reflect") => SpreadMode::Reflect,
+            Some("repeat") => SpreadMode::Repeat,
+            _ => SpreadMode::Pad,
+        };
+        let gradient = Gradient::new(stops)
+            .with_spread(spread)
+            .with_transform(transform);
+
+        // Lengths are fractions of the bounding box, or user space lengths.
+        let (vw, vh) = if bounding_box {
+            (1.0, 1.0)
+        } else {
+            (self.view_box.width(), self.view_box.height())
+        };
+        let value = |name: &str, reference: Float, default: &str| {
+            length(Some(attribute(name).unwrap_or(default)), reference)
+        };
+        match node.tag_name().name() {
+            "linearGradient" => Some(Paint::linear_gradient(
+                Point::new(value("x1", vw, "0%"), value("y1", vh, "0%")),
+                Point::new(value("x2", vw, "100%"), value("y2", vh, "0%")),
+                gradient,
+            )),
+            _ => {
+                let diagonal = (vw * vw + vh * vh).sqrt() / std::f32::consts::SQRT_2;
+                Some(Paint::radial_gradient(
+                    Point::new(value("cx", vw, "50%"), value("cy", vh, "50%")),
+                    value("r", diagonal, "50%"),
+                    gradient,
+                ))
+            }
+        }
+    }
+}
+
+/// Adds an ellipse approximated with cubic curves.
+fn add_ellipse(path: &mut Path, center: Point, rx: Float, ry: Float) {
+    let rect = Rect::from_float(
+        center.x() - rx,
+        center.y() - ry,
+        center.x() + rx,
+        center.y() + ry,
+    );
+    add_rounded_rect(path, rect, rx, ry);
+}
+
+/// Adds `rect` with elliptic corners of radii `rx` and `ry`.
+fn add_rounded_rect(path: &mut Path, rect: Rect, rx: Float, ry: Float) {
+    if rx <= 0.0 || ry <= 0.0 {
+        path.add_rect(rect);
+        return;
+    }
+    // Distance of the control points from the corner ends, relative to the
+    // radius.
+    const KAPPA: Float = 0.552_284_8;
+    let (kx, ky) = (rx * KAPPA, ry * KAPPA);
+    let (x0, y0, x1, y1) = (
+        rect.min().x(),
+        rect.min().y(),
+        rect.max().x(),
+        rect.max().y(),
+    );
+    let p = Point::new;
+    path.move_to(p(x0 + rx, y0));
+    path.line_to(p(x1 - rx, y0));
+    path.cubic_to(p(x1 - rx + kx, y0), p(x1, y0 + ry - ky), p(x1, y0 + ry));
+    path.line_to(p(x1, y1 - ry));
+    path.cubic_to(p(x1, y1 - ry + ky), p(x1 - rx + kx, y1), p(x1 - rx, y1));
+    path.line_to(p(x0 + rx, y1));
+    path.cubic_to(p(x0 + rx - kx, y1), p(x0, y1 - ry + ky), p(x0, y1 - ry));
+    path.line_to(p(x0, y0 + ry));
+    path.cubic_to(p(x0, y0 + ry - ky), p(x0 + rx - kx, y0), p(x0 + rx, y0));
+    path.close();
+}
+
+/// Parses a fill or stroke value. `None` if invalid, in which case the
+/// inherited value is kept.
+fn paint_ref(value: &str) -> Option<PaintRef> {
+    match value {
+        "none" => Some(PaintRef::None),
+        "currentColor" => Some(PaintRef::CurrentColor),
+        _ => match value.strip_prefix("url(") {
+            Some(url) => {
+                let id = url.split(')').next()?.trim().trim_matches(['\'', '"']);
+                Some(PaintRef::Url(id.strip_prefix('#')?.to_string()))
+            }
+            None => color(value).map(PaintRef::Color),
+        },
+    }
+}
+
+/// Parses a hex, `rgb()`, `rgba()` or named color.
+fn color(value: &str) -> Option<ColorF> {
+    let value = value.trim();
+    if value.starts_with('#') {
+        return Color::from_hex(value).ok().map(|c| c.c);
+    }
+    if let Some(args) = value
+        .strip_prefix("rgba(")
+        .or_else(|| value.strip_prefix("rgb("))
+    {
+        let args: Vec<&str> = args
+            .trim_end_matches(')')
+            .split([',', ' ', '/'])
+            .filter(|s| !s.is_empty())
+            .collect();
+        let channel = |s: &str| match s.strip_suffix('%') {
+            Some(p) => p.parse::<Float>().ok().map(|v| v / 100.0),
+            None => s.parse::<Float>().ok().map(|v| v / 255.0),
+        };
+        let alpha = match args.get(3) {
+            Some(a) => length(Some(a), 1.0),
+            None => 1.0,
+        };
+        let [r, g, b] = [
+            channel(args.first()?)?,
+            channel(args.get(1)?)?,
+            channel(args.get(2)?)?,
+        ];
+        return Some(Color::new(r, g, b, alpha).clamp().c);
+    }
+    let hex = match value.to_ascii_lowercase().as_str() {
+        "transparent" => return Some(Color::TRANSPARENT.c),
+        "black" => "000000",
+        "white" => "ffffff",
+        "red" => "ff0000",
+        "lime" => "00ff00",
+        "green" => "008000",
+        "blue" => "0000ff",
+        "yellow" => "ffff00",
+        "cyan" | "aqua" => "00ffff",
+        "magenta" | "fuchsia" => "ff00ff",
+        "gray" | "grey" => "808080",
+        "silver" => "c0c0c0",
+        "maroon" => "800000",
+        "olive" => "808000",
+        "purple" => "800080",
+        "teal" => "008080",
+        "navy" => "000080",
+        "orange" => "ffa500",
+        _ => return None,
+    };
+    Color::from_hex(hex).ok().map(|c| c.c)
+}
+
+/// Parses a number, ignoring a trailing unit.
+fn number(value: &str) -> Option<Float> {
+    Numbers::new(value).next()
+}
+
+/// Parses a length, taking percentages of `reference`. Missing or invalid
+/// lengths are zero.
+fn length(value: Option<&str>, reference: Float) -> Float {
+    let Some(value) = value.map(str::trim) else {
+        return 0.0;
+    };
+    match value.strip_suffix('%') {
+        Some(percent) => number(percent).map_or(0.0, |p| p * reference / 100.0),
+        None => number(value).unwrap_or(0.0),
+    }
+}
+
+/// Parses a length that is not a percentage.
+fn absolute_length(value: &str) -> Option<Float> {
+    if value.trim_end().ends_with('%') {
+        return None;
+    }
+    number(value)
+}
+
+/// Parses a list of numbers separated by whitespace or commas.
+fn numbers(value: &str) -> Vec<Float> {
+    Numbers::new(value).collect()
+}
+
+/// Parses a `transform` attribute. Parsing stops at the first invalid
+/// transform.
+fn parse_transform(value: &str) -> Mat3 {
+    let mut transform = Mat3::identity();
+    let mut rest = value;
+    while let Some((name, tail)) = rest.split_once('(') {
+        let Some((args, tail)) = tail.split_once(')') else {
+            break;
+        };
+        rest = tail;
+        let a = numbers(args);
+        let arg = |i: usize, default: Float| a.get(i).copied().unwrap_or(default);
+        let t = match (name.trim().trim_start_matches(','), a.len()) {
+            ("matrix", 6) => [[a[0], a[1], 0.0], [a[2], a[3], 0.0], [a[4], a[5], 1.0]].into(),
+            ("translate", 1 | 2) => Mat3::translate(a[0], arg(1, 0.0)),
+            ("scale", 1 | 2) => Mat3::scale(a[0], arg(1, a[0])),
+            ("rotate", 1) => Mat3::rotate(a[0].to_radians()),
+            ("rotate", 3) => Mat3::translate(a[1], a[2])
+                .multiply(&Mat3::rotate(a[0].to_radians()))
+                .multiply(&Mat3::translate(-a[1], -a[2])),
+            ("skewX", 1) => Mat3::skew(a[0].to_radians(), 0.0),
+            ("skewY", 1) => Mat3::skew(0.0, a[0].to_radians()),
+            _ => break,
+        };
+        transform = transform.multiply(&t);
+    }
+    transform
+}
+
+/// Iterator over the numbers of an attribute, stopping at the first
+/// character that does not start a number.
+struct Numbers<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Numbers<'a> {
+    fn new(value: &'a str) -> Self {
+        Numbers {
+            bytes: value.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn skip_separators(&mut self) {
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| b.is_ascii_whitespace() || *b == b',')
+        {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_separators();
+        self.bytes.get(self.pos).copied()
+    }
+
+    /// Parses an arc flag, which may be followed by a number without a
+    /// separator.
+    fn flag(&mut self) -> Option<bool> {
+        let flag = match self.peek()? {
+            b'0' => false,
+            b'1' => true,
+            _ => return None,
+        };
+        self.pos += 1;
+        Some(flag)
+    }
+}
+
+impl Iterator for Numbers<'_> {
+    type Item = Float;
+
+    fn next(&mut self) -> Option<Float> {
+        self.skip_separators();
+        let start = self.pos;
+        let at = |i: usize| self.bytes.get(i).copied();
+        let mut end = start;
+        if matches!(at(end), Some(b'+' | b'-')) {
+            end += 1;
+        }
+        let digits = |mut i: usize| {
+            while at(i).is_some_and(|b| b.is_ascii_digit()) {
+                i += 1;
+            }
+            i
+        };
+        let integer_end = digits(end);
+        let mut has_digits = integer_end > end;
+        end = integer_end;
+        if at(end) == Some(b'.') {
+            let fraction_end = digits(end + 1);
+            has_digits |= fraction_end > end + 1;
+            end = fraction_end;
+        }
+        if !has_digits {
+            return None;
+        }
+        if matches!(at(end), Some(b'e' | b'E')) {
+            let mut exponent = end + 1;
+            if matches!(at(exponent), Some(b'+' | b'-')) {
+                exponent += 1;
+            }
+            let exponent_end = digits(exponent);
+            if exponent_end > exponent {
+                end = exponent_end;
+            }
+        }
+        let value = std::str::from_utf8(&self.bytes[start..end])
+            .ok()?
+            .parse()
+            .ok()?;
+        self.pos = end;
+        Some(value)
+    }
+}
+
+/// Parses path data. As in SVG, the path is drawn up to the first error.
+pub fn parse_path_data(data: &str) -> Path {
+    let mut path = Path::new();
+    let mut numbers = Numbers::new(data);
+    let mut command = None;
+    let (mut current, mut start) = (Point::new(0.0, 0.0), Point::new(0.0, 0.0));
+    // Second control point of the last cubic or quadratic curve, reflected
+    // by smooth curves.
+    let mut last_control: Option<(u8, Point)> = None;
+    loop {
+        let next = match numbers.peek() {
+            None => break,
+            Some(b) if b.is_ascii_alphabetic() => {
+                numbers.pos += 1;
+                b
+            }
+            // Numbers repeat the last command, lines after a move. A close
+            // takes no numbers, so any that follow are an error.
+            Some(_) => match command {
+                Some(b'M') => b'L',
+                Some(b'm') => b'l',
+                Some(b'Z' | b'z') | None => break,
+                Some(c) => c,
+            },
+        };
+        command = Some(next);
+        let relative = next.is_ascii_lowercase();
+        let offset = if relative {
+            current
+        } else {
+            Point::new(0.0, 0.0)
+        };
+        let mut point = || -> Option<Point> {
+            let x = numbers.next()?;
+            let y = numbers.next()?;
+            Some(Point::new(offset.x() + x, offset.y() + y))
+        };
+        let kind = next.to_ascii_uppercase();
+        let reflected = |kinds: &[u8]| match last_control {
+            Some((k, c)) if kinds.contains(&k) => {
+                Point::new(2.0 * current.x() - c.x(), 2.0 * current.y() - c.y())
+            }
+            _ => current,
+        };
+        let control = match kind {
+            b'M' => {
+                let Some(p) = point() else { break };
+                path.move_to(p);
+                (current, start) = (p, p);
+                None
+            }
+            b'L' => {
+                let Some(p) = point() else { break };
+                path.line_to(p);
+                current = p;
+                None
+            }
+            b'H' | b'V' => {
+                let Some(v) = numbers.next() else { break };
+                current = match (kind, relative) {
+                    (b'H', true) => Point::new(current.x() + v, current.y()),
+                    (b'H', false) => Point::new(v, current.y()),
+                    (_, true) => Point::new(current.x(), current.y() + v),
+                    (_, false) => Point::new(current.x(), v),
+                };
+                path.line_to(current);
+                None
+            }
+            b'C' => {
+                let (Some(c1), Some(c2), Some(p)) = (point(), point(), point()) else {
+                    break;
+                };
+                path.cubic_to(c1, c2, p);
+                current = p;
+                Some((b'C', c2))
+            }
+            b'S' => {
+                let c1 = reflected(b"CS");
+                let (Some(c2), Some(p)) = (point(), point()) else {
+                    break;
+                };
+                path.cubic_to(c1, c2, p);
+                current = p;
+                Some((b'C', c2))
+            }
+            b'Q' => {
+                let (Some(c), Some(p)) = (point(), point()) else {
+                    break;
+                };
+                path.quad_to(c, p);
+                current = p;
+                Some((b'Q', c))
+            }
+            b'T' => {
+                let c = reflected(b"QT");
+                let Some(p) = point() else { break };
+                path.quad_to(c, p);
+                current = p;
+                Some((b'Q', c))
+            }
+            b'A' => {
+                let (Some(rx), Some(ry), Some(angle)) =
+                    (numbers.next(), numbers.next(), numbers.next())
+                else {
+                    break;
+                };
+                let (Some(large_arc), Some(sweep)) = (numbers.flag(), numbers.flag()) else {
+                    break;
+                };
+                let Some(x) = numbers.next() else { break };
+                let Some(y) = numbers.next() else { break };
+                let p = Point::new(offset.x() + x, offset.y() + y);
+                add_arc(&mut path, current, p, rx, ry, angle, large_arc, sweep);
+                current = p;
+                None
+            }
+            b'Z' => {
+                path.close();
+                current = start;
+                None
+            }
+            _ => break,
+        };
+        last_control = control;
+    }
+    path
+}
+
+/// Adds an elliptical arc from `from` to `to` as cubic curves, following
+/// the SVG implementation notes.
+#[allow(clippy::too_many_arguments)]
+fn add_arc(
+    path: &mut Path,
+    from: Point,
+    to: Point,
+    rx: Float,
+    ry: Float,
+    angle: Float,
+    large_arc: bool,
+    sweep: bool,
+) {
+    let (mut rx, mut ry) = (rx.abs(), ry.abs());
+    if rx == 0.0 || ry == 0.0 || from == to {
+        path.line_to(to);
+        return;
+    }
+    let (sin, cos) = angle.to_radians().sin_cos();
+    // Midpoint in the coordinates of the ellipse axes.
+    let (dx, dy) = ((from.x() - to.x()) * 0.5, (from.y() - to.y()) * 0.5);
+    let (x1, y1) = (cos * dx + sin * dy, -sin * dx + cos * dy);
+    // Radii too small to reach `to` are scaled up.
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1.0 {
+        let s = lambda.sqrt();
+        rx *= s;
+        ry *= s;
+    }
+    let (rx2, ry2) = (rx * rx, ry * ry);
+    let num = rx2 * ry2 - rx2 * y1 * y1 - ry2 * x1 * x1;
+    let den = rx2 * y1 * y1 + ry2 * x1 * x1;
+    let mut coef = (num / den).max(0.0).sqrt();
+    if large_arc == sweep {
+        coef = -coef;
+    }
+    let (cx1, cy1) = (coef * rx * y1 / ry, -coef * ry * x1 / rx);
+    let cx = cos * cx1 - sin * cy1 + (from.x() + to.x()) * 0.5;
+    let cy = sin * cx1 + cos * cy1 + (from.y() + to.y()) * 0.5;
+
+    let angle_of = |ux: Float, uy: Float| uy.atan2(ux);
+    let theta = angle_of((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let mut delta = angle_of((-x1 - cx1) / rx, (-y1 - cy1) / ry) - theta;
+    if sweep && delta < 0.0 {
+        delta += 2.0 * PI;
+    } else if !sweep && delta > 0.0 {
+        delta -= 2.0 * PI;
+    }
+
+    // Cubic curves of at most a quarter turn each.
+    let segments = (delta.abs() / (PI * 0.5)).ceil().max(1.0) as usize;
+    let step = delta / segments as Float;
+    let k = 4.0 / 3.0 * (step / 4.0).tan();
+    let point_at = |t: Float, dx: Float, dy: Float| {
+        let (s, c) = t.sin_cos();
+        let (ex, ey) = (rx * (c - k * s * dx), ry * (s + k * c * dy));
+        Point::new(cx + cos * ex - sin * ey, cy + sin * ex + cos * ey)
+    };
+    for i in 0..segments {
+        let t0 = theta + step * i as Float;
+        let t1 = t0 + step;
+        let c1 = point_at(t0, 1.0, 1.0);
+        let c2 = point_at(t1, -1.0, -1.0);
+        let end = if i + 1 == segments {
+            to
+        } else {
+            point_at(t1, 0.0, 0.0)
+        };
+        path.cubic_to(c1, c2, end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::PathElement::{self, *};
+
+    fn p(x: Float, y: Float) -> Point {
+        Point::new(x, y)
+    }
+
+    fn elements(data: &str) -> Vec<PathElement> {
+        parse_path_data(data).elements().to_vec()
+    }
+
+    #[test]
+    fn shapes_inherit_the_root_style() {
+        let svg = Svg::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 24 24" fill="none"
+                stroke="currentColor" stroke-width="2" color="red">
+                <path d="M4 4L20 20"/>
+                <circle cx="12" cy="12" r="4" fill="blue"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let [line, circle] = svg.shapes() else {
+            panic!("expected 2 shapes");
+        };
+        assert_eq!(line.fill, None);
+        let stroke = line.stroke.as_ref().unwrap();
+        assert_eq!(stroke.style.width, 2.0);
+        assert_eq!(stroke.paint, Paint::solid(Color::RED));
+        assert!(circle.fill.is_some());
+        assert_eq!(circle.stroke.as_ref().unwrap().style.width, 2.0);
+    }
+
+    #[test]
+    fn number_after_close_stops_parsing() {
+        assert_eq!(
+            elements("M0 0 L1 1 L2 0 Z 5"),
+            [
+                MoveTo(p(0.0, 0.0)),
+                LineTo(p(1.0, 1.0)),
+                LineTo(p(2.0, 0.0)),
+                Close
+            ]
+        );
+        assert_eq!(elements("M0 0z1 1").len(), 2);
+    }
+
+    #[test]
+    fn implicit_repeats() {
+        assert_eq!(
+            elements("M0 0 1 1 2 2 L3 3 4 4"),
+            [
+                MoveTo(p(0.0, 0.0)),
+                LineTo(p(1.0, 1.0)),
+                LineTo(p(2.0, 2.0)),
+                LineTo(p(3.0, 3.0)),
+                LineTo(p(4.0, 4.0)),
+            ]
+        );
+        assert_eq!(
+            elements("m1 1 2 2h1 1v-1"),
+            [
+                MoveTo(p(1.0, 1.0)),
+                LineTo(p(3.0, 3.0)),
+                LineTo(p(4.0, 3.0)),
+                LineTo(p(5.0, 3.0)),
+                LineTo(p(5.0, 2.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_commands() {
+        assert_eq!(
+            elements("m10 10 l5 0 q5 0 5 5 c0 5-5 5-5 5z l1 1"),
+            [
+                MoveTo(p(10.0, 10.0)),
+                LineTo(p(15.0, 10.0)),
+                QuadTo(p(20.0, 10.0), p(20.0, 15.0)),
+                CubicTo(p(20.0, 20.0), p(15.0, 20.0), p(15.0, 20.0)),
+                Close,
+                LineTo(p(11.0, 11.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn compact_numbers() {
+        assert_eq!(
+            elements("M.5.5L-1-1e1"),
+            [MoveTo(p(0.5, 0.5)), LineTo(p(-1.0, -10.0))]
+        );
+    }
+
+    #[test]
+    fn smooth_curves_reflect_controls() {
+        assert_eq!(
+            elements("M0 0 C0 1 1 1 1 0 S2 -1 2 0"),
+            [
+                MoveTo(p(0.0, 0.0)),
+                CubicTo(p(0.0, 1.0), p(1.0, 1.0), p(1.0, 0.0)),
+                CubicTo(p(1.0, -1.0), p(2.0, -1.0), p(2.0, 0.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn arcs() {
+        // Half circle of radius 1 from (0, 0) to (2, 0), with flags written
+        // without separators.
+        let elements = elements("M0 0 A1 1 0 012 0");
+        let Some(&CubicTo(_, _, end)) = elements.last() else {
+            panic!("{elements:?}");
+        };
+        assert_eq!(end, p(2.0, 0.0));
+        assert_eq!(elements.len(), 3);
+        // Zero radii draw a line.
+        assert_eq!(
+            self::elements("M0 0 a0 1 0 0 0 3 4"),
+            [MoveTo(p(0.0, 0.0)), LineTo(p(3.0, 4.0))]
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_error() {
+        assert_eq!(
+            elements("M0 0 L1 1 L2"),
+            [MoveTo(p(0.0, 0.0)), LineTo(p(1.0, 1.0))]
+        );
+        assert_eq!(elements("1 1 L2 2"), []);
+    }
+}
@@ -0,0 +1,448 @@
+//! Conversion of paths into edges, triangles and stroke outlines shared by
+//! the backends.
+
+#[cfg(feature = "wgpu")]
+use crate::path::FillRule;
+use crate::{
+    geometry::{Float, Mat3, Point},
+    path::{Polyline, StrokeStyle},
+};
+
+/// Non-horizontal line segment with `y0 < y1`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Edge {
+    pub x0: Float,
+    pub y0: Float,
+    pub x1: Float,
+    pub y1: Float,
+    /// `1` if the segment goes down in the original polyline, `-1` if up.
+    pub winding: i32,
+}
+
+impl Edge {
+    fn new(a: Point, b: Point) -> Option<Self> {
+        if a.y() == b.y() {
+            return None;
+        }
+        Some(if a.y() < b.y() {
+            Edge {
+                x0: a.x(),
+                y0: a.y(),
+                x1: b.x(),
+                y1: b.y(),
+                winding: 1,
+            }
+        } else {
+            Edge {
+                x0: b.x(),
+                y0: b.y(),
+                x1: a.x(),
+                y1: a.y(),
+                winding: -1,
+            }
+        })
+    }
+
+    #[inline(always)]
+    pub fn x_at(&self, y: Float) -> Float {
+        self.x0 + (y - self.y0) * (self.x1 - self.x0) / (self.y1 - self.y0)
+    }
+
+    /// `y` of the intersection with `other`, if they cross inside both.
+    #[cfg(feature = "wgpu")]
+    fn intersection_y(&self, other: &Edge) -> Option<Float> {
+        let top = self.y0.max(other.y0);
+        let bottom = self.y1.min(other.y1);
+        if top >= bottom {
+            return None;
+        }
+        let d_top = self.x_at(top) - other.x_at(top);
+        let d_bottom = self.x_at(bottom) - other.x_at(bottom);
+        if d_top * d_bottom >= 0.0 {
+            return None;
+        }
+        let t = d_top / (d_top - d_bottom);
+        Some(top + (bottom - top) * t)
+    }
+}
+
+/// Edges of the polylines, all treated as closed.
+pub(crate) fn edges(polylines: &[Polyline]) -> Vec<Edge> {
+    let mut edges = Vec::new();
+    for polyline in polylines {
+        let points = &polyline.points;
+        if points.len() < 2 {
+            continue;
+        }
+        for i in 0..points.len() {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            edges.extend(Edge::new(a, b));
+        }
+    }
+    edges
+}
+
+#[cfg(feature = "wgpu")]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Triangles {
+    pub vertices: Vec<Point>,
+    pub indices: Vec<u32>,
+}
+
+#[cfg(feature = "wgpu")]
+impl Triangles {
+    fn push_trapezoid(&mut self, top: Float, bottom: Float, left: [Float; 2], right: [Float; 2]) {
+        if right[0] - left[0] <= 0.0 && right[1] - left[1] <= 0.0 {
+            return;
+        }
+        let base = self.vertices.len() as u32;
+        self.vertices.extend([
+            Point::new(left[0], top),
+            Point::new(right[0], top),
+            Point::new(right[1], bottom),
+            Point::new(left[1], bottom),
+        ]);
+        self.indices
+            .extend([base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+}
+
+/// Triangulates the area inside `polylines` by decomposing it into
+/// horizontal trapezoids. The triangles do not overlap.
+#[cfg(feature = "wgpu")]
+pub(crate) fn fill(polylines: &[Polyline], fill_rule: FillRule) -> Triangles {
+    let edges = edges(polylines);
+    let mut triangles = Triangles::default();
+    if edges.is_empty() {
+        return triangles;
+    }
+
+    // Split the plane into bands at every vertex and every crossing, so that
+    // the edges active in a band never cross inside it.
+    let mut ys = Vec::with_capacity(edges.len() * 2);
+    for (i, a) in edges.iter().enumerate() {
+        ys.push(a.y0);
+        ys.push(a.y1);
+        for b in &edges[i + 1..] {
+            ys.extend(a.intersection_y(b));
+        }
+    }
+    ys.sort_by(|a, b| a.total_cmp(b));
+    ys.dedup_by(|a, b| (*a - *b).abs() < 1e-4);
+
+    let mut active = Vec::new();
+    for band in ys.windows(2) {
+        let (top, bottom) = (band[0], band[1]);
+        let mid = (top + bottom) * 0.5;
+        active.clear();
+        active.extend(
+            edges
+                .iter()
+                .filter(|e| e.y0 < mid && mid < e.y1)
+                .map(|e| (e.x_at(mid), [e.x_at(top), e.x_at(bottom)], e.winding)),
+        );
+        active.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut winding = 0;
+        let mut left = [0.0; 2];
+        for &(_, x, w) in &active {
+            let was_inside = fill_rule.is_inside(winding);
+            winding += w;
+            match (was_inside, fill_rule.is_inside(winding)) {
+                (false, true) => left = x,
+                (true, false) => triangles.push_trapezoid(top, bottom, left, x),
+                _ => {}
+            }
+        }
+    }
+    triangles
+}
+
+/// Signed area, positive for clockwise polygons in y-down coordinates.
+fn signed_area(points: &[Point]) -> Float {
+    let mut area = 0.0;
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        area += a.x() * b.y() - b.x() * a.y();
+    }
+    area * 0.5
+}
+
+/// Closed polygon oriented clockwise, so overlapping polygons union under
+/// [`FillRule::NonZero`](crate::path::FillRule::NonZero).
+fn oriented(mut points: Vec<Point>) -> Polyline {
+    if signed_area(&points) < 0.0 {
+        points.reverse();
+    }
+    Polyline {
+        points,
+        closed: true,
+    }
+}
+
+#[inline]
+fn direction(a: Point, b: Point) -> Option<[Float; 2]> {
+    let (dx, dy) = (b.x() - a.x(), b.y() - a.y());
+    let len = (dx * dx + dy * dy).sqrt();
+    (len > 1e-6).then(|| [dx / len, dy / len])
+}
+
+#[inline]
+fn offset(p: Point, d: [Float; 2], scale: Float) -> Point {
+    Point::new(p.x() + d[0] * scale, p.y() + d[1] * scale)
+}
+
+/// Outlines in user space of `style` stroking `polylines`, flattened in
+/// user space and drawn with `transform`. The outlines are meant to be
+/// filled with [`FillRule::NonZero`](crate::path::FillRule::NonZero).
+///
+/// Hairlines are stroked in pixels and mapped back to user space, so
+/// nothing is drawn if `transform` can not be inverted.
+pub(crate) fn stroke_style(
+    polylines: &[Polyline],
+    style: &StrokeStyle,
+    transform: &Mat3,
+) -> Vec<Polyline> {
+    let dashed;
+    let polylines = if style.is_dashed() {
+        dashed = dash(polylines, &style.dashes, style.dash_offset);
+        &dashed
+    } else {
+        polylines
+    };
+    if !style.hairline {
+        return stroke(polylines, style.width, style.miter_limit);
+    }
+    let Some(inverse) = transform.invert() else {
+        return Vec::new();
+    };
+    let mut pixels = polylines.to_vec();
+    pixels.iter_mut().for_each(|p| p.transform(transform));
+    let mut outlines = stroke(&pixels, 1.0, style.miter_limit);
+    outlines.iter_mut().for_each(|p| p.transform(&inverse));
+    outlines
+}
+
+/// Splits `polylines` into open polylines along the dashes of the
+/// `pattern` of alternating dash and gap lengths, started at `offset`
+/// into it for each polyline. The pattern must have a positive length.
+pub(crate) fn dash(polylines: &[Polyline], pattern: &[Float], offset: Float) -> Vec<Polyline> {
+    let pattern: Vec<Float> = if pattern.len() % 2 == 1 {
+        pattern.iter().chain(pattern).copied().collect()
+    } else {
+        pattern.to_vec()
+    };
+    let total: Float = pattern.iter().sum();
+    let mut dashes = Vec::new();
+    for polyline in polylines {
+        let mut points = polyline.points.clone();
+        if polyline.closed && points.len() > 1 {
+            points.push(points[0]);
+        }
+        // Position in the pattern: index of the dash or gap and distance
+        // left in it.
+        let mut index = 0;
+        let mut left = offset.rem_euclid(total);
+        while left >= pattern[index] {
+            left -= pattern[index];
+            index = (index + 1) % pattern.len();
+        }
+        left = pattern[index] - left;
+        let mut current = Polyline::default();
+        if index % 2 == 0 && !points.is_empty() {
+            current.points.push(points[0]);
+        }
+        for pair in points.windows(2) {
+            let (mut a, b) = (pair[0], pair[1]);
+            let mut length = a.distance_from(&b);
+            while length > left {
+                let t = left / length;
+                a = Point::new(a.x() + (b.x() - a.x()) * t, a.y() + (b.y() - a.y()) * t);
+                length -= left;
+                if index % 2 == 0 {
+                    current.points.push(a);
+                    dashes.push(std::mem::take(&mut current));
+                } else {
+                    current.points.push(a);
+                }
+                index = (index + 1) % pattern.len();
+                left = pattern[index];
+            }
+            left -= length;
+            if index % 2 == 0 {
+                current.points.push(b);
+            }
+        }
+        if index % 2 == 0 {
+            dashes.push(current);
+        }
+    }
+    dashes.retain(|d| d.points.len() > 1);
+    dashes
+}
+
+/// Outlines of a stroke of `width` along `polylines` with butt caps and
+/// miter joins, beveled past `miter_limit`. The outlines are meant to be
+/// filled with [`FillRule::NonZero`](crate::path::FillRule::NonZero).
+pub(crate) fn stroke(polylines: &[Polyline], width: Float, miter_limit: Float) -> Vec<Polyline> {
+    let hw = width * 0.5;
+    let mut outlines = Vec::new();
+    if hw <= 0.0 {
+        return outlines;
+    }
+
+    for polyline in polylines {
+        let mut points = polyline.points.clone();
+        points.dedup_by(|a, b| a.distance_from(b) <= 1e-6);
+        if polyline.closed
+            && points.len() > 2
+            && points[0].distance_from(&points[points.len() - 1]) <= 1e-6
+        {
+            points.pop();
+        }
+        if points.len() < 2 {
+            continue;
+        }
+
+        let segments = if polyline.closed {
+            points.len()
+        } else {
+            points.len() - 1
+        };
+        let mut directions = Vec::with_capacity(segments);
+        for i in 0..segments {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            let Some(d) = direction(a, b) else { continue };
+            let n = [-d[1], d[0]];
+            outlines.push(oriented(vec![
+                offset(a, n, hw),
+                offset(b, n, hw),
+                offset(b, n, -hw),
+                offset(a, n, -hw),
+            ]));
+            directions.push((i, d));
+        }
+
+        // Joins between consecutive segments.
+        let join_count = if polyline.closed {
+            directions.len()
+        } else {
+            directions.len().saturating_sub(1)
+        };
+        for j in 0..join_count {
+            let (_, da) = directions[j];
+            let (i, db) = directions[(j + 1) % directions.len()];
+            let p = points[i];
+            outlines.extend(join(p, da, db, hw, miter_limit));
+        }
+    }
+    outlines
+}
+
+fn join(
+    p: Point,
+    da: [Float; 2],
+    db: [Float; 2],
+    hw: Float,
+    miter_limit: Float,
+) -> Option<Polyline> {
+    let cross = da[0] * db[1] - da[1] * db[0];
+    if cross.abs() <= 1e-6 {
+        return None;
+    }
+    // The outline opens on the side opposite to the turn.
+    let side = if cross > 0.0 { -hw } else { hw };
+    let na = [-da[1], da[0]];
+    let nb = [-db[1], db[0]];
+    let a = offset(p, na, side);
+    let b = offset(p, nb, side);
+
+    let dot = da[0] * db[0] + da[1] * db[1];
+    let scale = 1.0 / (1.0 + dot);
+    let miter = [(na[0] + nb[0]) * scale, (na[1] + nb[1]) * scale];
+    let miter_len = (miter[0] * miter[0] + miter[1] * miter[1]).sqrt();
+    if 1.0 + dot > 1e-6 && miter_len <= miter_limit {
+        Some(oriented(vec![p, a, offset(p, miter, side), b]))
+    } else {
+        Some(oriented(vec![p, a, b]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(points: &[[Float; 2]]) -> Vec<Polyline> {
+        vec![Polyline {
+            points: points.iter().map(|&[x, y]| Point::new(x, y)).collect(),
+            closed: false,
+        }]
+    }
+
+    /// Start and end x of each dash along a horizontal line.
+    fn spans(dashes: &[Polyline]) -> Vec<[Float; 2]> {
+        dashes
+            .iter()
+            .map(|d| [d.points[0].x(), d.points[d.points.len() - 1].x()])
+            .collect()
+    }
+
+    fn has_point(outlines: &[Polyline], x: Float, y: Float) -> bool {
+        outlines
+            .iter()
+            .flat_map(|o| &o.points)
+            .any(|p| (p.x() - x).abs() < 1e-4 && (p.y() - y).abs() < 1e-4)
+    }
+
+    #[test]
+    fn dashes_start_at_the_offset() {
+        let line = line(&[[0.0, 0.0], [10.0, 0.0]]);
+        let solid = [[0.0, 2.0], [4.0, 6.0], [8.0, 10.0]];
+        assert_eq!(spans(&dash(&line, &[2.0, 2.0], 0.0)), solid);
+        // A whole pattern further, or exactly at the end of a dash.
+        assert_eq!(spans(&dash(&line, &[2.0, 2.0], 4.0)), solid);
+        assert_eq!(
+            spans(&dash(&line, &[2.0, 2.0], 2.0)),
+            [[2.0, 4.0], [6.0, 8.0]]
+        );
+        // Negative offsets wrap around the pattern.
+        assert_eq!(
+            spans(&dash(&line, &[2.0, 2.0], -1.0)),
+            [[1.0, 3.0], [5.0, 7.0], [9.0, 10.0]]
+        );
+        // Odd patterns are repeated.
+        assert_eq!(spans(&dash(&line, &[3.0], 0.0)), [[0.0, 3.0], [6.0, 9.0]]);
+    }
+
+    #[test]
+    fn zero_length_dashes_draw_nothing() {
+        let line = line(&[[0.0, 0.0], [10.0, 0.0]]);
+        let dots = dash(&line, &[0.0, 2.0], 0.0);
+        assert!(stroke(&dots, 1.0, 4.0).is_empty());
+        // Zero length gaps leave the line solid.
+        let dashes = dash(&line, &[2.0, 0.0], 0.0);
+        let length: Float = spans(&dashes).iter().map(|[a, b]| b - a).sum();
+        assert_eq!(length, 10.0);
+    }
+
+    #[test]
+    fn sharp_joins_fall_back_to_bevels() {
+        let corner = line(&[[0.0, 0.0], [10.0, 0.0], [10.0, 10.0]]);
+        // The miter of a right angle is sqrt(2) half widths long.
+        assert!(has_point(&stroke(&corner, 2.0, 1.5), 11.0, -1.0));
+        let beveled = stroke(&corner, 2.0, 1.4);
+        assert!(!has_point(&beveled, 11.0, -1.0));
+        assert!(has_point(&beveled, 10.0, -1.0) && has_point(&beveled, 11.0, 0.0));
+    }
+
+    #[test]
+    fn hairlines_are_one_pixel_wide() {
+        let line = line(&[[0.0, 0.0], [10.0, 0.0]]);
+        let outlines = stroke_style(&line, &StrokeStyle::hairline(), &Mat3::scale(4.0, 4.0));
+        assert!(has_point(&outlines, 0.0, 0.125));
+        assert!(has_point(&outlines, 10.0, -0.125));
+        // Singular transforms draw nothing.
+        let singular = stroke_style(&line, &StrokeStyle::hairline(), &Mat3::scale(0.0, 0.0));
+        assert!(singular.is_empty());
+    }
+}
@@ -0,0 +1,392 @@
+use std::{collections::HashMap, hash::Hash};
+
+use crate::{
+    atlas::{Allocation, AtlasAllocator},
+    geometry::{Float, Rect, Size},
+    image::{Image, ImageId},
+};
+
+use super::{
+    RenderPipeline, TextureAlphaMode, TextureColorSpace, TextureId, TextureRegion, WgpuErr,
+    WgpuResult,
+};
+
+/// Texels repeated around each image, so that linear sampling at its edges
+/// does not read its neighbours.
+const PADDING: u32 = 1;
+
+/// Image packed into a [`TextureAtlas`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasEntry {
+    /// Page holding the image, for [`Context::draw_image`].
+    ///
+    /// [`Context::draw_image`]: crate::Context::draw_image
+    pub image: ImageId,
+    /// Texels of the image in the page.
+    pub rect: Rect,
+    /// `rect` in `0.0..=1.0` texture coordinates.
+    pub uv: Rect,
+    /// Texels per logical unit of the image: the scale factor of the atlas.
+    pub scale_factor: Float,
+}
+
+impl AtlasEntry {
+    /// Size of the image in logical units, drawing its texels one to one
+    /// with the pixels of a viewport of the same scale factor.
+    pub fn size(&self) -> Size {
+        Size::new(
+            self.rect.width() / self.scale_factor,
+            self.rect.height() / self.scale_factor,
+        )
+    }
+}
+
+struct Page {
+    texture: TextureId,
+    allocator: AtlasAllocator,
+}
+
+struct Slot {
+    page: usize,
+    allocation: Allocation,
+    /// Frame the entry was last inserted or looked up in.
+    last_used: u64,
+}
+
+/// Small images packed into shared textures, so that drawing them in a row
+/// from the same page takes a single draw call.
+///
+/// Pages are added up to a maximum count. Once all are full, the least
+/// recently used entries not used in the current frame are evicted.
+///
+/// Images, such as glyphs, should be rasterized at the physical resolution
+/// of the scale factor of the atlas. Changing it removes every entry, so
+/// that they are rasterized again.
+pub struct TextureAtlas<K> {
+    page_size: u32,
+    max_pages: usize,
+    pages: Vec<Page>,
+    slots: HashMap<K, Slot>,
+    frame: u64,
+    scale_factor: Float,
+}
+
+impl<K: Hash + Eq + Clone> TextureAtlas<K> {
+    /// Creates an empty atlas of `page_size` x `page_size` pages.
+    pub fn new(page_size: u32, max_pages: usize) -> Self {
+        TextureAtlas {
+            page_size,
+            max_pages,
+            pages: Vec::new(),
+            slots: HashMap::new(),
+            frame: 0,
+            scale_factor: 1.0,
+        }
+    }
+
+    /// Texels per logical unit of the images.
+    pub fn scale_factor(&self) -> Float {
+        self.scale_factor
+    }
+
+    /// Sets the texels per logical unit of the images inserted next.
+    /// Removes every entry if it changes, returning `true`: they have to be
+    /// rasterized again at the new resolution. Non-positive scale factors
+    /// are replaced by 1.
+    pub fn set_scale_factor(&mut self, scale_factor: Float) -> bool {
+        let scale_factor = if scale_factor > 0.0 {
+            scale_factor
+        } else {
+            1.0
+        };
+        if scale_factor == self.scale_factor {
+            return false;
+        }
+        self.scale_factor = scale_factor;
+        self.clear();
+        true
+    }
+
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    /// Textures of the pages.
+    pub fn pages(&self) -> impl Iterator<Item = TextureId> + '_ {
+        self.pages.iter().map(|page| page.texture)
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Starts a new frame. Entries used in the current frame are never
+    /// evicted.
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Returns the entry of `key`, marking it as used in the current frame.
+    pub fn get(&mut self, key: &K) -> Option<AtlasEntry> {
+        let slot = self.slots.get_mut(key)?;
+        slot.last_used = self.frame;
+        let (page, allocation) = (slot.page, slot.allocation);
+        Some(self.entry(page, allocation))
+    }
+
+    /// Packs `image` under `key`, replacing any previous image of the key.
+    pub fn insert(
+        &mut self,
+        pipeline: &mut RenderPipeline,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        key: K,
+        image: &Image,
+    ) -> WgpuResult<AtlasEntry> {
+        self.remove(&key);
+        let (width, height) = (image.width() + 2 * PADDING, image.height() + 2 * PADDING);
+        if width > self.page_size || height > self.page_size {
+            return Err(WgpuErr::InvalidTextureSize);
+        }
+        let (page, allocation) = self.allocate(pipeline, device, width, height)?;
+        let region = TextureRegion::new(allocation.x, allocation.y, width, height);
+        pipeline.update_texture(
+            device,
+            queue,
+            self.pages[page].texture,
+            region,
+            &padded(image),
+        )?;
+        let allocation = Allocation {
+            x: allocation.x + PADDING,
+            y: allocation.y + PADDING,
+            width: image.width(),
+            height: image.height(),
+            ..allocation
+        };
+        self.slots.insert(
+            key,
+            Slot {
+                page,
+                allocation,
+                last_used: self.frame,
+            },
+        );
+        Ok(self.entry(page, allocation))
+    }
+
+    /// Frees the space of `key`. Returns `false` if it is not in the atlas.
+    pub fn remove(&mut self, key: &K) -> bool {
+        let Some(slot) = self.slots.remove(key) else {
+            return false;
+        };
+        self.pages[slot.page]
+            .allocator
+            .deallocate(slot.allocation.id);
+        true
+    }
+
+    /// Removes every entry, keeping the pages.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        for page in &mut self.pages {
+            page.allocator.clear();
+        }
+    }
+
+    /// Destroys the textures of the pages and removes every entry.
+    pub fn destroy(&mut self, pipeline: &mut RenderPipeline) -> WgpuResult<()> {
+        self.slots.clear();
+        for page in self.pages.drain(..) {
+            pipeline.destroy_texture(page.texture)?;
+        }
+        Ok(())
+    }
+
+    /// Allocates `width` x `height` texels in an existing page, a new page,
+    /// or space freed by evicting the least recently used entries.
+    fn allocate(
+        &mut self,
+        pipeline: &mut RenderPipeline,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+    ) -> WgpuResult<(usize, Allocation)> {
+        for (i, page) in self.pages.iter_mut().enumerate() {
+            if let Some(allocation) = page.allocator.allocate(width, height) {
+                return Ok((i, allocation));
+            }
+        }
+        if self.pages.len() < self.max_pages {
+            let size = self.page_size;
+            // New textures are zeroed, which is transparent in any alpha
+            // mode, so the page is not uploaded.
+            let texture = pipeline.create_empty_texture(
+                device,
+                size,
+                size,
+                TextureColorSpace::Srgb,
+                TextureAlphaMode::Straight,
+            )?;
+            let mut allocator = AtlasAllocator::new(size, size);
+            let allocation = allocator
+                .allocate(width, height)
+                .ok_or(WgpuErr::InvalidTextureSize)?;
+            self.pages.push(Page { texture, allocator });
+            return Ok((self.pages.len() - 1, allocation));
+        }
+        self.evict(width, height).ok_or(WgpuErr::AtlasFull)
+    }
+
+    /// Evicts the least recently used entries of a page until `width` x
+    /// `height` texels fit in it. Entries are only evicted once a page is
+    /// known to fit, so nothing is lost when none does.
+    fn evict(&mut self, width: u32, height: u32) -> Option<(usize, Allocation)> {
+        let mut evictable: Vec<(u64, K)> = self
+            .slots
+            .iter()
+            .filter(|(_, slot)| slot.last_used < self.frame)
+            .map(|(key, slot)| (slot.last_used, key.clone()))
+            .collect();
+        evictable.sort_by_key(|(last_used, _)| *last_used);
+        // Frees the entries on copies of the allocators, in order of last
+        // use, until one of them fits.
+        let mut allocators: Vec<AtlasAllocator> = self
+            .pages
+            .iter()
+            .map(|page| page.allocator.clone())
+            .collect();
+        for (i, (_, key)) in evictable.iter().enumerate() {
+            let slot = &self.slots[key];
+            let allocator = &mut allocators[slot.page];
+            allocator.deallocate(slot.allocation.id);
+            let Some(allocation) = allocator.allocate(width, height) else {
+                continue;
+            };
+            let page = slot.page;
+            for (_, key) in &evictable[..=i] {
+                if self.slots[key].page == page {
+                    self.slots.remove(key);
+                }
+            }
+            self.pages[page].allocator = allocators.swap_remove(page);
+            return Some((page, allocation));
+        }
+        None
+    }
+
+    fn entry(&self, page: usize, allocation: Allocation) -> AtlasEntry {
+        let rect = allocation.rect();
+        let size = self.page_size as Float;
+        AtlasEntry {
+            image: ImageId::new(self.pages[page].texture),
+            rect,
+            uv: Rect::from_float(
+                rect.min().x() / size,
+                rect.min().y() / size,
+                rect.max().x() / size,
+                rect.max().y() / size,
+            ),
+            scale_factor: self.scale_factor,
+        }
+    }
+}
+
+/// Texels of `image` surrounded by copies of its edge texels.
+fn padded(image: &Image) -> Vec<u8> {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let p = PADDING as usize;
+    let padded_width = width + 2 * p;
+    let mut data = Vec::with_capacity(padded_width * (height + 2 * p) * 4);
+    for y in 0..height + 2 * p {
+        let row = y.saturating_sub(p).min(height - 1) * width * 4;
+        for x in 0..padded_width {
+            let i = row + x.saturating_sub(p).min(width - 1) * 4;
+            data.extend_from_slice(&image.data()[i..i + 4]);
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Atlas with `pages` pages, bypassing the textures.
+    fn atlas(page_size: u32, pages: usize) -> TextureAtlas<&'static str> {
+        let mut atlas = TextureAtlas::new(page_size, pages);
+        atlas.pages = (0..pages)
+            .map(|texture| Page {
+                texture,
+                allocator: AtlasAllocator::new(page_size, page_size),
+            })
+            .collect();
+        atlas
+    }
+
+    fn put(
+        atlas: &mut TextureAtlas<&'static str>,
+        key: &'static str,
+        page: usize,
+        size: (u32, u32),
+        last_used: u64,
+    ) {
+        let allocation = atlas.pages[page]
+            .allocator
+            .allocate(size.0, size.1)
+            .unwrap();
+        atlas.slots.insert(
+            key,
+            Slot {
+                page,
+                allocation,
+                last_used,
+            },
+        );
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut atlas = atlas(16, 1);
+        atlas.frame = 5;
+        for (key, last_used) in [("a", 1), ("b", 2), ("c", 3), ("d", 5)] {
+            put(&mut atlas, key, 0, (8, 8), last_used);
+        }
+        let (page, allocation) = atlas.evict(8, 8).unwrap();
+        assert_eq!((page, allocation.x, allocation.y), (0, 0, 0));
+        assert_eq!(atlas.len(), 3);
+        assert!(atlas.get(&"a").is_none());
+        assert!(atlas.get(&"b").is_some());
+    }
+
+    #[test]
+    fn keeps_entries_if_nothing_fits() {
+        let mut atlas = atlas(16, 1);
+        atlas.frame = 5;
+        for (key, last_used) in [("a", 1), ("b", 2), ("c", 3), ("d", 5)] {
+            put(&mut atlas, key, 0, (8, 8), last_used);
+        }
+        // "d" is used in the current frame, so the page never empties.
+        assert!(atlas.evict(16, 16).is_none());
+        assert_eq!(atlas.len(), 4);
+        assert!(atlas.pages[0].allocator.allocate(8, 8).is_none());
+    }
+
+    #[test]
+    fn only_evicts_from_the_page_that_fits() {
+        let mut atlas = atlas(8, 2);
+        atlas.frame = 3;
+        put(&mut atlas, "p", 0, (4, 8), 0);
+        put(&mut atlas, "q", 0, (4, 8), 3);
+        put(&mut atlas, "r", 1, (8, 8), 1);
+        let (page, _) = atlas.evict(8, 8).unwrap();
+        assert_eq!(page, 1);
+        assert!(atlas.slots.contains_key("p"));
+        assert!(!atlas.slots.contains_key("r"));
+        assert_eq!(atlas.len(), 2);
+    }
+}
@@ -0,0 +1,207 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use wgpu::util::DeviceExt;
+
+use crate::{effect, geometry::Float};
+
+const BLUR_SHADER_SRC: &str = include_str!("./blur.wgsl");
+
+/// Matches `BlurUniforms` in `blur.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlurUniforms {
+    direction: [i32; 2],
+    size: [i32; 2],
+    sigma: f32,
+    radius: i32,
+    _padding: [u32; 2],
+}
+
+/// Separable Gaussian blur of a region of a texture.
+pub(crate) struct BlurPipeline {
+    bind_group_layout: wgpu::BindGroupLayout,
+    layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    /// Pipelines by target format, created on first use.
+    pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+}
+
+impl BlurPipeline {
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("blur_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("blur_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("blur_shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(BLUR_SHADER_SRC)),
+        });
+        BlurPipeline {
+            bind_group_layout,
+            layout,
+            shader,
+            pipelines: HashMap::new(),
+        }
+    }
+
+    fn pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> &wgpu::RenderPipeline {
+        self.pipelines.entry(format).or_insert_with(|| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("blur_pipeline"),
+                layout: Some(&self.layout),
+                vertex: wgpu::VertexState {
+                    module: &self.shader,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.shader,
+                    entry_point: Some("fs_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            })
+        })
+    }
+
+    /// Blurs the `width` x `height` texels at the origin of `texture` in
+    /// place. `scratch` must be at least as large, with the same format.
+    /// Both need to be renderable and bindable. Returns the number of draw
+    /// calls.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn blur(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        scratch: &wgpu::Texture,
+        width: u32,
+        height: u32,
+        sigma: Float,
+    ) -> u64 {
+        let radius = effect::kernel_radius(sigma);
+        if radius == 0 || width == 0 || height == 0 {
+            return 0;
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let scratch_view = scratch.create_view(&wgpu::TextureViewDescriptor::default());
+        let uniforms = |direction| BlurUniforms {
+            direction,
+            size: [width as i32, height as i32],
+            sigma,
+            radius: radius as i32,
+            _padding: [0; 2],
+        };
+        let format = texture.format();
+        self.pass(
+            device,
+            encoder,
+            &view,
+            &scratch_view,
+            format,
+            uniforms([1, 0]),
+        );
+        self.pass(
+            device,
+            encoder,
+            &scratch_view,
+            &view,
+            format,
+            uniforms([0, 1]),
+        );
+        2
+    }
+
+    fn pass(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+        format: wgpu::TextureFormat,
+        uniforms: BlurUniforms,
+    ) {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Blur Buffer"),
+            contents: bytemuck::bytes_of(&uniforms),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+            ],
+            label: Some("blur_bind_group"),
+        });
+        let pipeline = self.pipeline(device, format);
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("rpp_blur_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        let [width, height] = uniforms.size;
+        pass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+        pass.set_scissor_rect(0, 0, width as u32, height as u32);
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,38 @@
+struct BlurUniforms {
+    // Step between taps, in texels.
+    direction: vec2<i32>,
+    // Size of the blurred region, in texels.
+    size: vec2<i32>,
+    sigma: f32,
+    radius: i32,
+}
+
+@group(0) @binding(0)
+var<uniform> blur: BlurUniforms;
+@group(0) @binding(1)
+var source: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // Triangle covering the viewport.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let p = vec2<i32>(position.xy);
+    if blur.radius == 0 {
+        return textureLoad(source, p, 0);
+    }
+    var sum = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var i = -blur.radius; i <= blur.radius; i++) {
+        let weight = exp(-f32(i * i) / (2.0 * blur.sigma * blur.sigma));
+        // Texels past the region repeat its edge texels.
+        let q = clamp(p + blur.direction * i, vec2<i32>(0), blur.size - 1);
+        sum += textureLoad(source, q, 0) * weight;
+        total += weight;
+    }
+    return sum / total;
+}
@@ -0,0 +1,293 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+#[cfg(feature = "rwh")]
+use super::WindowHandle;
+use super::{
+    Msaa, PresentMode, TargetColorSpace, WgpuDevice, WgpuErr, WgpuResult, is_wide_gamut_format,
+};
+use crate::geometry::Viewport;
+
+/// Called with the reason and a message when the device is lost.
+pub type DeviceLostCallback = Arc<dyn Fn(wgpu::DeviceLostReason, String) + Send + Sync>;
+
+/// Options for creating a [`WgpuDevice`].
+///
+/// A lost device can not be recovered. Once the device lost callback is
+/// called, or [`WgpuDevice::is_lost`] returns `true`, drop the device, build
+/// a new one and upload all textures again.
+#[derive(Clone)]
+pub struct WgpuDeviceBuilder {
+    backends: wgpu::Backends,
+    power_preference: wgpu::PowerPreference,
+    force_fallback_adapter: bool,
+    required_features: wgpu::Features,
+    optional_features: wgpu::Features,
+    limits: Option<wgpu::Limits>,
+    color_format: Option<wgpu::TextureFormat>,
+    wide_gamut: bool,
+    color_space: TargetColorSpace,
+    present_mode: PresentMode,
+    msaa: Msaa,
+    device_lost_callback: Option<DeviceLostCallback>,
+    pipeline_cache_data: Option<Vec<u8>>,
+    frame_stats: bool,
+}
+
+impl Default for WgpuDeviceBuilder {
+    fn default() -> Self {
+        WgpuDeviceBuilder {
+            backends: wgpu::Backends::all(),
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            required_features: wgpu::Features::empty(),
+            optional_features: wgpu::Features::empty(),
+            limits: None,
+            color_format: None,
+            wide_gamut: false,
+            color_space: TargetColorSpace::default(),
+            present_mode: PresentMode::default(),
+            msaa: Msaa::default(),
+            device_lost_callback: None,
+            pipeline_cache_data: None,
+            frame_stats: false,
+        }
+    }
+}
+
+impl WgpuDeviceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Backends to choose the adapter from.
+    pub fn backends(mut self, backends: wgpu::Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    /// Forces the fallback (software) adapter.
+    pub fn force_fallback_adapter(mut self, force: bool) -> Self {
+        self.force_fallback_adapter = force;
+        self
+    }
+
+    /// Features the device must support. Building fails without them.
+    pub fn required_features(mut self, features: wgpu::Features) -> Self {
+        self.required_features = features;
+        self
+    }
+
+    /// Features enabled only if the adapter supports them. Check
+    /// [`WgpuDevice::features`] for the enabled ones.
+    pub fn optional_features(mut self, features: wgpu::Features) -> Self {
+        self.optional_features = features;
+        self
+    }
+
+    /// Limits the device must support. Defaults to the WebGL2 limits with
+    /// the adapter's texture resolution.
+    pub fn limits(mut self, limits: wgpu::Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    pub fn color_format(mut self, format: wgpu::TextureFormat) -> Self {
+        self.color_format = Some(format);
+        self
+    }
+
+    /// Prefers a surface format keeping colors outside the sRGB gamut as
+    /// extended linear sRGB, such as the ones of
+    /// [`Color::display_p3`](crate::Color::display_p3), when no color format
+    /// is set. Falls back to the default format if the surface has none.
+    pub fn wide_gamut(mut self, wide_gamut: bool) -> Self {
+        self.wide_gamut = wide_gamut;
+        self
+    }
+
+    /// Color space the targets are displayed in, sRGB by default. See
+    /// [`TargetColorSpace`].
+    pub fn color_space(mut self, color_space: TargetColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
+    pub fn present_mode(mut self, mode: PresentMode) -> Self {
+        self.present_mode = mode;
+        self
+    }
+
+    /// Multisample anti-aliasing of the surface and of
+    /// [`WgpuDevice::render_to_texture`]. Falls back to a lower mode if the
+    /// adapter does not support it, see [`WgpuDevice::msaa`].
+    pub fn msaa(mut self, msaa: Msaa) -> Self {
+        self.msaa = msaa;
+        self
+    }
+
+    pub fn on_device_lost(
+        mut self,
+        callback: impl Fn(wgpu::DeviceLostReason, String) + Send + Sync + 'static,
+    ) -> Self {
+        self.device_lost_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Fills the pipeline cache with data saved by
+    /// [`WgpuDevice::pipeline_cache_data`] on a previous run. Ignored if
+    /// the adapter has no pipeline cache, or a different
+    /// [`WgpuDevice::pipeline_cache_key`].
+    ///
+    /// # Safety
+    ///
+    /// `data` must have been returned by [`WgpuDevice::pipeline_cache_data`],
+    /// see [`wgpu::Device::create_pipeline_cache`].
+    pub unsafe fn pipeline_cache_data(mut self, data: Vec<u8>) -> Self {
+        self.pipeline_cache_data = Some(data);
+        self
+    }
+
+    /// Collects [`FrameStats`](super::FrameStats) of each frame, see
+    /// [`WgpuDevice::frame_stats`]. GPU times need timestamp queries, which
+    /// are enabled if the adapter supports them.
+    pub fn frame_stats(mut self, frame_stats: bool) -> Self {
+        self.frame_stats = frame_stats;
+        self
+    }
+
+    fn create_instance(&self) -> wgpu::Instance {
+        wgpu::Instance::new(&wgpu::InstanceDescriptor {
+            backends: self.backends,
+            ..Default::default()
+        })
+    }
+
+    async fn request_adapter(
+        &self,
+        instance: &wgpu::Instance,
+        surface: Option<&wgpu::Surface<'_>>,
+    ) -> WgpuResult<wgpu::Adapter> {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: self.power_preference,
+                force_fallback_adapter: self.force_fallback_adapter,
+                compatible_surface: surface,
+            })
+            .await?;
+        Ok(adapter)
+    }
+
+    async fn request_device(
+        &self,
+        adapter: &wgpu::Adapter,
+    ) -> WgpuResult<(wgpu::Device, wgpu::Queue, Arc<AtomicBool>)> {
+        let required_limits = self.limits.clone().unwrap_or_else(|| {
+            wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits())
+        });
+        // Pipelines are compiled through a pipeline cache when possible.
+        let mut optional_features = self.optional_features | wgpu::Features::PIPELINE_CACHE;
+        if self.frame_stats {
+            optional_features |= wgpu::Features::TIMESTAMP_QUERY;
+        }
+        // Sample counts other than 1 and 4 need the adapter's own format
+        // features.
+        if self.msaa != Msaa::Off {
+            optional_features |= wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+        }
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: None,
+                required_features: self.required_features
+                    | (optional_features & adapter.features()),
+                required_limits,
+                memory_hints: wgpu::MemoryHints::MemoryUsage,
+                trace: wgpu::Trace::Off,
+                ..Default::default()
+            })
+            .await?;
+
+        let lost = Arc::new(AtomicBool::new(false));
+        let lost_flag = lost.clone();
+        let callback = self.device_lost_callback.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            lost_flag.store(true, Ordering::Release);
+            if let Some(callback) = &callback {
+                callback(reason, message);
+            }
+        });
+
+        Ok((device, queue, lost))
+    }
+
+    /// Creates a device rendering to `window`.
+    #[cfg(feature = "rwh")]
+    pub async fn build_with_window<'a, T: WindowHandle + 'a>(
+        self,
+        window: T,
+        viewport: &Viewport,
+    ) -> WgpuResult<WgpuDevice<'a>> {
+        let instance = self.create_instance();
+        let surface_target = wgpu::SurfaceTarget::Window(Box::new(window));
+        let surface = instance.create_surface(surface_target)?;
+        let adapter = self.request_adapter(&instance, Some(&surface)).await?;
+        let color_format = match self.color_format {
+            Some(format) => format,
+            None => {
+                let formats = surface.get_capabilities(&adapter).formats;
+                formats
+                    .iter()
+                    .find(|&&format| self.wide_gamut && is_wide_gamut_format(format))
+                    .or(formats.first())
+                    .copied()
+                    .ok_or(WgpuErr::UnsupportedSurfaceConfiguration)?
+            }
+        };
+        let (device, queue, lost) = self.request_device(&adapter).await?;
+
+        WgpuDevice::init(
+            &adapter,
+            device,
+            queue,
+            lost,
+            Some(surface),
+            viewport,
+            color_format,
+            self.present_mode,
+            self.msaa,
+            self.color_space,
+            self.pipeline_cache_data.as_deref(),
+            self.frame_stats,
+        )
+    }
+
+    /// Creates a device without a surface.
+    pub async fn build<'a>(self, viewport: &Viewport) -> WgpuResult<WgpuDevice<'a>> {
+        let instance = self.create_instance();
+        let adapter = self.request_adapter(&instance, None).await?;
+        let (device, queue, lost) = self.request_device(&adapter).await?;
+
+        WgpuDevice::init(
+            &adapter,
+            device,
+            queue,
+            lost,
+            None,
+            viewport,
+            self.color_format
+                .unwrap_or(wgpu::TextureFormat::Rgba8UnormSrgb),
+            self.present_mode,
+            self.msaa,
+            self.color_space,
+            self.pipeline_cache_data.as_deref(),
+            self.frame_stats,
+        )
+    }
+}
@@ -0,0 +1,104 @@
+struct CompositeUniforms {
+    // Texel of the target copied to the origin of the backdrop texture.
+    backdrop_origin: vec2<i32>,
+    opacity: f32,
+    // Index of the blend mode, in `BlendMode` order.
+    mode: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> composite: CompositeUniforms;
+@group(1) @binding(0)
+var layer: texture_2d<f32>;
+@group(1) @binding(1)
+var backdrop: texture_2d<f32>;
+
+// Blend modes
+const MODE_NORMAL: u32 = 0u;
+const MODE_MULTIPLY: u32 = 1u;
+const MODE_SCREEN: u32 = 2u;
+const MODE_OVERLAY: u32 = 3u;
+const MODE_DARKEN: u32 = 4u;
+const MODE_LIGHTEN: u32 = 5u;
+const MODE_COLOR_DODGE: u32 = 6u;
+const MODE_COLOR_BURN: u32 = 7u;
+const MODE_HARD_LIGHT: u32 = 8u;
+const MODE_SOFT_LIGHT: u32 = 9u;
+const MODE_DIFFERENCE: u32 = 10u;
+const MODE_EXCLUSION: u32 = 11u;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // Triangle covering the viewport.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn hard_light(cb: vec3<f32>, cs: vec3<f32>) -> vec3<f32> {
+    let multiply = cb * 2.0 * cs;
+    let s = 2.0 * cs - 1.0;
+    let screen = cb + s - cb * s;
+    return select(screen, multiply, cs <= vec3<f32>(0.5));
+}
+
+fn soft_light(cb: vec3<f32>, cs: vec3<f32>) -> vec3<f32> {
+    let dark = cb - (1.0 - 2.0 * cs) * cb * (1.0 - cb);
+    let d = select(sqrt(cb), ((16.0 * cb - 12.0) * cb + 4.0) * cb, cb <= vec3<f32>(0.25));
+    let light = cb + (2.0 * cs - 1.0) * (d - cb);
+    return select(light, dark, cs <= vec3<f32>(0.5));
+}
+
+fn color_dodge(cb: vec3<f32>, cs: vec3<f32>) -> vec3<f32> {
+    let dodge = min(cb / max(1.0 - cs, vec3<f32>(1e-6)), vec3<f32>(1.0));
+    return select(select(dodge, vec3<f32>(1.0), cs >= vec3<f32>(1.0)), vec3<f32>(0.0), cb <= vec3<f32>(0.0));
+}
+
+fn color_burn(cb: vec3<f32>, cs: vec3<f32>) -> vec3<f32> {
+    let burn = 1.0 - min((1.0 - cb) / max(cs, vec3<f32>(1e-6)), vec3<f32>(1.0));
+    return select(select(burn, vec3<f32>(0.0), cs <= vec3<f32>(0.0)), vec3<f32>(1.0), cb >= vec3<f32>(1.0));
+}
+
+// Blends straight colors `cb` below and `cs` above.
+fn blend(cb: vec3<f32>, cs: vec3<f32>) -> vec3<f32> {
+    switch composite.mode {
+        case MODE_MULTIPLY: { return cb * cs; }
+        case MODE_SCREEN: { return cb + cs - cb * cs; }
+        case MODE_OVERLAY: { return hard_light(cs, cb); }
+        case MODE_DARKEN: { return min(cb, cs); }
+        case MODE_LIGHTEN: { return max(cb, cs); }
+        case MODE_COLOR_DODGE: { return color_dodge(cb, cs); }
+        case MODE_COLOR_BURN: { return color_burn(cb, cs); }
+        case MODE_HARD_LIGHT: { return hard_light(cb, cs); }
+        case MODE_SOFT_LIGHT: { return soft_light(cb, cs); }
+        case MODE_DIFFERENCE: { return abs(cb - cs); }
+        case MODE_EXCLUSION: { return cb + cs - 2.0 * cb * cs; }
+        default: { return cs; }
+    }
+}
+
+// Normal layers are blended by the pipeline.
+@fragment
+fn fs_normal(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return textureLoad(layer, vec2<i32>(position.xy), 0) * composite.opacity;
+}
+
+// Other blend modes read a copy of the target and replace it.
+@fragment
+fn fs_blend(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let p = vec2<i32>(position.xy);
+    let src = textureLoad(layer, p, 0) * composite.opacity;
+    let dst = textureLoad(backdrop, p - composite.backdrop_origin, 0);
+    var blended = vec3<f32>(0.0);
+    if src.a > 0.0 && dst.a > 0.0 {
+        blended = src.a * dst.a * blend(dst.rgb / dst.a, src.rgb / src.a);
+    }
+    let rgb = src.rgb * (1.0 - dst.a) + dst.rgb * (1.0 - src.a) + blended;
+    return vec4<f32>(rgb, src.a + dst.a * (1.0 - src.a));
+}
+
+// Replaces the target with transparent texels, for regions redrawn from
+// scratch.
+@fragment
+fn fs_clear() -> @location(0) vec4<f32> {
+    return vec4<f32>(0.0);
+}
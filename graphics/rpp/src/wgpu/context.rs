@@ -0,0 +1,441 @@
+use crate::{
+    color::Color,
+    context::{Context, TransformStack},
+    effect::BoxShadow,
+    geometry::{Float, Mat3, Rect, RoundedRect},
+    image::{self, Glyph, ImageId, ImageOptions},
+    paint::{BlendMode, Border, Paint},
+    path::{FillRule, Path, Polyline, StrokeStyle},
+    tessellator::{self, Triangles},
+};
+
+use super::{ClipOp, LayerOp, Mesh, MeshClip, PrimitiveKind, Shape, WgpuVertex};
+
+/// [`Context`] recording meshes for [`super::WgpuDevice::render`].
+///
+/// Meshes are in logical units. Curves are flattened, hairlines stroked and
+/// clip rects snapped for the pixels of the scale factor, which should match
+/// the one of the [`Viewport`] drawn into.
+///
+/// [`Viewport`]: crate::geometry::Viewport
+#[derive(Clone, Debug)]
+pub struct WgpuContext {
+    meshes: Vec<Mesh>,
+    clips: Vec<ClipEntry>,
+    transforms: TransformStack,
+    /// Number of open layers.
+    layers: usize,
+    /// Pixels per logical unit.
+    scale_factor: Float,
+}
+
+impl Default for WgpuContext {
+    fn default() -> Self {
+        WgpuContext {
+            meshes: Vec::new(),
+            clips: Vec::new(),
+            transforms: TransformStack::default(),
+            layers: 0,
+            scale_factor: 1.0,
+        }
+    }
+}
+
+/// Entry of the clip stack.
+#[derive(Clone, Debug)]
+struct ClipEntry {
+    clip: MeshClip,
+    /// Mesh added to the stencil by the clip, removed again when popped.
+    mask: Option<Mesh>,
+}
+
+impl WgpuContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a context drawing for `scale_factor` pixels per logical unit.
+    pub fn with_scale_factor(scale_factor: Float) -> Self {
+        let mut context = Self::default();
+        context.set_scale_factor(scale_factor);
+        context
+    }
+
+    /// Pixels per logical unit.
+    pub fn scale_factor(&self) -> Float {
+        self.scale_factor
+    }
+
+    /// Sets the pixels per logical unit meshes are recorded for. Meshes
+    /// already recorded are kept as they are. Non-positive scale factors
+    /// are replaced by 1.
+    pub fn set_scale_factor(&mut self, scale_factor: Float) {
+        self.scale_factor = if scale_factor > 0.0 {
+            scale_factor
+        } else {
+            1.0
+        };
+    }
+
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.meshes
+    }
+
+    /// Appends `meshes` recorded by another context without clips nor
+    /// layers, drawn under the current transform and clip. Lets tessellated
+    /// geometry be cached and drawn again.
+    pub fn append_meshes(&mut self, meshes: &[Mesh]) {
+        let (clip, transform) = (self.clip(), self.transforms.current());
+        self.meshes.extend(meshes.iter().map(|mesh| Mesh {
+            clip,
+            transform: transform.multiply(&mesh.transform),
+            ..mesh.clone()
+        }));
+    }
+
+    pub fn clear(&mut self) {
+        self.meshes.clear();
+        self.clips.clear();
+        self.transforms.clear();
+        self.layers = 0;
+    }
+
+    /// Maps user space to pixels.
+    fn device_transform(&self) -> Mat3 {
+        Mat3::scale(self.scale_factor, self.scale_factor).multiply(&self.transforms.current())
+    }
+
+    /// Margin around analytic shapes leaving room for anti-aliasing: one
+    /// pixel, in user space.
+    fn aa_margin(&self) -> Float {
+        self.device_transform()
+            .invert()
+            .map_or(1.0, |inverse| inverse.max_scale())
+    }
+
+    /// Clip applied to meshes drawn now.
+    fn clip(&self) -> MeshClip {
+        self.clips.last().map(|c| c.clip).unwrap_or_default()
+    }
+
+    /// Pushes `mesh` as a clip mask, covering `bounds` in user space.
+    fn push_clip_mask(&mut self, mut mesh: Mesh, bounds: Rect) {
+        let parent = self.clip();
+        let transform = self.transforms.current();
+        let bounds = transform.transform_rect(bounds).outset(1.0);
+        mesh.clip = MeshClip {
+            op: ClipOp::Push,
+            ..parent
+        };
+        mesh.transform = transform;
+        self.meshes.push(mesh.clone());
+        self.clips.push(ClipEntry {
+            clip: MeshClip {
+                scissor: Some(intersect_scissor(parent.scissor, bounds)),
+                depth: parent.depth + 1,
+                op: ClipOp::Draw,
+            },
+            mask: Some(mesh),
+        });
+    }
+
+    fn push_mesh(&mut self, mut mesh: Mesh) {
+        mesh.clip = self.clip();
+        mesh.transform = self.transforms.current();
+        self.meshes.push(mesh);
+    }
+
+    fn push_triangles(&mut self, triangles: Triangles, paint: &Paint) {
+        if triangles.indices.is_empty() {
+            return;
+        }
+        self.push_mesh(triangles_mesh(triangles, Some(paint.clone())));
+    }
+
+    /// Pushes a single quad covering `rect`, masked by `shape`.
+    fn push_quad(
+        &mut self,
+        rect: Rect,
+        paint: Option<Paint>,
+        shape: Shape,
+        backdrop_blur: Option<Float>,
+    ) {
+        self.push_mesh(Mesh {
+            backdrop_blur,
+            ..quad_mesh(rect, paint, shape)
+        });
+    }
+
+    fn fill_polylines(&mut self, polylines: &[Polyline], fill_rule: FillRule, paint: &Paint) {
+        self.push_triangles(tessellator::fill(polylines, fill_rule), paint);
+    }
+}
+
+impl Context for WgpuContext {
+    fn fill_rect(&mut self, rect: Rect, paint: &Paint) {
+        self.fill_path(&Path::from_rect(rect), paint);
+    }
+
+    fn fill_rounded_rect(&mut self, rrect: RoundedRect, paint: &Paint, border: Option<Border>) {
+        self.push_quad(
+            rrect.rect().outset(self.aa_margin()),
+            Some(paint.clone()),
+            Shape::RoundedRect { rrect, border },
+            None,
+        );
+    }
+
+    fn draw_box_shadow(&mut self, rrect: RoundedRect, shadow: BoxShadow) {
+        self.push_quad(
+            shadow.bounds(rrect),
+            Some(Paint::Solid(shadow.color)),
+            Shape::BoxShadow { rrect, shadow },
+            None,
+        );
+    }
+
+    fn backdrop_blur(&mut self, rrect: RoundedRect, blur_radius: Float) {
+        let shape = Shape::RoundedRect {
+            rrect,
+            border: None,
+        };
+        let quad = rrect.rect().outset(self.aa_margin());
+        self.push_quad(quad, None, shape, Some(blur_radius));
+    }
+
+    fn fill_path(&mut self, path: &Path, paint: &Paint) {
+        let polylines = path.flatten_for(&self.device_transform());
+        self.fill_polylines(&polylines, path.fill_rule(), paint);
+    }
+
+    fn stroke_path(&mut self, path: &Path, style: &StrokeStyle, paint: &Paint) {
+        let transform = self.device_transform();
+        let polylines = path.flatten_for(&transform);
+        let outlines = tessellator::stroke_style(&polylines, style, &transform);
+        self.fill_polylines(&outlines, FillRule::NonZero, paint);
+    }
+
+    fn draw_image(
+        &mut self,
+        image: ImageId,
+        src_rect: Rect,
+        dst_rect: Rect,
+        options: &ImageOptions,
+    ) {
+        let slices = image::slices(src_rect, dst_rect, options.nine_slice);
+        if slices.is_empty() {
+            return;
+        }
+        let mut vertices = Vec::with_capacity(slices.len() * 4);
+        let mut indices = Vec::with_capacity(slices.len() * 6);
+        for (src, dst) in slices {
+            let base = vertices.len() as u32;
+            let (s0, s1, d0, d1) = (src.min(), src.max(), dst.min(), dst.max());
+            let vertex = |d: [Float; 2], s: [Float; 2]| WgpuVertex::new(d, s, Color::WHITE);
+            vertices.extend([
+                vertex([d0.x(), d0.y()], [s0.x(), s0.y()]),
+                vertex([d1.x(), d0.y()], [s1.x(), s0.y()]),
+                vertex([d1.x(), d1.y()], [s1.x(), s1.y()]),
+                vertex([d0.x(), d1.y()], [s0.x(), s1.y()]),
+            ]);
+            indices.extend([0, 1, 2, 0, 2, 3].map(|i| base + i));
+        }
+        // Images drawn in a row from the same texture, such as atlas
+        // entries, share a mesh and a draw call.
+        let (clip, transform) = (self.clip(), self.transforms.current());
+        if let Some(last) = self.meshes.last_mut()
+            && last.texel_coords
+            && last.texture == Some(image.value())
+            && last.sampling == options.sampling
+            && last.clip == clip
+            && last.transform == transform
+        {
+            let base = last.vertices.len() as u32;
+            last.vertices.extend(vertices);
+            last.indices.extend(indices.into_iter().map(|i| i + base));
+            return;
+        }
+        self.push_mesh(Mesh {
+            vertices,
+            indices,
+            texture: Some(image.value()),
+            sampling: options.sampling,
+            texel_coords: true,
+            ..Mesh::default()
+        });
+    }
+
+    fn draw_glyphs(&mut self, glyphs: &[Glyph], paint: &Paint) {
+        // Glyphs in a row from the same image share a mesh.
+        for run in glyphs.chunk_by(|a, b| a.image == b.image) {
+            let mut vertices = Vec::with_capacity(run.len() * 4);
+            let mut indices = Vec::with_capacity(run.len() * 6);
+            for glyph in run {
+                let base = vertices.len() as u32;
+                let (s0, s1) = (glyph.src_rect.min(), glyph.src_rect.max());
+                let (d0, d1) = (glyph.dst_rect.min(), glyph.dst_rect.max());
+                let vertex = |d: [Float; 2], s: [Float; 2]| WgpuVertex::new(d, s, Color::WHITE);
+                vertices.extend([
+                    vertex([d0.x(), d0.y()], [s0.x(), s0.y()]),
+                    vertex([d1.x(), d0.y()], [s1.x(), s0.y()]),
+                    vertex([d1.x(), d1.y()], [s1.x(), s1.y()]),
+                    vertex([d0.x(), d1.y()], [s0.x(), s1.y()]),
+                ]);
+                indices.extend([0, 1, 2, 0, 2, 3].map(|i| base + i));
+            }
+            self.push_mesh(Mesh {
+                vertices,
+                indices,
+                texture: Some(run[0].image.value()),
+                texel_coords: true,
+                paint: Some(paint.clone()),
+                shader: Some(PrimitiveKind::Text.shader()),
+                ..Mesh::default()
+            });
+        }
+    }
+
+    fn push_clip_rect(&mut self, rect: Rect) {
+        let transform = self.transforms.current();
+        if !transform.is_axis_aligned() {
+            self.push_clip_path(&Path::from_rect(rect));
+            return;
+        }
+        let parent = self.clip();
+        let rect = transform.transform_rect(rect);
+        let (min, max) = (rect.min(), rect.max());
+        let scale = self.scale_factor;
+        let snap = |v: Float| (v * scale).round() / scale;
+        let snapped = Rect::from_float(snap(min.x()), snap(min.y()), snap(max.x()), snap(max.y()));
+        self.clips.push(ClipEntry {
+            clip: MeshClip {
+                scissor: Some(intersect_scissor(parent.scissor, snapped)),
+                ..parent
+            },
+            mask: None,
+        });
+    }
+
+    fn push_clip_rounded_rect(&mut self, rrect: RoundedRect) {
+        let quad = rrect.rect().outset(self.aa_margin());
+        let shape = Shape::RoundedRect {
+            rrect,
+            border: None,
+        };
+        self.push_clip_mask(quad_mesh(quad, None, shape), rrect.rect());
+    }
+
+    fn push_clip_path(&mut self, path: &Path) {
+        let polylines = path.flatten_for(&self.device_transform());
+        let triangles = tessellator::fill(&polylines, path.fill_rule());
+        let mut bounds = Rect::from_float(0.0, 0.0, 0.0, 0.0);
+        if let Some(first) = triangles.vertices.first() {
+            let (mut min, mut max) = (first.p, first.p);
+            for v in &triangles.vertices {
+                for axis in 0..2 {
+                    min[axis] = min[axis].min(v.p[axis]);
+                    max[axis] = max[axis].max(v.p[axis]);
+                }
+            }
+            bounds = Rect::from_float(min[0], min[1], max[0], max[1]);
+        }
+        self.push_clip_mask(triangles_mesh(triangles, None), bounds);
+    }
+
+    fn pop_clip(&mut self) {
+        let Some(entry) = self.clips.pop() else {
+            return;
+        };
+        if let Some(mut mask) = entry.mask {
+            // Decrements the pixels incremented by the push, which are at the
+            // depth of the clip unless nothing could be drawn.
+            mask.clip.depth = entry.clip.depth;
+            mask.clip.op = ClipOp::Pop;
+            self.meshes.push(mask);
+        }
+    }
+
+    fn push_blurred_layer(&mut self, opacity: Float, blend_mode: BlendMode, blur_radius: Float) {
+        self.layers += 1;
+        self.push_mesh(Mesh {
+            layer: Some(LayerOp::Begin {
+                opacity,
+                blend_mode,
+                blur_radius: blur_radius * self.transforms.current().max_scale(),
+            }),
+            ..Mesh::default()
+        });
+    }
+
+    fn pop_layer(&mut self) {
+        if self.layers == 0 {
+            return;
+        }
+        self.layers -= 1;
+        self.push_mesh(Mesh {
+            layer: Some(LayerOp::End),
+            ..Mesh::default()
+        });
+    }
+
+    fn save(&mut self) {
+        self.transforms.save();
+    }
+
+    fn restore(&mut self) {
+        self.transforms.restore();
+    }
+
+    fn transform(&self) -> Mat3 {
+        self.transforms.current()
+    }
+
+    fn set_transform(&mut self, transform: Mat3) {
+        self.transforms.set(transform);
+    }
+}
+
+fn triangles_mesh(triangles: Triangles, paint: Option<Paint>) -> Mesh {
+    let vertices = triangles
+        .vertices
+        .iter()
+        .map(|p| WgpuVertex::new(p.p, [0.0, 0.0], Color::WHITE))
+        .collect();
+    Mesh {
+        vertices,
+        indices: triangles.indices,
+        texture: None,
+        paint,
+        shape: None,
+        ..Mesh::default()
+    }
+}
+
+/// Single quad covering `rect`, masked by `shape`.
+fn quad_mesh(rect: Rect, paint: Option<Paint>, shape: Shape) -> Mesh {
+    let (min, max) = (rect.min(), rect.max());
+    let vertex = |x, y| WgpuVertex::new([x, y], [0.0, 0.0], Color::WHITE);
+    Mesh {
+        vertices: vec![
+            vertex(min.x(), min.y()),
+            vertex(max.x(), min.y()),
+            vertex(max.x(), max.y()),
+            vertex(min.x(), max.y()),
+        ],
+        indices: vec![0, 1, 2, 0, 2, 3],
+        texture: None,
+        paint,
+        shape: Some(shape),
+        ..Mesh::default()
+    }
+}
+
+/// `scissor` restricted to `rect`, empty if they do not overlap.
+fn intersect_scissor(scissor: Option<Rect>, rect: Rect) -> Rect {
+    match scissor {
+        None => rect,
+        Some(scissor) => scissor
+            .intersection(&rect)
+            .unwrap_or(Rect::from_float(0.0, 0.0, 0.0, 0.0)),
+    }
+}
@@ -0,0 +1,8 @@
+// Entry point of custom shader paints, appended to `shader.wgsl` and the
+// `shade` function of the paint.
+
+@fragment
+fn fs_custom(in: VertexOutput) -> @location(0) vec4<f32> {
+    let input = ShaderInput(in.local, uniforms.time, shader_params());
+    return draw_mesh(in, premultiply(target_color(shade(input))));
+}
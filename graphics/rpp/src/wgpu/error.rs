@@ -0,0 +1,106 @@
+use std::{error, fmt};
+
+use super::TextureId;
+use crate::shader::ShaderId;
+
+#[derive(Clone, Debug)]
+pub enum WgpuErr {
+    RequestAdapterError(wgpu::RequestAdapterError),
+    CreateSurfaceError(wgpu::CreateSurfaceError),
+    CreateDeviceError(wgpu::RequestDeviceError),
+    /// The adapter can not present to the surface, or does not support the
+    /// requested color format.
+    UnsupportedSurfaceConfiguration,
+    SurfaceError(wgpu::SurfaceError),
+    NoSurface,
+    TextureNotFound(TextureId),
+    /// No shader is registered with this id.
+    ShaderNotFound(ShaderId),
+    /// A custom shader failed to compile, with the reason.
+    InvalidShader(String),
+    InvalidTextureSize,
+    InvalidTextureRegion,
+    InvalidTextureData,
+    /// Backdrop blurs need a render target that can be copied from.
+    BackdropNotReadable,
+    /// Loading the contents of a render target drawn into with MSAA needs it
+    /// to be sampled.
+    TargetNotLoadable,
+    /// Every page of a texture atlas is full of entries used in the current
+    /// frame.
+    AtlasFull,
+    /// Passes of a render graph depend on each other.
+    RenderGraphCycle,
+}
+
+pub type WgpuResult<T> = Result<T, WgpuErr>;
+
+impl fmt::Display for WgpuErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WgpuErr::RequestAdapterError(err) => write!(f, "failed to request adapter: {err}"),
+            WgpuErr::CreateSurfaceError(err) => write!(f, "failed to create surface: {err}"),
+            WgpuErr::CreateDeviceError(err) => write!(f, "failed to request device: {err}"),
+            WgpuErr::UnsupportedSurfaceConfiguration => {
+                write!(f, "surface configuration is not supported by the adapter")
+            }
+            WgpuErr::SurfaceError(err) => write!(f, "failed to acquire surface texture: {err}"),
+            WgpuErr::NoSurface => write!(f, "device has no surface"),
+            WgpuErr::TextureNotFound(id) => write!(f, "texture {id} does not exist"),
+            WgpuErr::ShaderNotFound(id) => write!(f, "shader {} does not exist", id.value()),
+            WgpuErr::InvalidShader(message) => write!(f, "invalid shader: {message}"),
+            WgpuErr::InvalidTextureSize => write!(f, "texture size is zero or exceeds limits"),
+            WgpuErr::InvalidTextureRegion => write!(f, "region exceeds texture bounds"),
+            WgpuErr::InvalidTextureData => {
+                write!(f, "texel data length does not match texture size")
+            }
+            WgpuErr::BackdropNotReadable => {
+                write!(
+                    f,
+                    "render target of a backdrop blur or blended layer can not be copied from"
+                )
+            }
+            WgpuErr::TargetNotLoadable => {
+                write!(f, "multisampled render target can not be loaded")
+            }
+            WgpuErr::AtlasFull => write!(f, "texture atlas has no room left"),
+            WgpuErr::RenderGraphCycle => write!(f, "render graph passes form a cycle"),
+        }
+    }
+}
+
+impl error::Error for WgpuErr {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            WgpuErr::RequestAdapterError(err) => Some(err),
+            WgpuErr::CreateSurfaceError(err) => Some(err),
+            WgpuErr::CreateDeviceError(err) => Some(err),
+            WgpuErr::SurfaceError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<wgpu::RequestAdapterError> for WgpuErr {
+    fn from(v: wgpu::RequestAdapterError) -> Self {
+        WgpuErr::RequestAdapterError(v)
+    }
+}
+
+impl From<wgpu::CreateSurfaceError> for WgpuErr {
+    fn from(v: wgpu::CreateSurfaceError) -> Self {
+        WgpuErr::CreateSurfaceError(v)
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for WgpuErr {
+    fn from(v: wgpu::RequestDeviceError) -> Self {
+        WgpuErr::CreateDeviceError(v)
+    }
+}
+
+impl From<wgpu::SurfaceError> for WgpuErr {
+    fn from(v: wgpu::SurfaceError) -> Self {
+        WgpuErr::SurfaceError(v)
+    }
+}
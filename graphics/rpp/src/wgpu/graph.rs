@@ -0,0 +1,588 @@
+use super::{Mesh, RenderPipeline, WgpuErr, WgpuResult};
+
+/// Frames a pooled texture is kept unused before it is destroyed.
+const MAX_IDLE_FRAMES: u64 = 3;
+
+/// Texture read or written by the passes of a [`RenderGraph`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GraphTexture(usize);
+
+/// Texture only used while executing a [`RenderGraph`], allocated from a
+/// [`TexturePool`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TransientDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub usage: wgpu::TextureUsages,
+}
+
+impl TransientDesc {
+    /// Describes a single sampled texture that can be drawn into, sampled
+    /// and copied.
+    pub fn new(width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        TransientDesc {
+            width,
+            height,
+            format,
+            sample_count: 1,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+        }
+    }
+
+    pub fn sample_count(mut self, sample_count: u32) -> Self {
+        self.sample_count = sample_count;
+        self
+    }
+
+    pub fn usage(mut self, usage: wgpu::TextureUsages) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    fn create(&self, device: &wgpu::Device) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("rpp_transient"),
+            size: wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: self.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: self.usage,
+            view_formats: &[],
+        })
+    }
+}
+
+struct PooledTexture {
+    desc: TransientDesc,
+    texture: wgpu::Texture,
+    /// Frame the texture was last released in.
+    last_used: u64,
+}
+
+/// Textures of transient [`RenderGraph`] resources, reused across passes
+/// and frames.
+///
+/// Textures unused for a few frames are destroyed.
+#[derive(Default)]
+pub struct TexturePool {
+    free: Vec<PooledTexture>,
+    frame: u64,
+}
+
+impl TexturePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of textures available for reuse.
+    pub fn len(&self) -> usize {
+        self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free.is_empty()
+    }
+
+    /// Returns a texture matching `desc`, reusing a free one if possible.
+    pub fn acquire(&mut self, device: &wgpu::Device, desc: &TransientDesc) -> wgpu::Texture {
+        match self.free.iter().position(|t| t.desc == *desc) {
+            Some(i) => self.free.swap_remove(i).texture,
+            None => desc.create(device),
+        }
+    }
+
+    /// Makes `texture`, acquired for `desc`, available for reuse. Its
+    /// contents are undefined once acquired again.
+    pub fn release(&mut self, desc: &TransientDesc, texture: wgpu::Texture) {
+        self.free.push(PooledTexture {
+            desc: *desc,
+            texture,
+            last_used: self.frame,
+        });
+    }
+
+    /// Starts a new frame, destroying the textures unused for the last
+    /// frames.
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+        let frame = self.frame;
+        self.free.retain(|t| {
+            let keep = frame - t.last_used <= MAX_IDLE_FRAMES;
+            if !keep {
+                t.texture.destroy();
+            }
+            keep
+        });
+    }
+
+    /// Destroys every free texture.
+    pub fn clear(&mut self) {
+        for t in self.free.drain(..) {
+            t.texture.destroy();
+        }
+    }
+}
+
+enum Resource {
+    Imported(wgpu::Texture),
+    Transient(TransientDesc),
+}
+
+/// What a pass records with.
+pub struct PassContext<'a> {
+    pub device: &'a wgpu::Device,
+    pub queue: &'a wgpu::Queue,
+    pub encoder: &'a mut wgpu::CommandEncoder,
+    pub pipeline: &'a mut RenderPipeline,
+    pub textures: GraphTextures<'a>,
+}
+
+/// Textures of a [`RenderGraph`] while a pass is recorded.
+#[derive(Clone, Copy)]
+pub struct GraphTextures<'a> {
+    textures: &'a [Option<wgpu::Texture>],
+}
+
+impl<'a> GraphTextures<'a> {
+    /// Texture of `id`. `None` unless it is read or written by the pass.
+    pub fn get(&self, id: GraphTexture) -> Option<&'a wgpu::Texture> {
+        self.textures.get(id.0)?.as_ref()
+    }
+}
+
+type RecordFn<'a> = Box<dyn FnOnce(&mut PassContext<'_>) -> WgpuResult<()> + 'a>;
+
+struct Pass<'a> {
+    label: String,
+    reads: Vec<GraphTexture>,
+    writes: Vec<GraphTexture>,
+    record: RecordFn<'a>,
+}
+
+/// Passes of a frame, recorded into a single command encoder by
+/// [`RenderPipeline::encode_graph`].
+///
+/// Passes can be added in any order: a pass runs after the passes writing
+/// the textures it reads. Passes writing the same texture run in the order
+/// they were added, and a pass both reading and writing a texture reads
+/// what the passes added before it wrote. Passes not contributing to an
+/// imported texture are skipped.
+///
+/// Transient textures are allocated from the [`TexturePool`] of the
+/// pipeline before their first pass, and released after their last one,
+/// so that later passes of the frame can reuse them.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    resources: Vec<Resource>,
+    passes: Vec<Pass<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        RenderGraph {
+            resources: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    /// Number of passes added.
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+
+    /// Adds a texture living outside of the graph, such as the frame drawn
+    /// into.
+    pub fn import(&mut self, texture: &wgpu::Texture) -> GraphTexture {
+        self.resources.push(Resource::Imported(texture.clone()));
+        GraphTexture(self.resources.len() - 1)
+    }
+
+    /// Declares a texture only used by the passes of the graph.
+    pub fn transient(&mut self, desc: TransientDesc) -> GraphTexture {
+        self.resources.push(Resource::Transient(desc));
+        GraphTexture(self.resources.len() - 1)
+    }
+
+    /// Adds a pass reading and writing textures of the graph, recorded by
+    /// `record`.
+    pub fn add_pass(
+        &mut self,
+        label: impl Into<String>,
+        reads: &[GraphTexture],
+        writes: &[GraphTexture],
+        record: impl FnOnce(&mut PassContext<'_>) -> WgpuResult<()> + 'a,
+    ) {
+        self.passes.push(Pass {
+            label: label.into(),
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            record: Box::new(record),
+        });
+    }
+
+    /// Adds a pass drawing `meshes` into `target` with
+    /// [`RenderPipeline::encode`]. Loading the target reads it.
+    pub fn draw_meshes(
+        &mut self,
+        target: GraphTexture,
+        load: wgpu::LoadOp<wgpu::Color>,
+        meshes: &'a [Mesh],
+    ) {
+        let reads = match load {
+            wgpu::LoadOp::Load => vec![target],
+            _ => Vec::new(),
+        };
+        self.add_pass("rpp_meshes", &reads, &[target], move |ctx| {
+            let texture = ctx.textures.get(target).unwrap();
+            ctx.pipeline
+                .encode(ctx.device, ctx.queue, ctx.encoder, texture, load, meshes)
+        });
+    }
+
+    /// Order the passes contributing to imported textures run in.
+    fn schedule(&self) -> WgpuResult<Vec<usize>> {
+        let passes: Vec<_> = self
+            .passes
+            .iter()
+            .map(|pass| (&pass.reads[..], &pass.writes[..]))
+            .collect();
+        schedule(&passes, |t| {
+            matches!(self.resources[t.0], Resource::Imported(_))
+        })
+    }
+
+    /// Labels of the passes in the order they run, without the skipped
+    /// ones.
+    pub fn pass_order(&self) -> WgpuResult<Vec<&str>> {
+        Ok(self
+            .schedule()?
+            .into_iter()
+            .map(|p| self.passes[p].label.as_str())
+            .collect())
+    }
+}
+
+/// Order the passes of `(reads, writes)` contributing to the textures
+/// `imported` returns true for run in. Scheduling only depends on what the
+/// passes access, not on their textures.
+fn schedule(
+    passes: &[(&[GraphTexture], &[GraphTexture])],
+    imported: impl Fn(GraphTexture) -> bool,
+) -> WgpuResult<Vec<usize>> {
+    let writers =
+        |texture: GraphTexture| (0..passes.len()).filter(move |&p| passes[p].1.contains(&texture));
+    // Passes each pass runs after.
+    let dependencies: Vec<Vec<usize>> = passes
+        .iter()
+        .enumerate()
+        .map(|(i, &(reads, writes))| {
+            let mut dependencies: Vec<usize> = Vec::new();
+            for &texture in reads {
+                let earlier = writes.contains(&texture);
+                dependencies.extend(writers(texture).filter(|&p| p != i && (!earlier || p < i)));
+            }
+            for &texture in writes {
+                dependencies.extend(writers(texture).filter(|&p| p < i));
+            }
+            dependencies.sort_unstable();
+            dependencies.dedup();
+            dependencies
+        })
+        .collect();
+
+    let mut used = vec![false; passes.len()];
+    let mut stack: Vec<usize> = (0..passes.len())
+        .filter(|&p| passes[p].1.iter().any(|&t| imported(t)))
+        .collect();
+    while let Some(p) = stack.pop() {
+        if !std::mem::replace(&mut used[p], true) {
+            stack.extend(&dependencies[p]);
+        }
+    }
+
+    // Runs the first pass added whose dependencies have run.
+    let mut order = Vec::new();
+    let mut done = vec![false; passes.len()];
+    let count = used.iter().filter(|&&u| u).count();
+    while order.len() < count {
+        let next = (0..passes.len())
+            .find(|&p| used[p] && !done[p] && dependencies[p].iter().all(|&d| done[d]))
+            .ok_or(WgpuErr::RenderGraphCycle)?;
+        done[next] = true;
+        order.push(next);
+    }
+    Ok(order)
+}
+
+impl RenderPipeline {
+    /// Records the passes of `graph` into `encoder`, allocating its
+    /// transient textures from the pool of the pipeline.
+    pub fn encode_graph(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        graph: RenderGraph<'_>,
+    ) -> WgpuResult<()> {
+        let order = graph.schedule()?;
+        let RenderGraph { resources, passes } = graph;
+        // Steps of the first and last pass using each texture.
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; resources.len()];
+        for (step, &p) in order.iter().enumerate() {
+            for texture in passes[p].reads.iter().chain(&passes[p].writes) {
+                let lifetime = lifetimes[texture.0].get_or_insert((step, step));
+                lifetime.1 = step;
+            }
+        }
+        let mut textures: Vec<Option<wgpu::Texture>> = resources
+            .iter()
+            .map(|r| match r {
+                Resource::Imported(texture) => Some(texture.clone()),
+                Resource::Transient(_) => None,
+            })
+            .collect();
+
+        let mut pool = std::mem::take(&mut self.pool);
+        pool.next_frame();
+        // Each pass appends its gradient ramps instead of overwriting the
+        // ones of the previous passes.
+        self.ramps.clear();
+        self.retain_ramps = true;
+        let mut passes: Vec<Option<Pass<'_>>> = passes.into_iter().map(Some).collect();
+        let mut result = Ok(());
+        for (step, &p) in order.iter().enumerate() {
+            for (i, resource) in resources.iter().enumerate() {
+                if let (Resource::Transient(desc), Some((first, _))) = (resource, lifetimes[i])
+                    && first == step
+                {
+                    textures[i] = Some(pool.acquire(device, desc));
+                }
+            }
+            let pass = passes[p].take().unwrap();
+            encoder.push_debug_group(&pass.label);
+            result = (pass.record)(&mut PassContext {
+                device,
+                queue,
+                encoder,
+                pipeline: self,
+                textures: GraphTextures {
+                    textures: &textures,
+                },
+            });
+            encoder.pop_debug_group();
+            if result.is_err() {
+                break;
+            }
+            for (i, resource) in resources.iter().enumerate() {
+                if let (Resource::Transient(desc), Some((_, last))) = (resource, lifetimes[i])
+                    && last == step
+                    && let Some(texture) = textures[i].take()
+                {
+                    pool.release(desc, texture);
+                }
+            }
+        }
+        // Textures of passes left after an error.
+        for (resource, texture) in resources.iter().zip(&mut textures) {
+            if let (Resource::Transient(desc), Some(texture)) = (resource, texture.take()) {
+                pool.release(desc, texture);
+            }
+        }
+        self.pool = pool;
+        self.retain_ramps = false;
+        result
+    }
+
+    /// Pool of the transient textures of [`RenderPipeline::encode_graph`].
+    pub fn texture_pool(&self) -> &TexturePool {
+        &self.pool
+    }
+
+    pub fn texture_pool_mut(&mut self) -> &mut TexturePool {
+        &mut self.pool
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        context::Context,
+        geometry::{Point, Rect, Viewport},
+        paint::{Gradient, GradientStop, Paint},
+        wgpu::{
+            WgpuContext,
+            tests::{device, read},
+        },
+    };
+
+    const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    fn noop(_: &mut PassContext<'_>) -> WgpuResult<()> {
+        Ok(())
+    }
+
+    fn target(device: &wgpu::Device) -> wgpu::Texture {
+        crate::wgpu::tests::target(device, 4, 4)
+    }
+
+    /// Labels of `(label, reads, writes)` passes in the order they run,
+    /// with texture 0 imported.
+    fn order<'l>(passes: &[(&'l str, &[usize], &[usize])]) -> WgpuResult<Vec<&'l str>> {
+        let textures = |ids: &[usize]| ids.iter().map(|&id| GraphTexture(id)).collect::<Vec<_>>();
+        let accesses: Vec<_> = passes
+            .iter()
+            .map(|&(_, reads, writes)| (textures(reads), textures(writes)))
+            .collect();
+        let accesses: Vec<_> = accesses.iter().map(|(r, w)| (&r[..], &w[..])).collect();
+        let order = schedule(&accesses, |t| t == GraphTexture(0))?;
+        Ok(order.into_iter().map(|p| passes[p].0).collect())
+    }
+
+    #[test]
+    fn passes_run_after_the_passes_they_read() {
+        let order = order(&[
+            ("composite", &[1], &[0]),
+            ("overlay", &[0], &[0]),
+            ("draw", &[], &[1]),
+        ]);
+        assert_eq!(order.unwrap(), ["draw", "composite", "overlay"]);
+    }
+
+    #[test]
+    fn passes_writing_a_texture_run_in_order() {
+        let order = order(&[
+            ("composite", &[1], &[0]),
+            ("background", &[], &[1]),
+            ("foreground", &[1], &[1]),
+            ("clear", &[], &[0]),
+        ]);
+        assert_eq!(
+            order.unwrap(),
+            ["background", "foreground", "composite", "clear"]
+        );
+    }
+
+    #[test]
+    fn passes_not_reaching_imports_are_skipped() {
+        let order = order(&[("unused", &[], &[1]), ("draw", &[], &[0])]);
+        assert_eq!(order.unwrap(), ["draw"]);
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let order = order(&[("a", &[2], &[1]), ("b", &[1], &[2]), ("draw", &[2], &[0])]);
+        assert!(matches!(order, Err(WgpuErr::RenderGraphCycle)));
+    }
+
+    #[test]
+    fn graphs_schedule_their_passes() {
+        let Some((device, _)) = device() else {
+            return;
+        };
+        let target = target(&device);
+        let mut graph = RenderGraph::new();
+        let frame = graph.import(&target);
+        let unused = graph.transient(TransientDesc::new(4, 4, FORMAT));
+        let scratch = graph.transient(TransientDesc::new(4, 4, FORMAT));
+        graph.add_pass("unused", &[], &[unused], noop);
+        graph.add_pass("composite", &[scratch], &[frame], noop);
+        graph.add_pass("draw", &[], &[scratch], noop);
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph.pass_order().unwrap(), ["draw", "composite"]);
+    }
+
+    #[test]
+    fn pool_reuses_released_textures() {
+        let Some((device, _)) = device() else {
+            return;
+        };
+        let desc = TransientDesc::new(4, 4, FORMAT);
+        let mut pool = TexturePool::new();
+        let texture = pool.acquire(&device, &desc);
+        pool.release(&desc, texture.clone());
+        assert_eq!(pool.len(), 1);
+        assert!(pool.acquire(&device, &desc.sample_count(4)) != texture);
+        assert!(pool.acquire(&device, &desc) == texture);
+        assert!(pool.is_empty());
+
+        pool.release(&desc, texture);
+        for _ in 0..MAX_IDLE_FRAMES {
+            pool.next_frame();
+        }
+        assert_eq!(pool.len(), 1);
+        pool.next_frame();
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn transients_share_textures_once_released() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut pipeline = RenderPipeline::new(&device, &queue, &Viewport::new(4.0, 4.0), FORMAT);
+        let target = target(&device);
+        let mut graph = RenderGraph::new();
+        let frame = graph.import(&target);
+        let desc = TransientDesc::new(4, 4, FORMAT);
+        let [a, b, c] = [(); 3].map(|_| graph.transient(desc));
+        graph.add_pass("a", &[], &[a], noop);
+        graph.add_pass("b", &[a], &[b], noop);
+        graph.add_pass("c", &[b], &[c], noop);
+        graph.add_pass("draw", &[c], &[frame], noop);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        pipeline
+            .encode_graph(&device, &queue, &mut encoder, graph)
+            .unwrap();
+        // `c` reuses the texture of `a`.
+        assert_eq!(pipeline.texture_pool().len(), 2);
+    }
+
+    #[test]
+    fn passes_keep_their_gradient_ramps() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let mut pipeline = RenderPipeline::new(&device, &queue, &Viewport::new(4.0, 4.0), FORMAT);
+        let contexts = [[1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]].map(|color| {
+            let gradient =
+                Gradient::new([GradientStop::new(0.0, color), GradientStop::new(1.0, color)]);
+            let paint =
+                Paint::linear_gradient(Point::new(0.0, 0.0), Point::new(4.0, 0.0), gradient);
+            let mut context = WgpuContext::new();
+            context.fill_rect(Rect::from_float(0.0, 0.0, 4.0, 4.0), &paint);
+            context
+        });
+        let targets = [target(&device), target(&device)];
+        let mut graph = RenderGraph::new();
+        for (context, target) in contexts.iter().zip(&targets) {
+            let frame = graph.import(target);
+            graph.draw_meshes(
+                frame,
+                wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                context.meshes(),
+            );
+        }
+        let mut encoder = device.create_command_encoder(&Default::default());
+        pipeline
+            .encode_graph(&device, &queue, &mut encoder, graph)
+            .unwrap();
+        queue.submit(Some(encoder.finish()));
+        assert_eq!(read(&device, &queue, &targets[0])[2][2], [255, 0, 0, 255]);
+        assert_eq!(read(&device, &queue, &targets[1])[2][2], [0, 0, 255, 255]);
+    }
+}
@@ -0,0 +1,312 @@
+use std::{borrow::Cow, collections::HashMap};
+
+use wgpu::util::DeviceExt;
+
+use crate::{geometry::Float, paint::BlendMode};
+
+use super::{STENCIL_FORMAT, TextureRegion};
+
+const COMPOSITE_SHADER_SRC: &str = include_str!("./composite.wgsl");
+
+/// Matches `CompositeUniforms` in `composite.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct CompositeUniforms {
+    backdrop_origin: [i32; 2],
+    opacity: f32,
+    mode: u32,
+}
+
+impl CompositeUniforms {
+    /// Composition into `region` of the target, whose backdrop is copied
+    /// at the origin of the backdrop texture.
+    pub(crate) fn new(region: TextureRegion, opacity: Float, blend_mode: BlendMode) -> Self {
+        CompositeUniforms {
+            backdrop_origin: [region.x as i32, region.y as i32],
+            opacity: opacity.clamp(0.0, 1.0),
+            mode: blend_mode.index(),
+        }
+    }
+}
+
+/// Draws layers into the target below them.
+pub(crate) struct CompositePipeline {
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    /// Pipelines by target format, sample count and whether they read a
+    /// backdrop, created on first use.
+    pipelines: HashMap<(wgpu::TextureFormat, u32, bool), wgpu::RenderPipeline>,
+    /// Sample count of the passes composited into.
+    sample_count: u32,
+    clear_layout: wgpu::PipelineLayout,
+    /// Pipelines clearing regions of single sampled targets, by format.
+    clear_pipelines: HashMap<wgpu::TextureFormat, wgpu::RenderPipeline>,
+    /// Uniforms of the compositions prepared by
+    /// [`CompositePipeline::prepare`], with the dynamic offset of each.
+    uniforms: Option<(wgpu::BindGroup, Vec<u32>)>,
+    /// Compositions recorded since the last prepare.
+    recorded: usize,
+    /// Bind groups by layer and backdrop, until the next prepare.
+    texture_bind_groups: HashMap<(wgpu::TextureView, Option<wgpu::TextureView>), wgpu::BindGroup>,
+}
+
+impl CompositePipeline {
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let uniform_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<CompositeUniforms>() as u64,
+                        ),
+                    },
+                    count: None,
+                }],
+                label: Some("composite_uniform_bind_group_layout"),
+            });
+        let texture_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[texture_entry(0), texture_entry(1)],
+                label: Some("composite_texture_bind_group_layout"),
+            });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("composite_pipeline_layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("composite_shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(COMPOSITE_SHADER_SRC)),
+        });
+        let clear_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("clear_pipeline_layout"),
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        CompositePipeline {
+            uniform_bind_group_layout,
+            texture_bind_group_layout,
+            layout,
+            shader,
+            pipelines: HashMap::new(),
+            sample_count: 1,
+            clear_layout,
+            clear_pipelines: HashMap::new(),
+            uniforms: None,
+            recorded: 0,
+            texture_bind_groups: HashMap::new(),
+        }
+    }
+
+    /// Uploads the uniforms of the compositions recorded next, in order,
+    /// returning the size of the buffer.
+    pub(crate) fn prepare(&mut self, device: &wgpu::Device, uniforms: &[CompositeUniforms]) -> u64 {
+        self.recorded = 0;
+        self.texture_bind_groups.clear();
+        if uniforms.is_empty() {
+            self.uniforms = None;
+            return 0;
+        }
+        let size = std::mem::size_of::<CompositeUniforms>();
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as usize;
+        let stride = size.div_ceil(alignment) * alignment;
+        let mut contents = vec![0; stride * uniforms.len()];
+        let mut offsets = Vec::with_capacity(uniforms.len());
+        for (i, uniforms) in uniforms.iter().enumerate() {
+            let offset = stride * i;
+            contents[offset..offset + size].copy_from_slice(bytemuck::bytes_of(uniforms));
+            offsets.push(offset as u32);
+        }
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Composite Buffer"),
+            contents: &contents,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.uniform_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(size as u64),
+                }),
+            }],
+            label: Some("composite_uniform_bind_group"),
+        });
+        self.uniforms = Some((bind_group, offsets));
+        contents.len() as u64
+    }
+
+    pub(crate) fn set_sample_count(&mut self, sample_count: u32) {
+        self.sample_count = sample_count;
+    }
+
+    fn pipeline(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        backdrop: bool,
+    ) -> &wgpu::RenderPipeline {
+        let sample_count = self.sample_count;
+        let key = (format, sample_count, backdrop);
+        self.pipelines.entry(key).or_insert_with(|| {
+            let stencil = wgpu::StencilFaceState {
+                compare: wgpu::CompareFunction::Equal,
+                fail_op: wgpu::StencilOperation::Keep,
+                depth_fail_op: wgpu::StencilOperation::Keep,
+                pass_op: wgpu::StencilOperation::Keep,
+            };
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("composite_pipeline"),
+                layout: Some(&self.layout),
+                vertex: wgpu::VertexState {
+                    module: &self.shader,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: STENCIL_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState {
+                        front: stencil,
+                        back: stencil,
+                        read_mask: 0xff,
+                        write_mask: 0,
+                    },
+                    bias: wgpu::DepthBiasState::default(),
+                }),
+                multisample: wgpu::MultisampleState {
+                    count: sample_count,
+                    ..Default::default()
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.shader,
+                    entry_point: Some(if backdrop { "fs_blend" } else { "fs_normal" }),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: (!backdrop)
+                            .then_some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            })
+        })
+    }
+
+    /// Records the next prepared composition of `layer` into `pass`,
+    /// restricted to `region` and to texels where the stencil equals
+    /// `stencil_reference`.
+    ///
+    /// Blend modes other than [`BlendMode::Normal`] read `backdrop`, a copy
+    /// of `region` of the target at its origin.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn composite(
+        &mut self,
+        device: &wgpu::Device,
+        pass: &mut wgpu::RenderPass<'_>,
+        format: wgpu::TextureFormat,
+        layer: &wgpu::TextureView,
+        backdrop: Option<&wgpu::TextureView>,
+        region: TextureRegion,
+        stencil_reference: u32,
+    ) {
+        let (uniforms, offsets) = self.uniforms.as_ref().expect("compositions are prepared");
+        let offset = offsets[self.recorded];
+        let uniforms = uniforms.clone();
+        self.recorded += 1;
+        let textures = self
+            .texture_bind_groups
+            .entry((layer.clone(), backdrop.cloned()))
+            .or_insert_with(|| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.texture_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(layer),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            // Unused by normal layers.
+                            resource: wgpu::BindingResource::TextureView(backdrop.unwrap_or(layer)),
+                        },
+                    ],
+                    label: Some("composite_texture_bind_group"),
+                })
+            })
+            .clone();
+        let pipeline = self.pipeline(device, format, backdrop.is_some());
+
+        pass.set_pipeline(pipeline);
+        pass.set_bind_group(0, &uniforms, &[offset]);
+        pass.set_bind_group(1, &textures, &[]);
+        pass.set_stencil_reference(stencil_reference);
+        pass.set_scissor_rect(region.x, region.y, region.width, region.height);
+        pass.draw(0..3, 0..1);
+    }
+    /// Records clearing `regions` to transparent into `pass`, which draws
+    /// into a single sampled target of `format` without depth or stencil.
+    pub(crate) fn clear(
+        &mut self,
+        device: &wgpu::Device,
+        pass: &mut wgpu::RenderPass<'_>,
+        format: wgpu::TextureFormat,
+        regions: &[TextureRegion],
+    ) {
+        let pipeline = self.clear_pipelines.entry(format).or_insert_with(|| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("clear_pipeline"),
+                layout: Some(&self.clear_layout),
+                vertex: wgpu::VertexState {
+                    module: &self.shader,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.shader,
+                    entry_point: Some("fs_clear"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                multiview: None,
+                cache: None,
+            })
+        });
+        pass.set_pipeline(pipeline);
+        for region in regions {
+            pass.set_scissor_rect(region.x, region.y, region.width, region.height);
+            pass.draw(0..3, 0..1);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // Triangle covering the viewport.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Averages the 2x2 texels of the previous level, repeating its last row
+// and column when its size is odd.
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let last = vec2<i32>(textureDimensions(source)) - 1;
+    let p = vec2<i32>(position.xy) * 2;
+    let a = textureLoad(source, min(p, last), 0);
+    let b = textureLoad(source, min(p + vec2<i32>(1, 0), last), 0);
+    let c = textureLoad(source, min(p + vec2<i32>(0, 1), last), 0);
+    let d = textureLoad(source, min(p + vec2<i32>(1, 1), last), 0);
+    return (a + b + c + d) * 0.25;
+}
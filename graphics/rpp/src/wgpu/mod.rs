@@ -1,51 +1,268 @@
-use std::borrow::Cow;
-use std::collections::HashMap;
+mod atlas;
+mod blur;
+mod builder;
+mod context;
+mod error;
+mod graph;
+mod layer;
+mod mipmap;
+mod paint;
+mod pipeline;
+mod shape;
+mod stats;
+#[cfg(feature = "svg")]
+mod svg;
+mod texture;
 
-const PP_SHADER_SRC: &str = include_str!("./shader.wgsl");
+use std::{
+    borrow::Cow,
+    hash::Hash,
+    ops::Range,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
-use crate::geometry::{Mat4, Size, Viewport};
+/// Format of the stencil attachment holding rounded rect and path clips.
+pub const STENCIL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Stencil8;
 
+use crate::{
+    color::Color,
+    damage::Damage,
+    effect,
+    geometry::{Mat3, Mat4, Rect, Viewport},
+    image::{Image, ImageId, Sampling},
+    paint::{BlendMode, Paint},
+    shader::ShaderId,
+};
+
+pub use atlas::{AtlasEntry, TextureAtlas};
+use blur::BlurPipeline;
+pub use builder::{DeviceLostCallback, WgpuDeviceBuilder};
+pub use context::WgpuContext;
+pub use error::{WgpuErr, WgpuResult};
+pub use graph::{
+    GraphTexture, GraphTextures, PassContext, RenderGraph, TexturePool, TransientDesc,
+};
+use layer::{CompositePipeline, CompositeUniforms};
+use mipmap::MipmapPipeline;
+use paint::{GradientRamps, PaintUniforms};
+pub use pipeline::{PipelineKey, PipelineRegistry, PrimitiveKind};
 #[cfg(feature = "rwh")]
 use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+pub use shape::Shape;
+use shape::ShapeUniforms;
+pub use stats::FrameStats;
+use stats::GpuTimer;
+#[cfg(feature = "svg")]
+pub use svg::SvgCache;
+use texture::TextureStore;
+pub use texture::{Texture, TextureAlphaMode, TextureColorSpace, TextureId, TextureRegion};
 use wgpu::util::DeviceExt;
 
 pub trait WindowHandle: HasWindowHandle + HasDisplayHandle + Sync + Send {}
 
-pub type TextureId = usize;
+/// Uniforms shared by every draw, matching `Uniforms` in `shader.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Uniforms {
+    pub ortho: Mat4,
+    /// Seconds passed to custom shaders.
+    pub time: f32,
+    _padding: [f32; 3],
+}
 
-pub struct Texture {
-    wgpu_texture: wgpu::Texture,
-    bind_group: wgpu::BindGroup,
-    size: Size,
+impl Uniforms {
+    pub fn new(ortho: Mat4, time: f32) -> Self {
+        Uniforms {
+            ortho,
+            time,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+/// Per draw uniforms, matching `DrawUniforms` in `shader.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawUniforms {
+    paint: PaintUniforms,
+    shape: ShapeUniforms,
+    transform: [[f32; 4]; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct WgpuVertex {
+    pub position: [f32; 2],
+    pub tex_coords: [f32; 2],
+    /// Straight alpha RGBA8 color packed as `r | g << 8 | b << 16 | a << 24`,
+    /// see [`Color::pack`].
+    pub color: u32,
 }
 
+impl WgpuVertex {
+    pub fn new(position: [f32; 2], tex_coords: [f32; 2], color: Color) -> Self {
+        WgpuVertex {
+            position,
+            tex_coords,
+            color: color.pack(),
+        }
+    }
+}
+
+/// Indexed triangles drawn with a single texture and paint.
+///
+/// The premultiplied texture, the vertex colors and the paint are
+/// multiplied, and the result is blended premultiplied. Meshes
+/// without a texture are drawn with an opaque white texture, and meshes
+/// without a paint use the vertex colors as is. A shape masks the mesh,
+/// which then only has to cover it.
 #[derive(Clone, Debug)]
-pub enum WgpuErr {
-    AdapterRequestDeviceError(wgpu::RequestAdapterError),
-    CreateSurfaceError,
-    CreateDeviceError,
+pub struct Mesh {
+    pub vertices: Vec<WgpuVertex>,
+    pub indices: Vec<u32>,
+    pub texture: Option<TextureId>,
+    pub sampling: Sampling,
+    /// Texture coordinates are in texels instead of `0.0..=1.0`.
+    pub texel_coords: bool,
+    pub paint: Option<Paint>,
+    pub shape: Option<Shape>,
+    /// Blur radius of the content drawn before the mesh, which replaces the
+    /// texture. Only supported by [`RenderPipeline::encode`].
+    pub backdrop_blur: Option<f32>,
+    pub clip: MeshClip,
+    /// Maps vertex positions and the shape to the viewport.
+    pub transform: Mat3,
+    /// Marks the start or end of a layer instead of drawing. Only supported
+    /// by [`RenderPipeline::encode`].
+    pub layer: Option<LayerOp>,
+    /// Shader drawing the mesh, such as [`PrimitiveKind::Text`]. Picked
+    /// from the texture, paint and shape of the mesh if `None`.
+    pub shader: Option<ShaderId>,
 }
 
-pub type WgpuResult<T> = Result<T, WgpuErr>;
+/// Start or end of a layer in a list of meshes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LayerOp {
+    /// Following meshes are drawn into a new transparent layer, composited
+    /// with `opacity` and `blend_mode` into the target below. The content
+    /// of the layer is blurred first if `blur_radius` is positive, in
+    /// viewport units.
+    Begin {
+        opacity: f32,
+        blend_mode: BlendMode,
+        blur_radius: f32,
+    },
+    /// Composites the layer into the target below, inside the clip of the
+    /// mesh. Layers still open at the end of the meshes are composited
+    /// there, inside the clip of their [`LayerOp::Begin`].
+    End,
+}
 
-#[repr(C)]
-#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct Uniforms {
-    pub ortho: Mat4,
+impl Default for Mesh {
+    fn default() -> Self {
+        Mesh {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            texture: None,
+            sampling: Sampling::default(),
+            texel_coords: false,
+            paint: None,
+            shape: None,
+            backdrop_blur: None,
+            clip: MeshClip::default(),
+            transform: Mat3::identity(),
+            layer: None,
+            shader: None,
+        }
+    }
+}
+
+/// How a mesh affects the clip stencil.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ClipOp {
+    /// The mesh is drawn where the stencil equals the clip depth.
+    #[default]
+    Draw,
+    /// The mesh is added to the clip, incrementing the stencil where it
+    /// equals the clip depth. Nothing is drawn.
+    Push,
+    /// A mesh pushed before is removed from the clip, decrementing the
+    /// stencil where it equals the clip depth. Nothing is drawn.
+    Pop,
+}
+
+/// Clip state of a mesh.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MeshClip {
+    /// Rect drawing is restricted to, snapped to pixels.
+    pub scissor: Option<Rect>,
+    /// Number of rounded rect and path clips containing the mesh.
+    pub depth: u32,
+    pub op: ClipOp,
+}
+
+/// Copy of the render target under backdrop meshes.
+struct Backdrop {
+    texture: wgpu::Texture,
+    scratch: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
 }
 
-struct WgpuVertex {
-    position: [f32; 2],
-    tex_coords: [f32; 2],
-    color: f32,
+/// Vertex, index and uniform data of meshes, ready to be drawn.
+struct PreparedMeshes {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    offsets: Vec<u32>,
+    /// First index and base vertex of each mesh.
+    ranges: Vec<(Range<u32>, i32)>,
 }
 
 pub struct RenderPipeline {
-    rp: wgpu::RenderPipeline,
-    textures: HashMap<TextureId, Texture>,
+    pipelines: PipelineRegistry,
+    /// Format of the targets of [`RenderPipeline::draw`].
+    color_format: wgpu::TextureFormat,
+    /// Seconds passed to custom shaders.
+    time: f32,
+    msaa: Msaa,
+    color_space: TargetColorSpace,
+    /// Multisampled textures drawn into and resolved into the target, then
+    /// the layers by nesting depth, sized to the last target.
+    msaa_textures: Vec<wgpu::Texture>,
+    textures: TextureStore,
+    white_texture: Texture,
+    /// Texture samplers by [`Sampling`].
+    samplers: [wgpu::Sampler; Sampling::COUNT],
     uniform_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
     texture_bind_group_layout: wgpu::BindGroupLayout,
+    draw_bind_group_layout: wgpu::BindGroupLayout,
+    ramps: GradientRamps,
+    ramp_sampler: wgpu::Sampler,
+    viewport: Viewport,
+    blur: BlurPipeline,
+    backdrop: Option<Backdrop>,
+    stencil: Option<wgpu::Texture>,
+    composite: CompositePipeline,
+    /// Layer textures by nesting depth, sized to the last target.
+    layers: Vec<wgpu::Texture>,
+    mipmap: MipmapPipeline,
+    /// Pixels drawing is restricted to while encoding a damaged region.
+    damage: Option<TextureRegion>,
+    /// Whether the first multisampled texture already holds the target,
+    /// skipping the copy when loading it. Set between damaged regions.
+    msaa_loaded: bool,
+    /// Counters since the last [`RenderPipeline::take_stats`].
+    stats: FrameStats,
+    /// Transient textures of render graphs and blurs.
+    pool: TexturePool,
+    /// Whether gradient ramps are kept across draws, while encoding a render
+    /// graph whose passes are submitted together.
+    retain_ramps: bool,
 }
 
 impl RenderPipeline {
@@ -59,14 +276,32 @@ impl RenderPipeline {
 
     pub fn new(
         device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        viewport: &Viewport,
+        color_format: wgpu::TextureFormat,
+    ) -> Self {
+        // SAFETY: the pipeline cache is created without data.
+        unsafe { Self::with_pipeline_cache(device, queue, viewport, color_format, None) }
+    }
+
+    /// Creates a pipeline whose pipeline cache is filled with `cache_data`.
+    ///
+    /// # Safety
+    ///
+    /// `cache_data` must have been returned by
+    /// [`PipelineRegistry::cache_data`].
+    pub(crate) unsafe fn with_pipeline_cache(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
         viewport: &Viewport,
         color_format: wgpu::TextureFormat,
+        cache_data: Option<&[u8]>,
     ) -> Self {
         let uniform_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -77,13 +312,8 @@ impl RenderPipeline {
                 label: Some("uniform_bind_group_layout"),
             });
 
-        let ortho_matrix = viewport.to_ortho();
-        let uniform_buffer = Self::create_uniform_buffer(
-            &device,
-            &Uniforms {
-                ortho: ortho_matrix.into(),
-            },
-        );
+        let uniform_buffer =
+            Self::create_uniform_buffer(device, &Uniforms::new(viewport.to_ortho(), 0.0));
 
         let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &uniform_bind_group_layout,
@@ -118,205 +348,2620 @@ impl RenderPipeline {
                 label: Some("texture_bind_group_layout"),
             });
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(PP_SHADER_SRC)),
-        });
+        let draw_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: true,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<DrawUniforms>() as u64,
+                            ),
+                        },
+                        count: None,
+                    },
+                    // Gradient ramps
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+                label: Some("draw_bind_group_layout"),
+            });
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[&uniform_bind_group_layout, &texture_bind_group_layout],
-            push_constant_ranges: &[],
-        });
+        // SAFETY: guaranteed by the caller.
+        let pipelines = unsafe {
+            PipelineRegistry::new(
+                device,
+                &[
+                    &uniform_bind_group_layout,
+                    &texture_bind_group_layout,
+                    &draw_bind_group_layout,
+                ],
+                cache_data,
+            )
+        };
 
-        // position, tex coords, color
-        let attributes = &wgpu::vertex_attr_array![
-            0 => Float32x2,
-            1 => Float32x2,
-            2 => Uint32
+        // Only the mipmapped sampler reads levels other than the first, as
+        // on the CPU.
+        let create_sampler = |label, filter, mipmap_filter, lod_max_clamp| {
+            device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some(label),
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                mag_filter: filter,
+                min_filter: filter,
+                mipmap_filter,
+                lod_max_clamp,
+                ..Default::default()
+            })
+        };
+        let samplers = [
+            create_sampler(
+                "texture_sampler_nearest",
+                wgpu::FilterMode::Nearest,
+                wgpu::FilterMode::Nearest,
+                0.0,
+            ),
+            create_sampler(
+                "texture_sampler",
+                wgpu::FilterMode::Linear,
+                wgpu::FilterMode::Nearest,
+                0.0,
+            ),
+            create_sampler(
+                "texture_sampler_mipmapped",
+                wgpu::FilterMode::Linear,
+                wgpu::FilterMode::Linear,
+                32.0,
+            ),
         ];
 
-        let rp = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                compilation_options: Default::default(),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<WgpuVertex>() as wgpu::BufferAddress,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes,
-                }],
-            },
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                compilation_options: Default::default(),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: color_format,
-                    blend: Some(wgpu::BlendState {
-                        color: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::Src,
-                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                        alpha: wgpu::BlendComponent {
-                            src_factor: wgpu::BlendFactor::One,
-                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                            operation: wgpu::BlendOperation::Add,
-                        },
-                    }),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            multiview: None,
-            cache: None,
+        let white_texture = Texture::new(
+            device,
+            &texture_bind_group_layout,
+            &samplers,
+            1,
+            1,
+            1,
+            TextureColorSpace::Linear,
+            TextureAlphaMode::Premultiplied,
+        );
+        white_texture.write(queue, TextureRegion::new(0, 0, 1, 1), &[255; 4]);
+
+        let ramp_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("ramp_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
         });
 
         RenderPipeline {
             uniform_buffer,
             uniform_bind_group,
             texture_bind_group_layout,
-            textures: HashMap::new(),
-            rp,
+            textures: TextureStore::new(),
+            white_texture,
+            samplers,
+            draw_bind_group_layout,
+            ramps: GradientRamps::new(device),
+            ramp_sampler,
+            viewport: *viewport,
+            blur: BlurPipeline::new(device),
+            backdrop: None,
+            stencil: None,
+            composite: CompositePipeline::new(device),
+            mipmap: MipmapPipeline::new(device),
+            layers: Vec::new(),
+            damage: None,
+            msaa_loaded: false,
+            color_format,
+            pipelines,
+            time: 0.0,
+            msaa: Msaa::Off,
+            color_space: TargetColorSpace::default(),
+            msaa_textures: Vec::new(),
+            stats: FrameStats::default(),
+            pool: TexturePool::new(),
+            retain_ramps: false,
         }
     }
-}
 
-pub struct Surface<'a> {
-    surface: wgpu::Surface<'a>,
-    config: wgpu::SurfaceConfiguration,
-}
+    /// Viewport meshes are drawn into.
+    pub fn viewport(&self) -> &Viewport {
+        &self.viewport
+    }
 
-pub struct WgpuDevice<'a> {
-    surface: Option<Surface<'a>>,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    pipeline: RenderPipeline,
-}
+    /// Updates the projection to match `viewport`.
+    pub fn set_viewport(&mut self, queue: &wgpu::Queue, viewport: &Viewport) {
+        self.viewport = *viewport;
+        let uniforms = Uniforms::new(viewport.to_ortho(), self.time);
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+        self.stats.buffer_bytes += std::mem::size_of::<Uniforms>() as u64;
+    }
 
-impl<'a> WgpuDevice<'a> {
-    fn create_instance() -> wgpu::Instance {
-        wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            ..Default::default()
-        })
+    /// Sets the time passed to custom shaders, in seconds.
+    pub fn set_time(&mut self, queue: &wgpu::Queue, time: f32) {
+        self.time = time;
+        let offset = std::mem::offset_of!(Uniforms, time) as wgpu::BufferAddress;
+        queue.write_buffer(&self.uniform_buffer, offset, bytemuck::bytes_of(&time));
+        self.stats.buffer_bytes += std::mem::size_of::<f32>() as u64;
     }
 
-    #[cfg(feature = "rwh")]
-    fn create_surface_rwh_window<T: WindowHandle + 'a>(
-        instance: &wgpu::Instance,
-        window: T,
-    ) -> WgpuResult<wgpu::Surface<'a>> {
-        let surface_target = wgpu::SurfaceTarget::Window(Box::new(window));
-        let surface = match instance.create_surface(surface_target) {
-            Ok(s) => s,
-            Err(..) => return Err(WgpuErr::CreateSurfaceError),
+    /// Counters of what has been drawn and uploaded since the last
+    /// [`RenderPipeline::take_stats`]. Times are left unset.
+    pub fn stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    /// Returns the counters and resets them, such as once per frame.
+    pub fn take_stats(&mut self) -> FrameStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    /// Sets the multisample anti-aliasing of the targets drawn into. The
+    /// adapter must support it for the formats of the targets and
+    /// [`STENCIL_FORMAT`].
+    pub fn set_msaa(&mut self, msaa: Msaa) {
+        self.msaa = msaa;
+        self.composite.set_sample_count(msaa.sample_count());
+    }
+
+    pub fn msaa(&self) -> Msaa {
+        self.msaa
+    }
+
+    /// Sets the color space the targets drawn into are displayed in, which
+    /// paint and vertex colors are converted to.
+    pub fn set_color_space(&mut self, color_space: TargetColorSpace) {
+        self.color_space = color_space;
+    }
+
+    pub fn color_space(&self) -> TargetColorSpace {
+        self.color_space
+    }
+
+    /// Render pipelines drawing meshes.
+    pub fn pipelines(&self) -> &PipelineRegistry {
+        &self.pipelines
+    }
+
+    pub fn pipelines_mut(&mut self) -> &mut PipelineRegistry {
+        &mut self.pipelines
+    }
+
+    /// Registers a custom shader for [`Paint::Shader`], see
+    /// [`PipelineRegistry::register_shader`].
+    pub fn register_shader(&mut self, device: &wgpu::Device, source: &str) -> WgpuResult<ShaderId> {
+        self.pipelines.register_shader(device, source)
+    }
+
+    /// Compiles the pipelines drawing `meshes` into a target of `format`.
+    fn prepare_pipelines(
+        &mut self,
+        device: &wgpu::Device,
+        meshes: &[Mesh],
+        format: wgpu::TextureFormat,
+    ) -> WgpuResult<()> {
+        for mesh in meshes.iter().filter(|m| m.layer.is_none()) {
+            let key =
+                PipelineKey::for_mesh(mesh, format, self.msaa.sample_count(), self.color_space);
+            self.pipelines.prepare(device, key)?;
+        }
+        Ok(())
+    }
+
+    /// Creates a texture from tightly packed RGBA8 texels. Straight texels
+    /// are premultiplied on upload, here and by later updates.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        width: u32,
+        height: u32,
+        color_space: TextureColorSpace,
+        alpha_mode: TextureAlphaMode,
+        data: &[u8],
+    ) -> WgpuResult<TextureId> {
+        if data.len() != width as usize * height as usize * 4 {
+            return Err(WgpuErr::InvalidTextureData);
+        }
+        let id = self.create_empty_texture(device, width, height, color_space, alpha_mode)?;
+        let texture = self.textures.get(id).ok_or(WgpuErr::TextureNotFound(id))?;
+        texture.write(queue, TextureRegion::new(0, 0, width, height), data);
+        self.stats.texture_uploads += 1;
+        Ok(id)
+    }
+
+    /// Creates a texture with zeroed texels, without uploading any data.
+    pub(crate) fn create_empty_texture(
+        &mut self,
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        color_space: TextureColorSpace,
+        alpha_mode: TextureAlphaMode,
+    ) -> WgpuResult<TextureId> {
+        let max = device.limits().max_texture_dimension_2d;
+        if width == 0 || height == 0 || width > max || height > max {
+            return Err(WgpuErr::InvalidTextureSize);
+        }
+        let texture = Texture::new(
+            device,
+            &self.texture_bind_group_layout,
+            &self.samplers,
+            width,
+            height,
+            1,
+            color_space,
+            alpha_mode,
+        );
+        Ok(self.textures.insert(texture))
+    }
+
+    /// Creates a texture from `image` for [`Context::draw_image`], with
+    /// mipmaps for [`Sampling::Mipmapped`] if `mipmapped` is set.
+    ///
+    /// [`Context::draw_image`]: crate::Context::draw_image
+    pub fn create_image(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &Image,
+        mipmapped: bool,
+    ) -> WgpuResult<ImageId> {
+        let (width, height) = (image.width(), image.height());
+        let max = device.limits().max_texture_dimension_2d;
+        if width > max || height > max {
+            return Err(WgpuErr::InvalidTextureSize);
+        }
+        let mip_level_count = if mipmapped {
+            32 - width.max(height).leading_zeros()
+        } else {
+            1
         };
-        Ok(surface)
-    }
-
-    async fn init_adapter(
-        instance: &wgpu::Instance,
-        surface: Option<&wgpu::Surface<'a>>,
-    ) -> WgpuResult<wgpu::Adapter> {
-        match instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                force_fallback_adapter: false,
-                compatible_surface: surface,
-            })
-            .await
-        {
-            Ok(a) => Ok(a),
-            Err(err) => Err(WgpuErr::AdapterRequestDeviceError(err)),
+        let texture = Texture::new(
+            device,
+            &self.texture_bind_group_layout,
+            &self.samplers,
+            width,
+            height,
+            mip_level_count,
+            TextureColorSpace::Srgb,
+            TextureAlphaMode::Straight,
+        );
+        texture.write(queue, TextureRegion::new(0, 0, width, height), image.data());
+        self.mipmap.generate(device, queue, texture.wgpu_texture());
+        self.stats.texture_uploads += 1;
+        Ok(ImageId::new(self.textures.insert(texture)))
+    }
+
+    /// Overwrites `region` of the texture with tightly packed RGBA8 texels,
+    /// updating its mipmaps.
+    pub fn update_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        id: TextureId,
+        region: TextureRegion,
+        data: &[u8],
+    ) -> WgpuResult<()> {
+        let texture = self.textures.get(id).ok_or(WgpuErr::TextureNotFound(id))?;
+        if !texture.contains(region) {
+            return Err(WgpuErr::InvalidTextureRegion);
         }
+        if data.len() != region.width as usize * region.height as usize * 4 {
+            return Err(WgpuErr::InvalidTextureData);
+        }
+        texture.write(queue, region, data);
+        self.mipmap.generate(device, queue, texture.wgpu_texture());
+        self.stats.texture_uploads += 1;
+        Ok(())
     }
 
-    async fn init(
-        adapter: &wgpu::Adapter,
-        wgpu_surface: Option<wgpu::Surface<'a>>,
-        viewport: &Viewport,
-        color_format: wgpu::TextureFormat,
-    ) -> WgpuResult<Self> {
-        let (device, queue) = match adapter
-            .request_device(&wgpu::DeviceDescriptor {
-                label: None,
-                required_features: wgpu::Features::empty(),
-                required_limits: wgpu::Limits::downlevel_webgl2_defaults()
-                    .using_resolution(adapter.limits()),
-                memory_hints: wgpu::MemoryHints::MemoryUsage,
-                trace: wgpu::Trace::Off,
-                ..Default::default()
+    /// Frees the texture. Its slot is reused by later textures, under ids
+    /// that do not match this one.
+    pub fn destroy_texture(&mut self, id: TextureId) -> WgpuResult<()> {
+        let texture = self
+            .textures
+            .remove(id)
+            .ok_or(WgpuErr::TextureNotFound(id))?;
+        texture.destroy();
+        Ok(())
+    }
+
+    pub fn texture(&self, id: TextureId) -> Option<&Texture> {
+        self.textures.get(id)
+    }
+
+    /// Applies a Gaussian blur with a CSS style `blur_radius` to the
+    /// texture, repeating its edge texels.
+    pub fn blur_texture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        id: TextureId,
+        blur_radius: f32,
+    ) -> WgpuResult<()> {
+        let texture = self
+            .textures
+            .get(id)
+            .ok_or(WgpuErr::TextureNotFound(id))?
+            .wgpu_texture();
+        let scratch_desc = TransientDesc::new(texture.width(), texture.height(), texture.format())
+            .usage(wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT);
+        let scratch = self.pool.acquire(device, &scratch_desc);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("rpp_blur_encoder"),
+        });
+        self.stats.draw_calls += self.blur.blur(
+            device,
+            &mut encoder,
+            texture,
+            &scratch,
+            texture.width(),
+            texture.height(),
+            effect::blur_sigma(blur_radius),
+        );
+        queue.submit(Some(encoder.finish()));
+        self.pool.release(&scratch_desc, scratch);
+        Ok(())
+    }
+
+    /// Uploads the paints and shapes of `meshes`, returning the bind group
+    /// and the dynamic offset of each mesh's uniforms.
+    fn prepare_draws(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        meshes: &[Mesh],
+    ) -> (wgpu::BindGroup, Vec<u32>) {
+        let size = std::mem::size_of::<DrawUniforms>();
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as usize;
+        let stride = size.div_ceil(alignment) * alignment;
+
+        if !self.retain_ramps {
+            self.ramps.clear();
+        }
+        let rows: Vec<Option<u32>> = meshes
+            .iter()
+            .map(|mesh| {
+                let gradient = mesh.paint.as_ref()?.gradient()?;
+                Some(self.ramps.push(gradient))
             })
-            .await
-        {
-            Ok(r) => r,
-            Err(..) => return Err(WgpuErr::CreateDeviceError),
+            .collect();
+        if self.ramps.upload(device, queue) {
+            self.stats.texture_uploads += 1;
+        }
+
+        let mut contents = vec![0; stride * meshes.len()];
+        let mut offsets = Vec::with_capacity(meshes.len());
+        for (i, (mesh, row)) in meshes.iter().zip(rows).enumerate() {
+            let paint = match &mesh.paint {
+                Some(paint) => {
+                    PaintUniforms::new(paint, row.map(|row| self.ramps.v(row)).unwrap_or(0.0))
+                }
+                None => PaintUniforms::solid([1.0; 4]),
+            };
+            let uniforms = DrawUniforms {
+                paint,
+                shape: ShapeUniforms::new(mesh.shape.as_ref()),
+                transform: paint::columns(&mesh.transform),
+            };
+            let offset = stride * i;
+            contents[offset..offset + size].copy_from_slice(bytemuck::bytes_of(&uniforms));
+            offsets.push(offset as u32);
+        }
+
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Draw Buffer"),
+            contents: &contents,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        self.stats.buffer_bytes += contents.len() as u64;
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.draw_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(size as u64),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(self.ramps.view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.ramp_sampler),
+                },
+            ],
+            label: Some("draw_bind_group"),
+        });
+        (bind_group, offsets)
+    }
+
+    /// Bounds in pixels of the vertices of `mesh`, grown by `margin`.
+    fn mesh_bounds(&self, mesh: &Mesh, margin: f32) -> Option<[f32; 4]> {
+        let scale = self.viewport.scale_factor();
+        let (mut min, mut max) = ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]);
+        for vertex in &mesh.vertices {
+            let position: [f32; 2] = mesh
+                .transform
+                .transform_point(vertex.position.into())
+                .into();
+            for axis in 0..2 {
+                min[axis] = min[axis].min(position[axis] * scale - margin);
+                max[axis] = max[axis].max(position[axis] * scale + margin);
+            }
+        }
+        (min[0] <= max[0]).then_some([min[0], min[1], max[0], max[1]])
+    }
+
+    /// Regions of a render target of `size` read by each mesh: the backdrop
+    /// of backdrop blurs, and the area layers are composited into at their
+    /// end, grown by their blur. `None` if empty. `margins` are the ones of
+    /// [`blur_margins`].
+    fn target_regions(
+        &self,
+        meshes: &[Mesh],
+        margins: &[u32],
+        size: wgpu::Extent3d,
+    ) -> Vec<Option<TextureRegion>> {
+        let scale = self.viewport.scale_factor();
+        let to_region = |b: [f32; 4]| {
+            let x0 = b[0].floor().clamp(0.0, size.width as f32) as u32;
+            let y0 = b[1].floor().clamp(0.0, size.height as f32) as u32;
+            let x1 = b[2].ceil().clamp(0.0, size.width as f32) as u32;
+            let y1 = b[3].ceil().clamp(0.0, size.height as f32) as u32;
+            (x0 < x1 && y0 < y1).then(|| TextureRegion::new(x0, y0, x1 - x0, y1 - y0))
+        };
+        let union = |a: Option<[f32; 4]>, b: [f32; 4]| match a {
+            Some(a) => [
+                a[0].min(b[0]),
+                a[1].min(b[1]),
+                a[2].max(b[2]),
+                a[3].max(b[3]),
+            ],
+            None => b,
         };
+        let mut regions = Vec::with_capacity(meshes.len());
+        // Bounds of what has been drawn into each open layer, and the
+        // margin its blur spreads them by.
+        let mut layers: Vec<(Option<[f32; 4]>, f32)> = Vec::new();
+        for (i, mesh) in meshes.iter().enumerate() {
+            let (region, bounds) = match mesh.layer {
+                Some(LayerOp::Begin { blur_radius, .. }) => {
+                    layers.push((None, blur_margin(blur_radius, scale) as f32));
+                    (None, None)
+                }
+                Some(LayerOp::End) => {
+                    let clip = self.scissor(&mesh.clip, (size.width, size.height), margins[i]);
+                    let region = layers
+                        .pop()
+                        .and_then(|(bounds, m)| {
+                            bounds.map(|b| [b[0] - m, b[1] - m, b[2] + m, b[3] + m])
+                        })
+                        .and_then(to_region)
+                        .zip(clip)
+                        .and_then(|(a, b)| intersect_regions(a, b));
+                    let bounds = region.map(|r| {
+                        [
+                            r.x as f32,
+                            r.y as f32,
+                            (r.x + r.width) as f32,
+                            (r.y + r.height) as f32,
+                        ]
+                    });
+                    (region, bounds)
+                }
+                None => {
+                    let margin = mesh
+                        .backdrop_blur
+                        .map_or(0.0, |blur_radius| blur_margin(blur_radius, scale) as f32);
+                    let bounds = self.mesh_bounds(mesh, margin);
+                    (mesh.backdrop_blur.and(bounds).and_then(to_region), bounds)
+                }
+            };
+            if let (Some((top, _)), Some(bounds)) = (layers.last_mut(), bounds) {
+                *top = Some(union(*top, bounds));
+            }
+            regions.push(region);
+        }
+        regions
+    }
 
-        let pipeline = RenderPipeline::new(&device, viewport, color_format);
+    /// Makes sure there are `count` layer textures matching a target of
+    /// `format` and `size`.
+    fn ensure_layers(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        size: wgpu::Extent3d,
+        count: usize,
+    ) {
+        if self
+            .layers
+            .first()
+            .is_some_and(|l| l.format() != format || l.size() != size)
+        {
+            self.layers.drain(..).for_each(|l| l.destroy());
+        }
+        while self.layers.len() < count {
+            self.layers
+                .push(device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("rpp_layer"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC
+                        | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                }));
+        }
+    }
 
-        let surface = match wgpu_surface {
-            Some(wgpu_surface) => {
-                let config = wgpu_surface
-                    .get_default_config(&adapter, viewport.width as u32, viewport.height as u32)
-                    .unwrap();
-                wgpu_surface.configure(&device, &config);
-                Some(Surface {
-                    surface: wgpu_surface,
-                    config: config,
-                })
+    /// Makes sure the backdrop textures can hold `width` x `height` texels
+    /// of `format`.
+    fn ensure_backdrop(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) {
+        if let Some(backdrop) = &self.backdrop
+            && backdrop.texture.format() == format
+            && backdrop.texture.width() >= width
+            && backdrop.texture.height() >= height
+        {
+            return;
+        }
+        let (width, height) = match self.backdrop.take() {
+            Some(backdrop) => {
+                let size = (
+                    backdrop.texture.width().max(width),
+                    backdrop.texture.height().max(height),
+                );
+                backdrop.texture.destroy();
+                backdrop.scratch.destroy();
+                size
             }
-            None => None,
+            None => (width, height),
+        };
+        let create = |label, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | usage,
+                view_formats: &[],
+            })
         };
+        let texture = create(
+            "rpp_backdrop",
+            wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+        );
+        let scratch = create("rpp_backdrop_scratch", wgpu::TextureUsages::empty());
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(
+                        &self.samplers[Sampling::Linear.index()],
+                    ),
+                },
+            ],
+            label: Some("backdrop_bind_group"),
+        });
+        self.backdrop = Some(Backdrop {
+            texture,
+            scratch,
+            bind_group,
+        });
+    }
 
-        Ok(WgpuDevice {
-            device,
-            surface,
-            queue,
-            pipeline,
-        })
+    /// Makes sure there are `count` multisampled textures matching a target
+    /// of `format` and `size`, unless MSAA is off.
+    fn ensure_msaa(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        size: wgpu::Extent3d,
+        count: usize,
+    ) {
+        let sample_count = self.msaa.sample_count();
+        if self.msaa_textures.first().is_some_and(|t| {
+            t.format() != format || t.size() != size || t.sample_count() != sample_count
+        }) || sample_count == 1
+        {
+            self.msaa_textures.drain(..).for_each(|t| t.destroy());
+        }
+        if sample_count == 1 {
+            return;
+        }
+        while self.msaa_textures.len() < count {
+            self.msaa_textures
+                .push(device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("rpp_msaa"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                    view_formats: &[],
+                }));
+        }
     }
 
-    #[cfg(feature = "rwh")]
-    pub async fn with_window<T: WindowHandle + 'a>(
-        window: T,
-        viewport: &Viewport,
-        color_format: Option<wgpu::TextureFormat>,
-    ) -> WgpuResult<Self> {
-        let instance = Self::create_instance();
-        let surface = Self::create_surface_rwh_window(&instance, window)?;
-        let adapter = Self::init_adapter(&instance, Some(&surface)).await?;
-        let color_format = color_format.unwrap_or_else(|| {
-            let swapchain_capabilities = surface.get_capabilities(&adapter);
-            swapchain_capabilities.formats[0]
+    /// Makes sure the stencil attachment matches a target of `width` x
+    /// `height` pixels.
+    fn ensure_stencil(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        let sample_count = self.msaa.sample_count();
+        if let Some(stencil) = &self.stencil
+            && stencil.width() == width
+            && stencil.height() == height
+            && stencil.sample_count() == sample_count
+        {
+            return;
+        }
+        if let Some(stencil) = self.stencil.take() {
+            stencil.destroy();
+        }
+        self.stencil = Some(device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("rpp_stencil"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: STENCIL_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        }));
+    }
+
+    /// Uploads `meshes`. Backdrop meshes sample the backdrop texture,
+    /// holding the region at the same index of `backdrops`.
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        meshes: &[Mesh],
+        backdrops: &[Option<TextureRegion>],
+    ) -> WgpuResult<Option<PreparedMeshes>> {
+        let scale = self.viewport.scale_factor();
+        let backdrop_size = self
+            .backdrop
+            .as_ref()
+            .map(|b| [b.texture.width() as f32, b.texture.height() as f32]);
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut ranges = Vec::with_capacity(meshes.len());
+        for (i, mesh) in meshes.iter().enumerate() {
+            if let Some(id) = mesh.texture
+                && self.textures.get(id).is_none()
+            {
+                return Err(WgpuErr::TextureNotFound(id));
+            }
+            let first_index = indices.len() as u32;
+            ranges.push((
+                first_index..first_index + mesh.indices.len() as u32,
+                vertices.len() as i32,
+            ));
+            indices.extend_from_slice(&mesh.indices);
+            match (backdrops.get(i).copied().flatten(), backdrop_size) {
+                (Some(region), Some(size)) => {
+                    vertices.extend(mesh.vertices.iter().map(|v| {
+                        let position = mesh.transform.transform_point(v.position.into());
+                        WgpuVertex {
+                            tex_coords: [
+                                (position.x() * scale - region.x as f32) / size[0],
+                                (position.y() * scale - region.y as f32) / size[1],
+                            ],
+                            ..*v
+                        }
+                    }));
+                }
+                _ => match mesh.texture.filter(|_| mesh.texel_coords) {
+                    Some(id) => {
+                        let size = self.textures.get(id).map_or([1.0; 2], |t| t.size().s);
+                        vertices.extend(mesh.vertices.iter().map(|v| WgpuVertex {
+                            tex_coords: [v.tex_coords[0] / size[0], v.tex_coords[1] / size[1]],
+                            ..*v
+                        }));
+                    }
+                    None => vertices.extend_from_slice(&mesh.vertices),
+                },
+            }
+        }
+        if indices.is_empty() {
+            return Ok(None);
+        }
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
         });
+        self.stats.vertices += vertices.len() as u64;
+        self.stats.buffer_bytes += (std::mem::size_of_val(vertices.as_slice())
+            + std::mem::size_of_val(indices.as_slice())) as u64;
+        let (bind_group, offsets) = self.prepare_draws(device, queue, meshes);
+        Ok(Some(PreparedMeshes {
+            vertex_buffer,
+            index_buffer,
+            bind_group,
+            offsets,
+            ranges,
+        }))
+    }
 
-        Self::init(&adapter, Some(surface), viewport, color_format).await
+    /// Scissor rect of `clip` in pixels of a target of `size`, or `None` if
+    /// nothing can be drawn. While encoding damage, drawing is restricted
+    /// to the damaged pixels grown by `margin`.
+    fn scissor(&self, clip: &MeshClip, size: (u32, u32), margin: u32) -> Option<TextureRegion> {
+        let region = match clip.scissor {
+            None => TextureRegion::new(0, 0, size.0, size.1),
+            Some(rect) => {
+                let scale = self.viewport.scale_factor();
+                let snap = |v: f32, max: u32| ((v * scale).round().max(0.0) as u32).min(max);
+                let (min, max) = (rect.min(), rect.max());
+                let (x0, y0) = (snap(min.x(), size.0), snap(min.y(), size.1));
+                let (x1, y1) = (snap(max.x(), size.0), snap(max.y(), size.1));
+                TextureRegion::new(x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0))
+            }
+        };
+        let region = match self.damage {
+            Some(damage) => intersect_regions(region, outset_region(damage, margin, size))?,
+            None => region,
+        };
+        (region.width > 0 && region.height > 0).then_some(region)
     }
 
-    pub async fn new(
-        viewport: &Viewport,
-        color_format: Option<wgpu::TextureFormat>,
-    ) -> WgpuResult<Self> {
-        let instance = Self::create_instance();
-        let adapter = Self::init_adapter(&instance, None).await?;
-
-        Self::init(
-            &adapter,
-            None,
-            viewport,
-            color_format.unwrap_or(wgpu::TextureFormat::Rgba8UnormSrgb),
-        )
-        .await
+    /// Records the meshes in `range` into `pass`, drawing into a target of
+    /// `format` and `size` pixels. Their pipelines must be prepared.
+    /// `margins` are the ones of [`blur_margins`], or empty. Returns the
+    /// number of draw calls.
+    #[allow(clippy::too_many_arguments)]
+    fn record(
+        &self,
+        pass: &mut wgpu::RenderPass<'_>,
+        prepared: &PreparedMeshes,
+        meshes: &[Mesh],
+        margins: &[u32],
+        range: Range<usize>,
+        format: wgpu::TextureFormat,
+        size: (u32, u32),
+    ) -> u64 {
+        pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+        pass.set_vertex_buffer(0, prepared.vertex_buffer.slice(..));
+        pass.set_index_buffer(prepared.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+        let mut draws = 0;
+        for i in range {
+            let mesh = &meshes[i];
+            if mesh.layer.is_some() {
+                continue;
+            }
+            let margin = margins.get(i).copied().unwrap_or(0);
+            let Some(scissor) = self.scissor(&mesh.clip, size, margin) else {
+                continue;
+            };
+            let key =
+                PipelineKey::for_mesh(mesh, format, self.msaa.sample_count(), self.color_space);
+            let Some(pipeline) = self.pipelines.get(&key) else {
+                continue;
+            };
+            pass.set_pipeline(pipeline);
+            pass.set_stencil_reference(mesh.clip.depth);
+            pass.set_scissor_rect(scissor.x, scissor.y, scissor.width, scissor.height);
+            let bind_group = match (mesh.backdrop_blur, mesh.texture, &self.backdrop) {
+                (Some(_), _, Some(backdrop)) => &backdrop.bind_group,
+                (None, Some(id), _) => self
+                    .textures
+                    .get(id)
+                    .unwrap_or(&self.white_texture)
+                    .bind_group(mesh.sampling),
+                _ => self.white_texture.bind_group(mesh.sampling),
+            };
+            let (indices, base_vertex) = prepared.ranges[i].clone();
+            pass.set_bind_group(1, bind_group, &[]);
+            pass.set_bind_group(2, &prepared.bind_group, &[prepared.offsets[i]]);
+            pass.draw_indexed(indices, base_vertex, 0..1);
+            draws += 1;
+        }
+        draws
     }
-}
 
-pub async fn create_device<'a>(viewport: &Viewport) -> WgpuResult<WgpuDevice<'a>> {
-    WgpuDevice::new(viewport, None).await
+    /// Records `meshes` into `pass`. Backdrop blurs are ignored, as they
+    /// need to read the render target, and layers are drawn directly into
+    /// the pass, without opacity nor blend mode.
+    ///
+    /// The pass needs a [`STENCIL_FORMAT`] attachment cleared to zero, and
+    /// attachments with the sample count of [`RenderPipeline::msaa`].
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pass: &mut wgpu::RenderPass<'_>,
+        meshes: &[Mesh],
+    ) -> WgpuResult<()> {
+        self.prepare_pipelines(device, meshes, self.color_format)?;
+        if let Some(prepared) = self.prepare(device, queue, meshes, &[])? {
+            let drawn = meshes
+                .iter()
+                .enumerate()
+                .filter(|(_, m)| m.backdrop_blur.is_none());
+            let size = self.viewport.physical_size();
+            for (i, _) in drawn {
+                self.stats.draw_calls += self.record(
+                    pass,
+                    &prepared,
+                    meshes,
+                    &[],
+                    i..i + 1,
+                    self.color_format,
+                    size,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Records `meshes` drawn into `target` into `encoder`, splitting the
+    /// render pass around backdrop blurs and layers.
+    ///
+    /// Backdrop blurs and layers with a blend mode other than
+    /// [`BlendMode::Normal`] need `target` to have the
+    /// [`wgpu::TextureUsages::COPY_SRC`] usage. With [`Msaa`], loading
+    /// `target` needs the [`wgpu::TextureUsages::TEXTURE_BINDING`] usage.
+    pub fn encode(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::Texture,
+        load: wgpu::LoadOp<wgpu::Color>,
+        meshes: &[Mesh],
+    ) -> WgpuResult<()> {
+        let meshes = &close_layers(meshes)[..];
+        let ends = layer_ends(meshes);
+        let reads_target = |i: usize| {
+            meshes[i].backdrop_blur.is_some()
+                || ends[i].is_some_and(|(_, blend_mode, _)| blend_mode != BlendMode::Normal)
+        };
+        if (0..meshes.len()).any(reads_target)
+            && !target.usage().contains(wgpu::TextureUsages::COPY_SRC)
+        {
+            return Err(WgpuErr::BackdropNotReadable);
+        }
+        let scale = self.viewport.scale_factor();
+        let size = (target.width(), target.height());
+        let margins = blur_margins(meshes, scale);
+        let regions = self.target_regions(meshes, &margins, target.size());
+        // Region of the layer blurred before each end, with the pixels
+        // around the composited region it spreads, and the sigma.
+        let blurs: Vec<Option<(TextureRegion, f32)>> = (0..meshes.len())
+            .map(|i| {
+                let (_, _, blur_radius) = ends[i]?;
+                let margin = blur_margin(blur_radius, scale);
+                let region = outset_region(regions[i].filter(|_| margin > 0)?, margin, size);
+                Some((region, effect::blur_sigma(blur_radius) * scale))
+            })
+            .collect();
+        // The backdrop texture also holds the layers being blurred.
+        let (width, height) = (0..meshes.len())
+            .filter(|&i| reads_target(i))
+            .filter_map(|i| regions[i])
+            .chain(blurs.iter().flatten().map(|&(region, _)| region))
+            .fold((0, 0), |(w, h), r| (w.max(r.width), h.max(r.height)));
+        if width > 0 && height > 0 {
+            self.ensure_backdrop(device, target.format(), width, height);
+        }
+        let (mut depth, mut max_depth) = (0usize, 0);
+        for mesh in meshes {
+            match mesh.layer {
+                Some(LayerOp::Begin { .. }) => depth += 1,
+                Some(LayerOp::End) => depth = depth.saturating_sub(1),
+                None => {}
+            }
+            max_depth = max_depth.max(depth);
+        }
+        let msaa = self.msaa != Msaa::Off;
+        if msaa
+            && matches!(load, wgpu::LoadOp::Load)
+            && !target
+                .usage()
+                .contains(wgpu::TextureUsages::TEXTURE_BINDING)
+        {
+            return Err(WgpuErr::TargetNotLoadable);
+        }
+        self.ensure_layers(device, target.format(), target.size(), max_depth);
+        self.ensure_msaa(device, target.format(), target.size(), max_depth + 1);
+        self.prepare_pipelines(device, meshes, target.format())?;
+        let prepared = self.prepare(device, queue, meshes, &regions)?;
+        self.ensure_stencil(device, target.width(), target.height());
+
+        // Textures drawn into, by layer depth.
+        let targets: Vec<wgpu::Texture> = std::iter::once(target)
+            .chain(&self.layers[..max_depth])
+            .cloned()
+            .collect();
+        let views: Vec<wgpu::TextureView> = targets
+            .iter()
+            .map(|t| t.create_view(&wgpu::TextureViewDescriptor::default()))
+            .collect();
+        // With MSAA, passes draw into the multisampled textures and resolve
+        // into the targets.
+        let attachments: Vec<Attachment> = views
+            .iter()
+            .enumerate()
+            .map(|(depth, view)| match self.msaa_textures.get(depth) {
+                Some(texture) if msaa => Attachment {
+                    view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+                    resolve_target: Some(view.clone()),
+                },
+                _ => Attachment {
+                    view: view.clone(),
+                    resolve_target: None,
+                },
+            })
+            .collect();
+        let stencil_view = self
+            .stencil
+            .as_ref()
+            .map(|s| s.create_view(&wgpu::TextureViewDescriptor::default()))
+            .unwrap();
+        let backdrop_view = self.backdrop.as_ref().map(|b| {
+            b.texture
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        let copy_target = msaa && matches!(load, wgpu::LoadOp::Load) && !self.msaa_loaded;
+        let full = TextureRegion::new(0, 0, size.0, size.1);
+        let composites: Vec<CompositeUniforms> = copy_target
+            .then(|| CompositeUniforms::new(full, 1.0, BlendMode::Normal))
+            .into_iter()
+            .chain((0..meshes.len()).filter_map(|i| {
+                let (opacity, blend_mode, _) = ends[i]?;
+                Some(CompositeUniforms::new(regions[i]?, opacity, blend_mode))
+            }))
+            .collect();
+        self.stats.buffer_bytes += self.composite.prepare(device, &composites);
+        // Load operation of the next pass drawing into each target.
+        let mut loads = vec![load];
+        let mut stencil_load = wgpu::LoadOp::Clear(0);
+        if copy_target {
+            // The multisampled texture starts with a copy of the target, as
+            // the whole target is resolved into.
+            let copy = Attachment {
+                view: attachments[0].view.clone(),
+                resolve_target: None,
+            };
+            let mut pass = begin_pass(
+                encoder,
+                &copy,
+                &stencil_view,
+                wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                stencil_load,
+            );
+            stencil_load = wgpu::LoadOp::Load;
+            self.composite
+                .composite(device, &mut pass, target.format(), &views[0], None, full, 0);
+            self.stats.draw_calls += 1;
+        }
+        let mut draws = 0;
+        let mut depth = 0;
+        let mut start = 0;
+        let pass_over = |this: &Self,
+                         encoder: &mut wgpu::CommandEncoder,
+                         load: &mut wgpu::LoadOp<wgpu::Color>,
+                         stencil_load: &mut wgpu::LoadOp<u32>,
+                         attachment: &Attachment,
+                         range: Range<usize>| {
+            let mut pass = begin_pass(encoder, attachment, &stencil_view, *load, *stencil_load);
+            *load = wgpu::LoadOp::Load;
+            *stencil_load = wgpu::LoadOp::Load;
+            match &prepared {
+                Some(prepared) => {
+                    let format = target.format();
+                    this.record(&mut pass, prepared, meshes, &margins, range, format, size)
+                }
+                None => 0,
+            }
+        };
+        for (i, mesh) in meshes.iter().enumerate() {
+            if mesh.backdrop_blur.is_none() && mesh.layer.is_none() {
+                continue;
+            }
+            draws += pass_over(
+                self,
+                encoder,
+                &mut loads[depth],
+                &mut stencil_load,
+                &attachments[depth],
+                start..i,
+            );
+            start = i + 1;
+            match mesh.layer {
+                Some(LayerOp::Begin { .. }) => {
+                    depth += 1;
+                    loads.push(wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT));
+                }
+                Some(LayerOp::End) => {
+                    let Some((_, blend_mode, _)) = ends[i] else {
+                        continue;
+                    };
+                    depth -= 1;
+                    loads.pop();
+                    let Some(region) = regions[i] else {
+                        continue;
+                    };
+                    if let (Some((blurred, sigma)), Some(backdrop)) = (blurs[i], &self.backdrop) {
+                        let layer = &targets[depth + 1];
+                        copy_region(encoder, layer, &backdrop.texture, blurred);
+                        draws += self.blur.blur(
+                            device,
+                            encoder,
+                            &backdrop.texture,
+                            &backdrop.scratch,
+                            blurred.width,
+                            blurred.height,
+                            sigma,
+                        );
+                        copy_into_region(encoder, &backdrop.texture, layer, blurred);
+                    }
+                    let backdrop = match (blend_mode, &self.backdrop) {
+                        (BlendMode::Normal, _) | (_, None) => None,
+                        (_, Some(backdrop)) => {
+                            copy_region(encoder, &targets[depth], &backdrop.texture, region);
+                            backdrop_view.as_ref()
+                        }
+                    };
+                    let mut pass = begin_pass(
+                        encoder,
+                        &attachments[depth],
+                        &stencil_view,
+                        loads[depth],
+                        stencil_load,
+                    );
+                    loads[depth] = wgpu::LoadOp::Load;
+                    stencil_load = wgpu::LoadOp::Load;
+                    self.composite.composite(
+                        device,
+                        &mut pass,
+                        target.format(),
+                        &views[depth + 1],
+                        backdrop,
+                        region,
+                        mesh.clip.depth,
+                    );
+                    draws += 1;
+                }
+                None => {
+                    let (Some(region), Some(backdrop), Some(blur_radius)) =
+                        (regions[i], &self.backdrop, mesh.backdrop_blur)
+                    else {
+                        continue;
+                    };
+                    copy_region(encoder, &targets[depth], &backdrop.texture, region);
+                    let sigma = effect::blur_sigma(blur_radius) * scale;
+                    draws += self.blur.blur(
+                        device,
+                        encoder,
+                        &backdrop.texture,
+                        &backdrop.scratch,
+                        region.width,
+                        region.height,
+                        sigma,
+                    );
+                    draws += pass_over(
+                        self,
+                        encoder,
+                        &mut loads[depth],
+                        &mut stencil_load,
+                        &attachments[depth],
+                        i..i + 1,
+                    );
+                }
+            }
+        }
+        let range = start..meshes.len();
+        draws += pass_over(
+            self,
+            encoder,
+            &mut loads[depth],
+            &mut stencil_load,
+            &attachments[depth],
+            range,
+        );
+        self.stats.draw_calls += draws;
+        Ok(())
+    }
+
+    /// Records `meshes` drawn into `target` into `encoder`, redrawing only
+    /// the pixels inside the `damage` rects. The rest of `target` keeps its
+    /// contents, which must be the previous frame.
+    ///
+    /// The damaged pixels are cleared to transparent first, so that removed
+    /// meshes disappear and translucent ones are not blended twice. Each
+    /// rect is then encoded separately, see [`MAX_DAMAGE_RECTS`].
+    ///
+    /// [`MAX_DAMAGE_RECTS`]: crate::MAX_DAMAGE_RECTS
+    pub fn encode_damage(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::Texture,
+        meshes: &[Mesh],
+        damage: &[Rect],
+    ) -> WgpuResult<()> {
+        let scale = self.viewport.scale_factor();
+        let (width, height) = (target.width(), target.height());
+        // Pixels touched by the rects.
+        let regions: Vec<TextureRegion> = damage
+            .iter()
+            .filter_map(|rect| {
+                let (min, max) = (rect.min(), rect.max());
+                let x0 = ((min.x() * scale).floor().max(0.0) as u32).min(width);
+                let y0 = ((min.y() * scale).floor().max(0.0) as u32).min(height);
+                let x1 = ((max.x() * scale).ceil().max(0.0) as u32).min(width);
+                let y1 = ((max.y() * scale).ceil().max(0.0) as u32).min(height);
+                (x0 < x1 && y0 < y1).then(|| TextureRegion::new(x0, y0, x1 - x0, y1 - y0))
+            })
+            .collect();
+        if regions.is_empty() {
+            return Ok(());
+        }
+
+        let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("rpp_clear_damage"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        self.composite
+            .clear(device, &mut pass, target.format(), &regions);
+        drop(pass);
+        self.stats.draw_calls += regions.len() as u64;
+
+        // With MSAA, the target is copied into the multisampled texture for
+        // the first rect only, which then keeps the target's contents.
+        let mut result = Ok(());
+        for region in regions {
+            self.damage = Some(region);
+            result = self.encode(device, queue, encoder, target, wgpu::LoadOp::Load, meshes);
+            self.msaa_loaded = true;
+            if result.is_err() {
+                break;
+            }
+        }
+        self.damage = None;
+        self.msaa_loaded = false;
+        result
+    }
+}
+
+/// `meshes` with a [`LayerOp::End`] appended for each layer left open,
+/// inside the clip of its [`LayerOp::Begin`].
+fn close_layers(meshes: &[Mesh]) -> Cow<'_, [Mesh]> {
+    let mut open = Vec::new();
+    for mesh in meshes {
+        match mesh.layer {
+            Some(LayerOp::Begin { .. }) => open.push(mesh.clip),
+            Some(LayerOp::End) => {
+                open.pop();
+            }
+            None => {}
+        }
+    }
+    if open.is_empty() {
+        return Cow::Borrowed(meshes);
+    }
+    let ends = open.into_iter().rev().map(|clip| Mesh {
+        clip,
+        layer: Some(LayerOp::End),
+        ..Mesh::default()
+    });
+    Cow::Owned(meshes.iter().cloned().chain(ends).collect())
+}
+
+/// Opacity, blend mode and blur radius of the layer each [`LayerOp::End`]
+/// of `meshes` composites, `None` for other meshes and ends without a
+/// layer.
+fn layer_ends(meshes: &[Mesh]) -> Vec<Option<(f32, BlendMode, f32)>> {
+    let mut open = Vec::new();
+    meshes
+        .iter()
+        .map(|mesh| match mesh.layer {
+            Some(LayerOp::Begin {
+                opacity,
+                blend_mode,
+                blur_radius,
+            }) => {
+                open.push((opacity, blend_mode, blur_radius));
+                None
+            }
+            Some(LayerOp::End) => open.pop(),
+            None => None,
+        })
+        .collect()
+}
+
+/// Number of pixels a blur of `blur_radius` in viewport units spreads
+/// content by, at `scale` pixels per unit.
+fn blur_margin(blur_radius: f32, scale: f32) -> u32 {
+    effect::kernel_radius(effect::blur_sigma(blur_radius) * scale)
+}
+
+/// Pixels each mesh of `meshes` has to be drawn past the damage, for the
+/// blurred layers containing it to spread into the damage.
+fn blur_margins(meshes: &[Mesh], scale: f32) -> Vec<u32> {
+    let mut open = Vec::new();
+    meshes
+        .iter()
+        .map(|mesh| {
+            match mesh.layer {
+                Some(LayerOp::Begin { blur_radius, .. }) => {
+                    open.push(blur_margin(blur_radius, scale))
+                }
+                Some(LayerOp::End) => {
+                    open.pop();
+                }
+                None => {}
+            }
+            open.iter().sum()
+        })
+        .collect()
+}
+
+/// `region` grown by `margin` on each side, inside a target of `size`.
+fn outset_region(region: TextureRegion, margin: u32, size: (u32, u32)) -> TextureRegion {
+    let (x0, y0) = (
+        region.x.saturating_sub(margin),
+        region.y.saturating_sub(margin),
+    );
+    let x1 = (region.x + region.width + margin).min(size.0);
+    let y1 = (region.y + region.height + margin).min(size.1);
+    TextureRegion::new(x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0))
+}
+
+/// Overlap of both regions, or `None` if it is empty.
+fn intersect_regions(a: TextureRegion, b: TextureRegion) -> Option<TextureRegion> {
+    let (x0, y0) = (a.x.max(b.x), a.y.max(b.y));
+    let x1 = (a.x + a.width).min(b.x + b.width);
+    let y1 = (a.y + a.height).min(b.y + b.height);
+    (x0 < x1 && y0 < y1).then(|| TextureRegion::new(x0, y0, x1 - x0, y1 - y0))
+}
+
+/// Color attachment of the passes drawing into a target.
+struct Attachment {
+    view: wgpu::TextureView,
+    /// Target the multisampled `view` is resolved into.
+    resolve_target: Option<wgpu::TextureView>,
+}
+
+fn begin_pass<'e>(
+    encoder: &'e mut wgpu::CommandEncoder,
+    attachment: &Attachment,
+    stencil_view: &wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    stencil_load: wgpu::LoadOp<u32>,
+) -> wgpu::RenderPass<'e> {
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("rpp_pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: &attachment.view,
+            depth_slice: None,
+            resolve_target: attachment.resolve_target.as_ref(),
+            ops: wgpu::Operations {
+                load,
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: stencil_view,
+            depth_ops: None,
+            stencil_ops: Some(wgpu::Operations {
+                load: stencil_load,
+                store: wgpu::StoreOp::Store,
+            }),
+        }),
+        timestamp_writes: None,
+        occlusion_query_set: None,
+    })
+}
+
+/// Presentation mode of the surface.
+///
+/// Modes the surface does not support fall back to [`PresentMode::Vsync`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Frames are presented at the display refresh rate without tearing.
+    #[default]
+    Vsync,
+    /// Frames are presented at the refresh rate; newer frames replace the
+    /// queued one without blocking.
+    Mailbox,
+    /// Frames are presented immediately, which may tear.
+    Immediate,
+}
+
+impl PresentMode {
+    fn to_wgpu(self, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        let mode = match self {
+            PresentMode::Vsync => wgpu::PresentMode::Fifo,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        };
+        if supported.contains(&mode) {
+            mode
+        } else {
+            wgpu::PresentMode::Fifo
+        }
+    }
+}
+
+/// Multisample anti-aliasing of the render targets, smoothing the edges of
+/// meshes without analytic anti-aliasing, such as tessellated paths.
+///
+/// Meshes are drawn into multisampled textures resolved into the targets.
+/// Modes the adapter does not support fall back to the next lower one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Msaa {
+    #[default]
+    Off,
+    X2,
+    X4,
+}
+
+impl Msaa {
+    /// Samples per pixel.
+    pub fn sample_count(self) -> u32 {
+        match self {
+            Msaa::Off => 1,
+            Msaa::X2 => 2,
+            Msaa::X4 => 4,
+        }
+    }
+
+    /// Highest mode up to `self` that `device` supports for targets of
+    /// `format`.
+    ///
+    /// Without [`wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`]
+    /// only the guaranteed format features apply, whatever the adapter
+    /// reports.
+    fn supported(
+        self,
+        adapter: &wgpu::Adapter,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Msaa {
+        let features = device.features();
+        let adapter_specific =
+            features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        let supports = |mode: Msaa| {
+            [format, STENCIL_FORMAT].iter().all(|&format| {
+                let format_features = if adapter_specific {
+                    adapter.get_texture_format_features(format)
+                } else {
+                    format.guaranteed_format_features(features)
+                };
+                format_features
+                    .flags
+                    .sample_count_supported(mode.sample_count())
+            })
+        };
+        [Msaa::X4, Msaa::X2]
+            .into_iter()
+            .filter(|&mode| mode.sample_count() <= self.sample_count())
+            .find(|&mode| supports(mode))
+            .unwrap_or(Msaa::Off)
+    }
+}
+
+pub struct Surface<'a> {
+    surface: wgpu::Surface<'a>,
+    config: wgpu::SurfaceConfiguration,
+    present_modes: Vec<wgpu::PresentMode>,
+    present_mode: PresentMode,
+}
+
+impl Surface<'_> {
+    fn is_zero_sized(&self) -> bool {
+        self.config.width == 0 || self.config.height == 0
+    }
+
+    fn configure(&self, device: &wgpu::Device) {
+        // Zero sized surfaces can not be configured. They are configured
+        // again once resized.
+        if !self.is_zero_sized() {
+            self.surface.configure(device, &self.config);
+        }
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.config.width = width;
+        self.config.height = height;
+        self.configure(device);
+    }
+
+    fn set_present_mode(&mut self, device: &wgpu::Device, mode: PresentMode) {
+        self.present_mode = mode;
+        self.config.present_mode = mode.to_wgpu(&self.present_modes);
+        self.configure(device);
+    }
+
+    /// Acquires the next frame, reconfiguring the surface if it has been
+    /// lost or become outdated.
+    ///
+    /// Returns `None` if no frame should be drawn this time.
+    fn acquire(&mut self, device: &wgpu::Device) -> WgpuResult<Option<wgpu::SurfaceTexture>> {
+        if self.is_zero_sized() {
+            return Ok(None);
+        }
+        match self.surface.get_current_texture() {
+            Ok(frame) => Ok(Some(frame)),
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.configure(device);
+                match self.surface.get_current_texture() {
+                    Ok(frame) => Ok(Some(frame)),
+                    Err(wgpu::SurfaceError::Timeout | wgpu::SurfaceError::Outdated) => Ok(None),
+                    Err(err) => Err(err.into()),
+                }
+            }
+            Err(wgpu::SurfaceError::Timeout) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Color space the render targets are displayed in, which the sRGB
+/// [`Color`]s of paints and vertices are converted to.
+///
+/// Whatever the color space, targets of `*Srgb` and float formats hold
+/// linear components and the others encoded ones. Float targets are
+/// extended linear sRGB in [`TargetColorSpace::Srgb`], keeping colors
+/// outside the sRGB gamut as components outside `0.0..=1.0`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum TargetColorSpace {
+    #[default]
+    Srgb,
+    /// Display-P3 primaries with the sRGB transfer function. wgpu does not
+    /// tag surfaces with a color space: this must match how the surface or
+    /// the consumer of the targets interprets them.
+    DisplayP3,
+}
+
+/// Returns `true` if colors outside `0.0..=1.0` survive rendering to
+/// `format`, as extended linear sRGB.
+pub fn is_wide_gamut_format(format: wgpu::TextureFormat) -> bool {
+    matches!(
+        format,
+        wgpu::TextureFormat::Rgba16Float | wgpu::TextureFormat::Rgba32Float
+    )
+}
+
+/// Returns `true` if targets of `format` hold linear components, which
+/// colors are decoded to.
+pub(crate) fn is_linear_format(format: wgpu::TextureFormat) -> bool {
+    format.is_srgb() || is_wide_gamut_format(format)
+}
+
+pub struct WgpuDevice<'a> {
+    surface: Option<Surface<'a>>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: RenderPipeline,
+    color_format: wgpu::TextureFormat,
+    lost: Arc<AtomicBool>,
+    /// Last frame drawn by [`WgpuDevice::render_damage`], copied to the
+    /// surface.
+    back_buffer: Option<wgpu::Texture>,
+    pipeline_cache_key: Option<String>,
+    /// Whether [`FrameStats`] are collected.
+    collect_stats: bool,
+    frame_stats: Option<FrameStats>,
+    /// Times frames on the GPU, if stats are collected and the device has
+    /// timestamp queries.
+    gpu_timer: Option<GpuTimer>,
+    /// GPU time of the last frame read back.
+    gpu_time: Option<Duration>,
+}
+
+impl<'a> WgpuDevice<'a> {
+    #[allow(clippy::too_many_arguments)]
+    fn init(
+        adapter: &wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        lost: Arc<AtomicBool>,
+        wgpu_surface: Option<wgpu::Surface<'a>>,
+        viewport: &Viewport,
+        color_format: wgpu::TextureFormat,
+        present_mode: PresentMode,
+        msaa: Msaa,
+        color_space: TargetColorSpace,
+        pipeline_cache_data: Option<&[u8]>,
+        collect_stats: bool,
+    ) -> WgpuResult<Self> {
+        let pipeline_cache_key = wgpu::util::pipeline_cache_key(&adapter.get_info());
+        // SAFETY: guaranteed by `WgpuDeviceBuilder::pipeline_cache_data`.
+        let mut pipeline = unsafe {
+            RenderPipeline::with_pipeline_cache(
+                &device,
+                &queue,
+                viewport,
+                color_format,
+                pipeline_cache_data,
+            )
+        };
+        pipeline.set_msaa(msaa.supported(adapter, &device, color_format));
+        pipeline.set_color_space(color_space);
+
+        let surface = match wgpu_surface {
+            Some(wgpu_surface) => {
+                let (width, height) = viewport.physical_size();
+                let capabilities = wgpu_surface.get_capabilities(adapter);
+                if !capabilities.formats.contains(&color_format) {
+                    return Err(WgpuErr::UnsupportedSurfaceConfiguration);
+                }
+                let present_modes = capabilities.present_modes;
+                let mut config = wgpu_surface
+                    .get_default_config(adapter, width, height)
+                    .ok_or(WgpuErr::UnsupportedSurfaceConfiguration)?;
+                config.format = color_format;
+                // Backdrop blurs copy from the frame.
+                if capabilities.usages.contains(wgpu::TextureUsages::COPY_SRC) {
+                    config.usage |= wgpu::TextureUsages::COPY_SRC;
+                }
+                // Partial redraws copy into the frame.
+                if capabilities.usages.contains(wgpu::TextureUsages::COPY_DST) {
+                    config.usage |= wgpu::TextureUsages::COPY_DST;
+                }
+                config.present_mode = present_mode.to_wgpu(&present_modes);
+                let surface = Surface {
+                    surface: wgpu_surface,
+                    config,
+                    present_modes,
+                    present_mode,
+                };
+                surface.configure(&device);
+                Some(surface)
+            }
+            None => None,
+        };
+
+        let gpu_timer = if collect_stats {
+            GpuTimer::new(&device, &queue)
+        } else {
+            None
+        };
+        Ok(WgpuDevice {
+            device,
+            surface,
+            queue,
+            pipeline,
+            color_format,
+            lost,
+            back_buffer: None,
+            pipeline_cache_key,
+            collect_stats,
+            frame_stats: None,
+            gpu_timer,
+            gpu_time: None,
+        })
+    }
+
+    pub fn builder() -> WgpuDeviceBuilder {
+        WgpuDeviceBuilder::new()
+    }
+
+    #[cfg(feature = "rwh")]
+    pub async fn with_window<T: WindowHandle + 'a>(
+        window: T,
+        viewport: &Viewport,
+        color_format: Option<wgpu::TextureFormat>,
+    ) -> WgpuResult<Self> {
+        let mut builder = WgpuDeviceBuilder::new();
+        if let Some(format) = color_format {
+            builder = builder.color_format(format);
+        }
+        builder.build_with_window(window, viewport).await
+    }
+
+    /// Returns `true` once the device has been lost. A lost device can not
+    /// render anymore and has to be built again.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Acquire)
+    }
+
+    /// Features enabled on the device.
+    pub fn features(&self) -> wgpu::Features {
+        self.device.features()
+    }
+
+    /// Format of the render targets.
+    pub fn color_format(&self) -> wgpu::TextureFormat {
+        self.color_format
+    }
+
+    /// Multisample anti-aliasing used, which may be lower than requested.
+    pub fn msaa(&self) -> Msaa {
+        self.pipeline.msaa()
+    }
+
+    /// Color space the targets are displayed in.
+    pub fn color_space(&self) -> TargetColorSpace {
+        self.pipeline.color_space()
+    }
+
+    /// Returns `true` if solid paints keep colors outside the sRGB gamut:
+    /// the targets are extended linear sRGB or Display-P3. Vertex colors
+    /// and gradient ramps are always clamped to sRGB, see [`Color::pack`].
+    pub fn is_wide_gamut(&self) -> bool {
+        is_wide_gamut_format(self.color_format) || self.color_space() == TargetColorSpace::DisplayP3
+    }
+
+    /// Registers a custom shader for [`Paint::Shader`], see
+    /// [`PipelineRegistry::register_shader`].
+    pub fn register_shader(&mut self, source: &str) -> WgpuResult<ShaderId> {
+        self.pipeline.register_shader(&self.device, source)
+    }
+
+    /// Sets the time passed to custom shaders, in seconds, such as the time
+    /// since the application started.
+    pub fn set_time(&mut self, time: f32) {
+        self.pipeline.set_time(&self.queue, time);
+    }
+
+    /// Compiles the pipelines of the built-in shaders for the color format,
+    /// which are otherwise compiled when first drawn with.
+    pub fn prepare_pipelines(&mut self) {
+        let msaa = self.pipeline.msaa().sample_count();
+        let color_space = self.pipeline.color_space();
+        self.pipeline.pipelines_mut().prepare_builtin(
+            &self.device,
+            self.color_format,
+            msaa,
+            color_space,
+        );
+    }
+
+    /// Contents of the pipeline cache, to save with
+    /// [`WgpuDevice::pipeline_cache_key`] and give to
+    /// [`WgpuDeviceBuilder::pipeline_cache_data`] on the next run. `None`
+    /// without [`wgpu::Features::PIPELINE_CACHE`].
+    pub fn pipeline_cache_data(&self) -> Option<Vec<u8>> {
+        self.pipeline.pipelines().cache_data()
+    }
+
+    /// Identifies the adapter pipeline cache data is valid for, such as to
+    /// name the file it is saved to. `None` if the backend has no pipeline
+    /// cache.
+    pub fn pipeline_cache_key(&self) -> Option<&str> {
+        self.pipeline_cache_key.as_deref()
+    }
+
+    pub fn create_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        color_space: TextureColorSpace,
+        alpha_mode: TextureAlphaMode,
+        data: &[u8],
+    ) -> WgpuResult<TextureId> {
+        self.pipeline.create_texture(
+            &self.device,
+            &self.queue,
+            width,
+            height,
+            color_space,
+            alpha_mode,
+            data,
+        )
+    }
+
+    pub fn create_image(&mut self, image: &Image, mipmapped: bool) -> WgpuResult<ImageId> {
+        self.pipeline
+            .create_image(&self.device, &self.queue, image, mipmapped)
+    }
+
+    pub fn update_texture(
+        &mut self,
+        id: TextureId,
+        region: TextureRegion,
+        data: &[u8],
+    ) -> WgpuResult<()> {
+        self.pipeline
+            .update_texture(&self.device, &self.queue, id, region, data)
+    }
+
+    /// Packs `image` into `atlas`, see [`TextureAtlas::insert`].
+    pub fn insert_into_atlas<K: Hash + Eq + Clone>(
+        &mut self,
+        atlas: &mut TextureAtlas<K>,
+        key: K,
+        image: &Image,
+    ) -> WgpuResult<AtlasEntry> {
+        atlas.insert(&mut self.pipeline, &self.device, &self.queue, key, image)
+    }
+
+    /// Destroys the pages of `atlas`, see [`TextureAtlas::destroy`].
+    pub fn destroy_atlas<K: Hash + Eq + Clone>(
+        &mut self,
+        atlas: &mut TextureAtlas<K>,
+    ) -> WgpuResult<()> {
+        atlas.destroy(&mut self.pipeline)
+    }
+
+    pub fn destroy_texture(&mut self, id: TextureId) -> WgpuResult<()> {
+        self.pipeline.destroy_texture(id)
+    }
+
+    pub fn texture(&self, id: TextureId) -> Option<&Texture> {
+        self.pipeline.texture(id)
+    }
+
+    /// Draws `meshes` into `texture`, clearing it first.
+    pub fn render_to_texture(
+        &mut self,
+        texture: &wgpu::Texture,
+        meshes: &[Mesh],
+    ) -> WgpuResult<()> {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("rpp_encoder"),
+            });
+        let started = self.begin_frame(&mut encoder);
+        self.pipeline.encode(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            texture,
+            wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+            meshes,
+        )?;
+        self.submit_frame(encoder, started);
+        Ok(())
+    }
+
+    /// Statistics of the last frame drawn, if enabled by
+    /// [`WgpuDeviceBuilder::frame_stats`].
+    pub fn frame_stats(&self) -> Option<&FrameStats> {
+        self.frame_stats.as_ref()
+    }
+
+    /// Starts timing a frame recorded into `encoder`, if stats are
+    /// collected. Returns when it started.
+    fn begin_frame(&mut self, encoder: &mut wgpu::CommandEncoder) -> Option<Instant> {
+        if !self.collect_stats {
+            return None;
+        }
+        let started = Instant::now();
+        if let Some(timer) = &mut self.gpu_timer {
+            if let Some(time) = timer.poll(&self.device) {
+                self.gpu_time = Some(time);
+            }
+            timer.begin(encoder);
+        }
+        Some(started)
+    }
+
+    /// Submits the frame recorded into `encoder`, and collects its stats
+    /// if it has been started by [`WgpuDevice::begin_frame`].
+    fn submit_frame(&mut self, mut encoder: wgpu::CommandEncoder, started: Option<Instant>) {
+        if let (Some(timer), Some(_)) = (&mut self.gpu_timer, started) {
+            timer.end(&mut encoder);
+        }
+        self.queue.submit(Some(encoder.finish()));
+        let stats = self.pipeline.take_stats();
+        let Some(started) = started else {
+            return;
+        };
+        if let Some(timer) = &mut self.gpu_timer {
+            timer.submitted();
+        }
+        self.frame_stats = Some(FrameStats {
+            encode_time: started.elapsed(),
+            gpu_time: self.gpu_time,
+            ..stats
+        });
+    }
+
+    /// Draws `meshes` into the next frame of the surface and presents it.
+    ///
+    /// Lost or outdated surfaces are reconfigured automatically. Nothing is
+    /// drawn while the surface is zero sized or no frame is available in
+    /// time.
+    pub fn render(&mut self, meshes: &[Mesh]) -> WgpuResult<()> {
+        let surface = self.surface.as_mut().ok_or(WgpuErr::NoSurface)?;
+        let Some(frame) = surface.acquire(&self.device)? else {
+            return Ok(());
+        };
+        let suboptimal = frame.suboptimal;
+        self.render_to_texture(&frame.texture, meshes)?;
+        frame.present();
+        // The back buffer no longer holds the last frame.
+        if let Some(back_buffer) = self.back_buffer.take() {
+            back_buffer.destroy();
+        }
+
+        if suboptimal && let Some(surface) = &self.surface {
+            surface.configure(&self.device);
+        }
+        Ok(())
+    }
+
+    /// Records the passes added by `build` into a single command encoder,
+    /// drawing into the next frame of the surface, and presents it. `build`
+    /// is given the frame, imported into the graph.
+    pub fn render_graph<'g>(
+        &mut self,
+        build: impl FnOnce(&mut RenderGraph<'g>, GraphTexture),
+    ) -> WgpuResult<()> {
+        let surface = self.surface.as_mut().ok_or(WgpuErr::NoSurface)?;
+        let Some(frame) = surface.acquire(&self.device)? else {
+            return Ok(());
+        };
+        let suboptimal = frame.suboptimal;
+        let mut graph = RenderGraph::new();
+        let target = graph.import(&frame.texture);
+        build(&mut graph, target);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("rpp_encoder"),
+            });
+        let started = self.begin_frame(&mut encoder);
+        self.pipeline
+            .encode_graph(&self.device, &self.queue, &mut encoder, graph)?;
+        self.submit_frame(encoder, started);
+        frame.present();
+        // The back buffer no longer holds the last frame.
+        if let Some(back_buffer) = self.back_buffer.take() {
+            back_buffer.destroy();
+        }
+
+        if suboptimal && let Some(surface) = &self.surface {
+            surface.configure(&self.device);
+        }
+        Ok(())
+    }
+
+    /// Draws the `damage` of `meshes` into the next frame of the surface
+    /// and presents it. Nothing is presented if nothing is damaged.
+    ///
+    /// Frames are drawn into a back buffer keeping the last frame, which is
+    /// then copied to the surface. Only the damaged pixels are drawn, except
+    /// when the back buffer is new, such as after a resize or
+    /// [`WgpuDevice::render`]. Surfaces that can not be copied into are
+    /// fully redrawn. The whole frame is presented, as wgpu has no damage
+    /// hints for presentation.
+    pub fn render_damage(&mut self, meshes: &[Mesh], damage: &Damage) -> WgpuResult<()> {
+        let surface = self.surface.as_mut().ok_or(WgpuErr::NoSurface)?;
+        if damage.is_empty() {
+            return Ok(());
+        }
+        if !surface.config.usage.contains(wgpu::TextureUsages::COPY_DST) {
+            return self.render(meshes);
+        }
+        let Some(frame) = surface.acquire(&self.device)? else {
+            return Ok(());
+        };
+        let suboptimal = frame.suboptimal;
+        let size = frame.texture.size();
+        let fresh = self.ensure_back_buffer(size);
+        let back_buffer = self.back_buffer.clone().unwrap();
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("rpp_encoder"),
+            });
+        let started = self.begin_frame(&mut encoder);
+        match damage.rects() {
+            Some(rects) if !fresh => self.pipeline.encode_damage(
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                &back_buffer,
+                meshes,
+                rects,
+            )?,
+            _ => self.pipeline.encode(
+                &self.device,
+                &self.queue,
+                &mut encoder,
+                &back_buffer,
+                wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                meshes,
+            )?,
+        }
+        encoder.copy_texture_to_texture(
+            back_buffer.as_image_copy(),
+            frame.texture.as_image_copy(),
+            size,
+        );
+        self.submit_frame(encoder, started);
+        frame.present();
+
+        if suboptimal && let Some(surface) = &self.surface {
+            surface.configure(&self.device);
+        }
+        Ok(())
+    }
+
+    /// Makes sure the back buffer matches frames of `size`. Returns `true`
+    /// if it has been created, without any previous frame.
+    fn ensure_back_buffer(&mut self, size: wgpu::Extent3d) -> bool {
+        if let Some(back_buffer) = &self.back_buffer
+            && back_buffer.size() == size
+        {
+            return false;
+        }
+        if let Some(back_buffer) = self.back_buffer.take() {
+            back_buffer.destroy();
+        }
+        self.back_buffer = Some(self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("rpp_back_buffer"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.color_format,
+            // Loaded by multisampled partial redraws.
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        }));
+        true
+    }
+
+    /// Viewport the frames are drawn into.
+    pub fn viewport(&self) -> &Viewport {
+        self.pipeline.viewport()
+    }
+
+    /// Resizes the surface and the projection to `viewport`. A change of
+    /// scale factor discards the previous frame, so that the next
+    /// [`WgpuDevice::render_damage`] redraws everything.
+    pub fn resize(&mut self, viewport: &Viewport) {
+        if viewport.scale_factor() != self.viewport().scale_factor()
+            && let Some(back_buffer) = self.back_buffer.take()
+        {
+            back_buffer.destroy();
+        }
+        self.pipeline.set_viewport(&self.queue, viewport);
+        if let Some(surface) = &mut self.surface {
+            let (width, height) = viewport.physical_size();
+            surface.resize(&self.device, width, height);
+        }
+    }
+
+    /// Sets the pixels per logical unit, keeping the logical size of the
+    /// viewport: the surface is resized to the new physical size. Contexts,
+    /// atlases and caches drawing for the previous scale factor have to be
+    /// updated too, see [`WgpuContext::set_scale_factor`] and
+    /// [`TextureAtlas::set_scale_factor`].
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        let mut viewport = *self.viewport();
+        viewport.set_scale_factor(scale_factor);
+        self.resize(&viewport);
+    }
+
+    /// Sets how frames are presented to the surface.
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        if let Some(surface) = &mut self.surface {
+            surface.set_present_mode(&self.device, mode);
+        }
+    }
+
+    pub fn present_mode(&self) -> Option<PresentMode> {
+        self.surface.as_ref().map(|s| s.present_mode)
+    }
+
+    pub async fn new(
+        viewport: &Viewport,
+        color_format: Option<wgpu::TextureFormat>,
+    ) -> WgpuResult<Self> {
+        let mut builder = WgpuDeviceBuilder::new();
+        if let Some(format) = color_format {
+            builder = builder.color_format(format);
+        }
+        builder.build(viewport).await
+    }
+}
+
+pub async fn create_device<'a>(viewport: &Viewport) -> WgpuResult<WgpuDevice<'a>> {
+    WgpuDevice::new(viewport, None).await
+}
+
+/// Copies `region` of `source` to the origin of `destination`.
+fn copy_region(
+    encoder: &mut wgpu::CommandEncoder,
+    source: &wgpu::Texture,
+    destination: &wgpu::Texture,
+    region: TextureRegion,
+) {
+    encoder.copy_texture_to_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture: source,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: region.x,
+                y: region.y,
+                z: 0,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        destination.as_image_copy(),
+        wgpu::Extent3d {
+            width: region.width,
+            height: region.height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// Copies the texels at the origin of `source` into `region` of
+/// `destination`.
+fn copy_into_region(
+    encoder: &mut wgpu::CommandEncoder,
+    source: &wgpu::Texture,
+    destination: &wgpu::Texture,
+    region: TextureRegion,
+) {
+    encoder.copy_texture_to_texture(
+        source.as_image_copy(),
+        wgpu::TexelCopyTextureInfo {
+            texture: destination,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: region.x,
+                y: region.y,
+                z: 0,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::Extent3d {
+            width: region.width,
+            height: region.height,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        context::Context,
+        geometry::Point,
+        image::{Glyph, ImageId},
+        paint::{Gradient, GradientStop, Paint},
+    };
+
+    /// Device of the default adapter, or `None` on machines without one,
+    /// where GPU tests are skipped with a message. Setting
+    /// `RPP_REQUIRE_GPU` fails them instead, so that they can not pass
+    /// silently on machines expected to have an adapter.
+    pub(crate) fn device() -> Option<(wgpu::Device, wgpu::Queue)> {
+        let device = pollster::block_on(async {
+            let instance = wgpu::Instance::default();
+            let adapter = instance.request_adapter(&Default::default()).await.ok()?;
+            adapter.request_device(&Default::default()).await.ok()
+        });
+        if device.is_none() {
+            let test = std::thread::current().name().unwrap_or("test").to_owned();
+            assert!(
+                std::env::var_os("RPP_REQUIRE_GPU").is_none(),
+                "{test}: no GPU adapter, but RPP_REQUIRE_GPU is set"
+            );
+            eprintln!("{test}: skipped, no GPU adapter");
+        }
+        device
+    }
+
+    pub(crate) fn target(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+        target_with_format(device, width, height, wgpu::TextureFormat::Rgba8Unorm)
+    }
+
+    fn target_with_format(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        })
+    }
+
+    /// RGBA8 texels, row by row.
+    pub(crate) type Texels = Vec<Vec<[u8; 4]>>;
+
+    /// Texels of an RGBA8 `texture`.
+    pub(crate) fn read(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture: &wgpu::Texture,
+    ) -> Texels {
+        let (width, height) = (texture.width(), texture.height());
+        let row_bytes = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (row_bytes * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+        queue.submit(Some(encoder.finish()));
+        buffer.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::PollType::wait_indefinitely()).unwrap();
+        let data = buffer.slice(..).get_mapped_range();
+        (0..height as usize)
+            .map(|y| {
+                (0..width as usize)
+                    .map(|x| {
+                        let i = y * row_bytes as usize + x * 4;
+                        [data[i], data[i + 1], data[i + 2], data[i + 3]]
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Texels after drawing two rects, then after redrawing the damage of
+    /// removing the first one, and the draw calls of the redraw.
+    fn redraw_damage(msaa: Msaa) -> Option<(Texels, Texels, u64)> {
+        let (device, queue) = device()?;
+        let viewport = Viewport::new(16.0, 16.0);
+        let mut pipeline =
+            RenderPipeline::new(&device, &queue, &viewport, wgpu::TextureFormat::Rgba8Unorm);
+        pipeline.set_msaa(msaa);
+        let target = target(&device, 16, 16);
+        let removed = Rect::from_float(0.0, 0.0, 8.0, 8.0);
+        let translucent = Rect::from_float(8.0, 8.0, 16.0, 16.0);
+        let mut before = WgpuContext::new();
+        before.fill_rect(removed, &Paint::solid([1.0, 0.0, 0.0, 1.0]));
+        before.fill_rect(translucent, &Paint::solid([0.0, 0.0, 1.0, 0.5]));
+        let mut after = WgpuContext::new();
+        after.fill_rect(translucent, &Paint::solid([0.0, 0.0, 1.0, 0.5]));
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT);
+        pipeline
+            .encode(
+                &device,
+                &queue,
+                &mut encoder,
+                &target,
+                clear,
+                before.meshes(),
+            )
+            .unwrap();
+        queue.submit(Some(encoder.finish()));
+        let drawn = read(&device, &queue, &target);
+        pipeline.take_stats();
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        pipeline
+            .encode_damage(
+                &device,
+                &queue,
+                &mut encoder,
+                &target,
+                after.meshes(),
+                &[removed, translucent],
+            )
+            .unwrap();
+        queue.submit(Some(encoder.finish()));
+        let redrawn = read(&device, &queue, &target);
+        Some((drawn, redrawn, pipeline.take_stats().draw_calls))
+    }
+
+    #[test]
+    fn damage_is_cleared_before_redrawing() {
+        let Some((drawn, redrawn, _)) = redraw_damage(Msaa::Off) else {
+            return;
+        };
+        assert_eq!(drawn[4][4], [255, 0, 0, 255]);
+        assert_eq!(redrawn[4][4], [0, 0, 0, 0]);
+        assert_eq!(redrawn[12][12], drawn[12][12]);
+    }
+
+    #[test]
+    fn msaa_damage_loads_target_once() {
+        let Some((_, _, draw_calls)) = redraw_damage(Msaa::Off) else {
+            return;
+        };
+        let (drawn, redrawn, msaa_draw_calls) = redraw_damage(Msaa::X4).unwrap();
+        assert_eq!(redrawn[4][4], [0, 0, 0, 0]);
+        assert_eq!(redrawn[12][12], drawn[12][12]);
+        // A single copy of the target for both rects.
+        assert_eq!(msaa_draw_calls, draw_calls + 1);
+    }
+
+    /// Texels of `context` drawn into a cleared 16x16 target of `format`
+    /// displayed in `color_space`.
+    fn draw(
+        context: &WgpuContext,
+        format: wgpu::TextureFormat,
+        color_space: TargetColorSpace,
+    ) -> Option<Texels> {
+        let (device, queue) = device()?;
+        let viewport = Viewport::new(16.0, 16.0);
+        let mut pipeline = RenderPipeline::new(&device, &queue, &viewport, format);
+        pipeline.set_color_space(color_space);
+        let target = target_with_format(&device, 16, 16, format);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        pipeline
+            .encode(
+                &device,
+                &queue,
+                &mut encoder,
+                &target,
+                wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                context.meshes(),
+            )
+            .unwrap();
+        queue.submit(Some(encoder.finish()));
+        Some(read(&device, &queue, &target))
+    }
+
+    #[test]
+    fn layers_keep_their_opacity() {
+        let mut context = WgpuContext::new();
+        for (x, opacity) in [(0.0, 1.0), (8.0, 0.5)] {
+            context.push_layer(opacity, BlendMode::Normal);
+            context.fill_rect(
+                Rect::from_float(x, 0.0, x + 8.0, 16.0),
+                &Paint::solid([1.0, 0.0, 0.0, 1.0]),
+            );
+            context.pop_layer();
+        }
+        let Some(texels) = draw(
+            &context,
+            wgpu::TextureFormat::Rgba8Unorm,
+            TargetColorSpace::Srgb,
+        ) else {
+            return;
+        };
+        assert_eq!(texels[8][4], [255, 0, 0, 255]);
+        assert!(texels[8][12][0].abs_diff(128) <= 1);
+        assert!(texels[8][12][3].abs_diff(128) <= 1);
+    }
+
+    #[test]
+    fn open_layers_are_composited_at_the_end() {
+        let mut context = WgpuContext::new();
+        context.push_layer(0.5, BlendMode::Normal);
+        context.fill_rect(
+            Rect::from_float(0.0, 0.0, 16.0, 16.0),
+            &Paint::solid([1.0, 0.0, 0.0, 1.0]),
+        );
+        let Some(texels) = draw(
+            &context,
+            wgpu::TextureFormat::Rgba8Unorm,
+            TargetColorSpace::Srgb,
+        ) else {
+            return;
+        };
+        assert!(texels[8][8][0].abs_diff(128) <= 1);
+        assert!(texels[8][8][3].abs_diff(128) <= 1);
+    }
+
+    #[test]
+    fn gradients_fade_to_transparent_without_darkening() {
+        let gradient = Gradient::new([
+            GradientStop::new(0.0, [1.0, 0.0, 0.0, 1.0]),
+            GradientStop::new(1.0, [0.0, 0.0, 0.0, 0.0]),
+        ]);
+        let mut context = WgpuContext::new();
+        context.fill_rect(
+            Rect::from_float(0.0, 0.0, 16.0, 16.0),
+            &Paint::linear_gradient(Point::new(0.0, 0.0), Point::new(16.0, 0.0), gradient),
+        );
+        let Some(texels) = draw(
+            &context,
+            wgpu::TextureFormat::Rgba8Unorm,
+            TargetColorSpace::Srgb,
+        ) else {
+            return;
+        };
+        // Premultiplied red as opaque as the texel.
+        let [r, _, _, a] = texels[8][8];
+        assert!(a.abs_diff(120) <= 8, "{a}");
+        assert!(r.abs_diff(a) <= 2, "{r} != {a}");
+    }
+
+    #[test]
+    fn glyphs_mask_their_paint_as_on_the_cpu() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        // Premultiplied white mask, opaque on the diagonal.
+        let mask: Vec<u8> = (0..16u32)
+            .flat_map(|i| {
+                let alpha = if i % 5 == 0 { 255 } else { (i * 12) as u8 };
+                [alpha; 4]
+            })
+            .collect();
+        let glyph = |image| Glyph {
+            image,
+            src_rect: Rect::from_float(0.0, 0.0, 4.0, 4.0),
+            dst_rect: Rect::from_float(4.0, 4.0, 12.0, 12.0),
+        };
+        let paint = Paint::solid([0.0, 0.0, 1.0, 1.0]);
+
+        let viewport = Viewport::new(16.0, 16.0);
+        let mut pipeline =
+            RenderPipeline::new(&device, &queue, &viewport, wgpu::TextureFormat::Rgba8Unorm);
+        let texture = pipeline
+            .create_texture(
+                &device,
+                &queue,
+                4,
+                4,
+                TextureColorSpace::Linear,
+                TextureAlphaMode::Premultiplied,
+                &mask,
+            )
+            .unwrap();
+        let mut context = WgpuContext::new();
+        context.draw_glyphs(&[glyph(ImageId::new(texture))], &paint);
+        let target = target(&device, 16, 16);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT);
+        pipeline
+            .encode(
+                &device,
+                &queue,
+                &mut encoder,
+                &target,
+                clear,
+                context.meshes(),
+            )
+            .unwrap();
+        queue.submit(Some(encoder.finish()));
+        let texels = read(&device, &queue, &target);
+
+        let mut cpu = crate::cpu::CpuContext::new(16, 16);
+        let image = cpu.add_image(&crate::image::Image::new(4, 4, mask).unwrap());
+        cpu.draw_glyphs(&[glyph(image)], &paint);
+        let pixmap = cpu.into_pixmap();
+        assert_eq!(texels[4][4], [0, 0, 255, 255]);
+        for (y, row) in texels.iter().enumerate() {
+            for (x, texel) in row.iter().enumerate() {
+                let expected = pixmap.pixel(x as u32, y as u32).unwrap();
+                assert!(
+                    (0..4).all(|c| texel[c].abs_diff(expected[c]) <= 2),
+                    "{texel:?} != {expected:?} at ({x}, {y})"
+                );
+            }
+        }
+    }
+
+    /// Draws a red rect into a layer blurred by a radius of 4.
+    fn blurred_rect(context: &mut impl Context) {
+        context.push_blurred_layer(1.0, BlendMode::Normal, 4.0);
+        context.fill_rect(
+            Rect::from_float(4.0, 4.0, 12.0, 12.0),
+            &Paint::solid([1.0, 0.0, 0.0, 1.0]),
+        );
+        context.pop_layer();
+    }
+
+    #[test]
+    fn blurred_layers_match_the_cpu() {
+        let mut context = WgpuContext::new();
+        blurred_rect(&mut context);
+        let Some(texels) = draw(
+            &context,
+            wgpu::TextureFormat::Rgba8Unorm,
+            TargetColorSpace::Srgb,
+        ) else {
+            return;
+        };
+        // Spread past the rect, and faded inside it.
+        assert!(texels[8][2][3] > 0);
+        assert!(texels[8][8][3] < 255);
+        let mut cpu = crate::cpu::CpuContext::new(16, 16);
+        blurred_rect(&mut cpu);
+        let pixmap = cpu.into_pixmap();
+        for (y, row) in texels.iter().enumerate() {
+            for (x, texel) in row.iter().enumerate() {
+                let expected = pixmap.pixel(x as u32, y as u32).unwrap();
+                assert!(
+                    (0..4).all(|c| texel[c].abs_diff(expected[c]) <= 2),
+                    "{texel:?} != {expected:?} at ({x}, {y})"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn damage_redraws_what_blurred_layers_spread() {
+        let Some((device, queue)) = device() else {
+            return;
+        };
+        let viewport = Viewport::new(16.0, 16.0);
+        let mut pipeline =
+            RenderPipeline::new(&device, &queue, &viewport, wgpu::TextureFormat::Rgba8Unorm);
+        let target = target(&device, 16, 16);
+        let mut context = WgpuContext::new();
+        blurred_rect(&mut context);
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        let clear = wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT);
+        pipeline
+            .encode(
+                &device,
+                &queue,
+                &mut encoder,
+                &target,
+                clear,
+                context.meshes(),
+            )
+            .unwrap();
+        queue.submit(Some(encoder.finish()));
+        let drawn = read(&device, &queue, &target);
+
+        // Left of the rect, which its blur spreads into.
+        let damage = Rect::from_float(0.0, 0.0, 4.0, 16.0);
+        let mut encoder = device.create_command_encoder(&Default::default());
+        pipeline
+            .encode_damage(
+                &device,
+                &queue,
+                &mut encoder,
+                &target,
+                context.meshes(),
+                &[damage],
+            )
+            .unwrap();
+        queue.submit(Some(encoder.finish()));
+        let redrawn = read(&device, &queue, &target);
+        assert!(drawn[8][2][3] > 0);
+        for (row, redrawn_row) in drawn.iter().zip(&redrawn) {
+            for (texel, redrawn) in row.iter().zip(redrawn_row) {
+                assert!((0..4).all(|c| texel[c].abs_diff(redrawn[c]) <= 1));
+            }
+        }
+    }
+
+    /// Texels of an opaque `color` filling a target of `format` displayed
+    /// in `color_space`.
+    fn fill(
+        color: Color,
+        format: wgpu::TextureFormat,
+        color_space: TargetColorSpace,
+    ) -> Option<[u8; 4]> {
+        let mut context = WgpuContext::new();
+        context.fill_rect(Rect::from_float(0.0, 0.0, 16.0, 16.0), &Paint::solid(color));
+        Some(draw(&context, format, color_space)?[8][8])
+    }
+
+    #[test]
+    fn colors_are_encoded_once_in_srgb_targets() {
+        let grey = Color::rgb(0.5, 0.5, 0.5);
+        let Some(unorm) = fill(
+            grey,
+            wgpu::TextureFormat::Rgba8Unorm,
+            TargetColorSpace::Srgb,
+        ) else {
+            return;
+        };
+        let srgb = fill(
+            grey,
+            wgpu::TextureFormat::Rgba8UnormSrgb,
+            TargetColorSpace::Srgb,
+        )
+        .unwrap();
+        assert_eq!(unorm, grey.to_rgba8());
+        assert!(srgb.iter().zip(unorm).all(|(a, b)| a.abs_diff(b) <= 1));
+    }
+
+    #[test]
+    fn colors_are_converted_to_display_p3() {
+        let Some(red) = fill(
+            Color::RED,
+            wgpu::TextureFormat::Rgba8Unorm,
+            TargetColorSpace::DisplayP3,
+        ) else {
+            return;
+        };
+        let expected = Color::from(Color::RED.to_display_p3()).to_rgba8();
+        assert!(red.iter().zip(expected).all(|(a, b)| a.abs_diff(b) <= 1));
+        assert_eq!(expected, [234, 51, 35, 255]);
+    }
 }
@@ -0,0 +1,211 @@
+use crate::{
+    geometry::Mat3,
+    paint::{Gradient, Paint, ShaderPaint, SpreadMode, premultiply},
+};
+
+/// Texels per gradient ramp.
+pub(crate) const RAMP_WIDTH: u32 = 256;
+
+const KIND_SOLID: u32 = 0;
+const KIND_LINEAR: u32 = 1;
+const KIND_RADIAL: u32 = 2;
+const KIND_CONIC: u32 = 3;
+
+/// Per draw paint parameters, matching `PaintUniforms` in `shader.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct PaintUniforms {
+    color: [f32; 4],
+    /// Columns of the inverse gradient transform.
+    inverse: [[f32; 4]; 3],
+    params: [f32; 4],
+    kind: u32,
+    spread: u32,
+    ramp_v: f32,
+    _padding: u32,
+}
+
+impl PaintUniforms {
+    pub(crate) fn solid(color: [f32; 4]) -> Self {
+        PaintUniforms {
+            color,
+            inverse: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+            ],
+            params: [0.0; 4],
+            kind: KIND_SOLID,
+            spread: 0,
+            ramp_v: 0.0,
+            _padding: 0,
+        }
+    }
+
+    /// Shader paints have no gradient: their parameters are held by the
+    /// color and the inverse transform, see `shader_params` in
+    /// `shader.wgsl`.
+    fn shader(paint: &ShaderPaint) -> Self {
+        let [color, x, y, z] = paint.params;
+        PaintUniforms {
+            inverse: [x, y, z],
+            ..Self::solid(color)
+        }
+    }
+
+    /// `ramp_v` is the vertical texture coordinate of the gradient's ramp.
+    pub(crate) fn new(paint: &Paint, ramp_v: f32) -> Self {
+        let (kind, params) = match paint {
+            Paint::Solid(color) => return Self::solid(*color),
+            Paint::LinearGradient(g) => (
+                KIND_LINEAR,
+                [g.start.x(), g.start.y(), g.end.x(), g.end.y()],
+            ),
+            Paint::RadialGradient(g) => (KIND_RADIAL, [g.center.x(), g.center.y(), g.radius, 0.0]),
+            Paint::ConicGradient(g) => (KIND_CONIC, [g.center.x(), g.center.y(), g.angle, 0.0]),
+            Paint::Shader(paint) => return Self::shader(paint),
+        };
+        let gradient = paint.gradient().unwrap();
+        let Some(inverse) = gradient.transform.invert() else {
+            // Singular gradient transforms paint nothing.
+            return Self::solid([0.0; 4]);
+        };
+        PaintUniforms {
+            color: [1.0; 4],
+            inverse: columns(&inverse),
+            params,
+            kind,
+            spread: match gradient.spread {
+                SpreadMode::Pad => 0,
+                SpreadMode::Repeat => 1,
+                SpreadMode::Reflect => 2,
+            },
+            ramp_v,
+            _padding: 0,
+        }
+    }
+}
+
+pub(crate) fn columns(m: &Mat3) -> [[f32; 4]; 3] {
+    let m = m.m;
+    [
+        [m[0][0], m[0][1], m[0][2], 0.0],
+        [m[1][0], m[1][1], m[1][2], 0.0],
+        [m[2][0], m[2][1], m[2][2], 0.0],
+    ]
+}
+
+/// Texture holding one row of [`RAMP_WIDTH`] interpolated premultiplied
+/// colors per gradient drawn in a frame.
+pub(crate) struct GradientRamps {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    texels: Vec<u8>,
+    /// Rows of `texels` already written to `texture`.
+    uploaded: u32,
+}
+
+impl GradientRamps {
+    pub(crate) fn new(device: &wgpu::Device) -> Self {
+        let (texture, view) = Self::create_texture(device, 16);
+        GradientRamps {
+            texture,
+            view,
+            texels: Vec::new(),
+            uploaded: 0,
+        }
+    }
+
+    fn create_texture(device: &wgpu::Device, rows: u32) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("gradient_ramps"),
+            size: wgpu::Extent3d {
+                width: RAMP_WIDTH,
+                height: rows,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        (texture, view)
+    }
+
+    pub(crate) fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.texels.clear();
+        self.uploaded = 0;
+    }
+
+    fn rows(&self) -> u32 {
+        (self.texels.len() / (RAMP_WIDTH as usize * 4)) as u32
+    }
+
+    /// Adds the ramp of `gradient`, returning its row.
+    pub(crate) fn push(&mut self, gradient: &Gradient) -> u32 {
+        let row = self.rows();
+        for i in 0..RAMP_WIDTH {
+            let color = premultiply(gradient.interpolate(i as f32 / (RAMP_WIDTH - 1) as f32));
+            self.texels
+                .extend(color.map(|c| (c.clamp(0.0, 1.0) * 255.0 + 0.5) as u8));
+        }
+        row
+    }
+
+    /// Vertical texture coordinate of the center of `row` once uploaded.
+    pub(crate) fn v(&self, row: u32) -> f32 {
+        let height = self.texture.height().max(self.rows().next_power_of_two());
+        (row as f32 + 0.5) / height as f32
+    }
+
+    /// Uploads the ramps pushed since the last upload, growing the texture
+    /// if needed. Returns `false` if there are none.
+    ///
+    /// Rows uploaded earlier are never overwritten before the next
+    /// [`GradientRamps::clear`], as all writes land before the commands of
+    /// the next submit.
+    pub(crate) fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        let rows = self.rows();
+        if rows == self.uploaded {
+            return false;
+        }
+        if rows > self.texture.height() {
+            // Draws recorded earlier keep the previous texture.
+            (self.texture, self.view) = Self::create_texture(device, rows.next_power_of_two());
+            self.uploaded = 0;
+        }
+        let start = (self.uploaded * RAMP_WIDTH * 4) as usize;
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: self.uploaded,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.texels[start..],
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(RAMP_WIDTH * 4),
+                rows_per_image: Some(rows - self.uploaded),
+            },
+            wgpu::Extent3d {
+                width: RAMP_WIDTH,
+                height: rows - self.uploaded,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.uploaded = rows;
+        true
+    }
+}
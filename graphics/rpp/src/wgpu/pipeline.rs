@@ -0,0 +1,480 @@
+use std::{borrow::Cow, collections::HashMap, error::Error};
+
+use crate::{paint::Paint, shader::ShaderId};
+
+use super::{
+    ClipOp, Mesh, STENCIL_FORMAT, TargetColorSpace, WgpuErr, WgpuResult, WgpuVertex,
+    is_linear_format,
+};
+
+const PP_SHADER_SRC: &str = include_str!("./shader.wgsl");
+const CUSTOM_SHADER_SRC: &str = include_str!("./custom.wgsl");
+
+/// Primitives drawn by the built-in programs of `shader.wgsl`.
+///
+/// Each kind only evaluates what its meshes need, the [`PrimitiveKind::Sdf`]
+/// program drawing any mesh.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PrimitiveKind {
+    /// Vertex colors times a solid paint.
+    Solid,
+    /// Vertex colors times a gradient paint.
+    Gradient,
+    /// Texture times vertex colors and paint.
+    Textured,
+    /// Coverage masks such as glyphs: the texture alpha times vertex colors
+    /// and paint. Only used when a mesh asks for its shader.
+    Text,
+    /// Textured and painted meshes masked by a [`Shape`](super::Shape).
+    Sdf,
+}
+
+impl PrimitiveKind {
+    pub const ALL: [PrimitiveKind; 5] = [
+        PrimitiveKind::Solid,
+        PrimitiveKind::Gradient,
+        PrimitiveKind::Textured,
+        PrimitiveKind::Text,
+        PrimitiveKind::Sdf,
+    ];
+
+    /// Id of the built-in shader drawing this kind.
+    pub const fn shader(self) -> ShaderId {
+        ShaderId::new(self as usize)
+    }
+
+    /// Kind drawn by `shader`, `None` if it is not built-in.
+    pub fn from_shader(shader: ShaderId) -> Option<Self> {
+        Self::ALL.get(shader.value()).copied()
+    }
+
+    /// Cheapest kind drawing `mesh`, ignoring [`Mesh::shader`].
+    pub fn of(mesh: &Mesh) -> Self {
+        if mesh.shape.is_some() {
+            PrimitiveKind::Sdf
+        } else if mesh.texture.is_some() || mesh.backdrop_blur.is_some() {
+            PrimitiveKind::Textured
+        } else if mesh.paint.as_ref().is_some_and(|p| p.gradient().is_some()) {
+            PrimitiveKind::Gradient
+        } else {
+            PrimitiveKind::Solid
+        }
+    }
+
+    fn entry_point(self) -> &'static str {
+        match self {
+            PrimitiveKind::Solid => "fs_solid",
+            PrimitiveKind::Gradient => "fs_gradient",
+            PrimitiveKind::Textured => "fs_textured",
+            PrimitiveKind::Text => "fs_text",
+            PrimitiveKind::Sdf => "fs_main",
+        }
+    }
+}
+
+/// State a render pipeline is compiled for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub shader: ShaderId,
+    /// Format of the color target.
+    pub format: wgpu::TextureFormat,
+    /// `None` replaces the target.
+    pub blend: Option<wgpu::BlendState>,
+    /// Clip meshes only write the stencil, whatever their shader.
+    pub clip: ClipOp,
+    /// Samples per pixel of the target, see [`Msaa`](super::Msaa).
+    pub sample_count: u32,
+    /// Color space colors are converted to.
+    pub color_space: TargetColorSpace,
+}
+
+impl PipelineKey {
+    /// Key drawing `mesh` into a target of `format` with `sample_count`
+    /// samples in `color_space`, blending premultiplied. Meshes use
+    /// [`Mesh::shader`], or the shader of a [`Paint::Shader`].
+    pub fn for_mesh(
+        mesh: &Mesh,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        color_space: TargetColorSpace,
+    ) -> Self {
+        match mesh.clip.op {
+            ClipOp::Draw => PipelineKey {
+                shader: mesh
+                    .shader
+                    .or(match &mesh.paint {
+                        Some(Paint::Shader(paint)) => Some(paint.shader),
+                        _ => None,
+                    })
+                    .unwrap_or_else(|| PrimitiveKind::of(mesh).shader()),
+                format,
+                blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                clip: ClipOp::Draw,
+                sample_count,
+                color_space,
+            },
+            clip => PipelineKey {
+                shader: PrimitiveKind::Sdf.shader(),
+                format,
+                blend: None,
+                clip,
+                sample_count,
+                color_space,
+            },
+        }
+    }
+}
+
+/// Render pipelines drawing meshes, compiled on first use.
+///
+/// Besides the built-in shaders of the [`PrimitiveKind`]s, custom shaders
+/// can be registered with [`PipelineRegistry::register_shader`].
+///
+/// Pipelines are compiled through a [`wgpu::PipelineCache`] when the device
+/// has [`wgpu::Features::PIPELINE_CACHE`], whose data can be saved with
+/// [`PipelineRegistry::cache_data`] to speed up compiling them on the next
+/// run.
+pub struct PipelineRegistry {
+    layout: wgpu::PipelineLayout,
+    module: wgpu::ShaderModule,
+    cache: Option<wgpu::PipelineCache>,
+    pipelines: HashMap<PipelineKey, wgpu::RenderPipeline>,
+    /// Modules of the custom shaders, by id after the built-in ones.
+    custom: Vec<wgpu::ShaderModule>,
+}
+
+impl PipelineRegistry {
+    /// Creates a registry of pipelines using `bind_group_layouts`, filling
+    /// the pipeline cache with `cache_data` if any.
+    ///
+    /// # Safety
+    ///
+    /// `cache_data` must have been returned by [`PipelineRegistry::cache_data`],
+    /// see [`wgpu::Device::create_pipeline_cache`].
+    pub(crate) unsafe fn new(
+        device: &wgpu::Device,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        cache_data: Option<&[u8]>,
+    ) -> Self {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("rpp_pipeline_layout"),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("rpp_shader"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(PP_SHADER_SRC)),
+        });
+        let cache = device
+            .features()
+            .contains(wgpu::Features::PIPELINE_CACHE)
+            .then(|| unsafe {
+                device.create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                    label: Some("rpp_pipeline_cache"),
+                    data: cache_data,
+                    fallback: true,
+                })
+            });
+        PipelineRegistry {
+            layout,
+            module,
+            cache,
+            pipelines: HashMap::new(),
+            custom: Vec::new(),
+        }
+    }
+
+    /// Number of compiled pipelines.
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    /// Pipeline compiled for `key`, if any.
+    pub fn get(&self, key: &PipelineKey) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines.get(key)
+    }
+
+    /// Compiles the pipeline for `key` unless it already is.
+    pub fn prepare(&mut self, device: &wgpu::Device, key: PipelineKey) -> WgpuResult<()> {
+        if self.pipelines.contains_key(&key) {
+            return Ok(());
+        }
+        let (module, entry_point) = match PrimitiveKind::from_shader(key.shader) {
+            Some(kind) => (&self.module, kind.entry_point()),
+            None => {
+                let module = self
+                    .custom
+                    .get(key.shader.value() - PrimitiveKind::ALL.len())
+                    .ok_or(WgpuErr::ShaderNotFound(key.shader))?;
+                (module, "fs_custom")
+            }
+        };
+        let pipeline = self.create(device, key, module, entry_point);
+        self.pipelines.insert(key, pipeline);
+        Ok(())
+    }
+
+    /// Registers a custom shader painting meshes with a [`Paint::Shader`],
+    /// returning its id.
+    ///
+    /// `source` is WGSL defining `fn shade(input: ShaderInput) -> vec4<f32>`,
+    /// which returns the straight alpha color of the paint, from:
+    ///
+    /// ```wgsl
+    /// struct ShaderInput {
+    ///     // Position in the user space of the mesh.
+    ///     local: vec2<f32>,
+    ///     // Seconds, see `RenderPipeline::set_time`.
+    ///     time: f32,
+    ///     // `ShaderPaint::params`.
+    ///     params: array<vec4<f32>, 4>,
+    /// }
+    /// ```
+    ///
+    /// The color is then drawn as other paints: multiplied with the texture
+    /// and vertex colors, and masked by the shape of the mesh. `source` is
+    /// appended to `shader.wgsl`, whose declarations it must not redefine.
+    ///
+    /// Returns [`WgpuErr::InvalidShader`] if `source` does not compile.
+    pub fn register_shader(&mut self, device: &wgpu::Device, source: &str) -> WgpuResult<ShaderId> {
+        let full = custom_shader_source(source)?;
+        let id = ShaderId::new(PrimitiveKind::ALL.len() + self.custom.len());
+        self.custom
+            .push(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("rpp_custom_shader"),
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(full)),
+            }));
+        Ok(id)
+    }
+
+    /// Compiles the pipelines of every built-in kind and clip for targets of
+    /// `format` with `sample_count` samples in `color_space`, so that the
+    /// first frames do not wait for them.
+    pub fn prepare_builtin(
+        &mut self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        color_space: TargetColorSpace,
+    ) {
+        let blend = Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING);
+        let draws = PrimitiveKind::ALL.map(|kind| PipelineKey {
+            shader: kind.shader(),
+            format,
+            blend,
+            clip: ClipOp::Draw,
+            sample_count,
+            color_space,
+        });
+        let clips = [ClipOp::Push, ClipOp::Pop].map(|clip| PipelineKey {
+            shader: PrimitiveKind::Sdf.shader(),
+            format,
+            blend: None,
+            clip,
+            sample_count,
+            color_space,
+        });
+        for key in draws.into_iter().chain(clips) {
+            // Built-in shaders always exist.
+            let _ = self.prepare(device, key);
+        }
+    }
+
+    /// Contents of the pipeline cache, to be given to
+    /// [`WgpuDeviceBuilder::pipeline_cache_data`] on the next run. `None`
+    /// without a cache or if the backend does not support saving it.
+    ///
+    /// [`WgpuDeviceBuilder::pipeline_cache_data`]: super::WgpuDeviceBuilder::pipeline_cache_data
+    pub fn cache_data(&self) -> Option<Vec<u8>> {
+        self.cache.as_ref()?.get_data()
+    }
+
+    /// Creates the pipeline for `key`, drawing with `entry_point` of
+    /// `module`.
+    fn create(
+        &self,
+        device: &wgpu::Device,
+        key: PipelineKey,
+        module: &wgpu::ShaderModule,
+        entry_point: &str,
+    ) -> wgpu::RenderPipeline {
+        let (label, entry_point, write_mask, pass_op) = match key.clip {
+            ClipOp::Draw => (
+                "rpp_pipeline",
+                entry_point,
+                wgpu::ColorWrites::ALL,
+                wgpu::StencilOperation::Keep,
+            ),
+            ClipOp::Push => (
+                "rpp_clip_push_pipeline",
+                "fs_clip",
+                wgpu::ColorWrites::empty(),
+                wgpu::StencilOperation::IncrementClamp,
+            ),
+            ClipOp::Pop => (
+                "rpp_clip_pop_pipeline",
+                "fs_clip",
+                wgpu::ColorWrites::empty(),
+                wgpu::StencilOperation::DecrementClamp,
+            ),
+        };
+        let stencil = wgpu::StencilFaceState {
+            compare: wgpu::CompareFunction::Equal,
+            fail_op: wgpu::StencilOperation::Keep,
+            depth_fail_op: wgpu::StencilOperation::Keep,
+            pass_op,
+        };
+        // Colors are converted to the target by `target_color`.
+        let constants = [
+            (
+                "LINEAR_TARGET",
+                f64::from(u8::from(is_linear_format(key.format))),
+            ),
+            (
+                "DISPLAY_P3",
+                f64::from(u8::from(key.color_space == TargetColorSpace::DisplayP3)),
+            ),
+        ];
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            constants: &constants,
+            ..Default::default()
+        };
+        // position, tex coords, color
+        let attributes = &wgpu::vertex_attr_array![
+            0 => Float32x2,
+            1 => Float32x2,
+            2 => Uint32
+        ];
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module,
+                entry_point: Some("vs_main"),
+                compilation_options: compilation_options.clone(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<WgpuVertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes,
+                }],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: STENCIL_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState {
+                    front: stencil,
+                    back: stencil,
+                    read_mask: 0xff,
+                    write_mask: 0xff,
+                },
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: key.sample_count,
+                ..Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point: Some(entry_point),
+                compilation_options,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: key.format,
+                    blend: key.blend,
+                    write_mask,
+                })],
+            }),
+            multiview: None,
+            cache: self.cache.as_ref(),
+        })
+    }
+}
+
+/// Source of the module of a custom shader: `source` between
+/// `shader.wgsl` and `custom.wgsl`. Returns [`WgpuErr::InvalidShader`],
+/// with lines relative to `source` when the error is in it, if the module
+/// does not compile.
+fn custom_shader_source(source: &str) -> WgpuResult<String> {
+    let full = format!("{PP_SHADER_SRC}\n{source}\n{CUSTOM_SHADER_SRC}");
+    // Line of `full` where `source` starts, to locate errors.
+    let first_line = PP_SHADER_SRC.lines().count() as u32 + 2;
+    let lines = source.lines().count() as u32;
+    let describe = |message: String, location: Option<naga::SourceLocation>| match location
+        .map(|l| l.line_number)
+    {
+        Some(line) if (first_line..first_line + lines).contains(&line) => {
+            WgpuErr::InvalidShader(format!("line {}: {message}", line - first_line + 1))
+        }
+        _ => WgpuErr::InvalidShader(message),
+    };
+    let module = naga::front::wgsl::parse_str(&full)
+        .map_err(|err| describe(err.message().to_string(), err.location(&full)))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::empty(),
+    )
+    .validate(&module)
+    .map_err(|err| describe(error_chain(err.as_inner()), err.location(&full)))?;
+    Ok(full)
+}
+
+/// `err` followed by its sources.
+fn error_chain(err: &dyn Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADE: &str = "fn shade(input: ShaderInput) -> vec4<f32> {
+    return vec4<f32>(fract(input.local), input.time, 1.0);
+}";
+
+    fn error(source: &str) -> String {
+        match custom_shader_source(source) {
+            Err(WgpuErr::InvalidShader(message)) => message,
+            other => panic!("expected an invalid shader, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn valid_shaders_compile() {
+        let full = custom_shader_source(SHADE).unwrap();
+        assert!(full.contains(SHADE));
+    }
+
+    #[test]
+    fn errors_report_the_line_in_the_snippet() {
+        let message = error(
+            "fn shade(input: ShaderInput) -> vec4<f32> {
+    let x = 1.0
+    return vec4<f32>(x);
+}",
+        );
+        assert!(message.starts_with("line 3: "), "{message}");
+    }
+
+    #[test]
+    fn snippets_can_not_redefine_declarations() {
+        let message = error(&format!(
+            "fn premultiply(color: vec4<f32>) -> vec4<f32> {{
+    return color;
+}}
+{SHADE}"
+        ));
+        assert!(message.contains("premultiply"), "{message}");
+    }
+}
@@ -1,3 +1,68 @@
+const TAU: f32 = 6.283185307179586;
+const RAMP_WIDTH: f32 = 256.0;
+
+// Whether the target holds linear components, such as `*Srgb` and float
+// formats, instead of encoded ones.
+override LINEAR_TARGET: bool = false;
+// Whether the target is displayed with Display-P3 primaries, see
+// `TargetColorSpace`.
+override DISPLAY_P3: bool = false;
+
+// Linear sRGB to linear Display-P3, as `Color::to_display_p3`.
+const SRGB_TO_P3 = mat3x3<f32>(
+    vec3<f32>(0.8224621, 0.0331942, 0.017082632),
+    vec3<f32>(0.177538, 0.9668058, 0.07239744),
+    vec3<f32>(0.0, 0.0, 0.9105199),
+);
+
+// Decodes sRGB encoded components, mirroring negative ones as in extended
+// sRGB.
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    let a = abs(c);
+    let linear = select(pow((a + 0.055) / 1.055, vec3<f32>(2.4)), a / 12.92, a <= vec3<f32>(0.04045));
+    return sign(c) * linear;
+}
+
+// Encodes linear components to sRGB, mirroring negative ones.
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    let a = abs(c);
+    let encoded = select(1.055 * pow(a, vec3<f32>(1.0 / 2.4)) - 0.055, a * 12.92, a <= vec3<f32>(0.0031308));
+    return sign(c) * encoded;
+}
+
+// Converts a straight color with sRGB encoded components, as `Color`s are,
+// to the primaries and transfer function of the target.
+fn target_color(color: vec4<f32>) -> vec4<f32> {
+    if !LINEAR_TARGET && !DISPLAY_P3 {
+        return color;
+    }
+    var rgb = srgb_to_linear(color.rgb);
+    if DISPLAY_P3 {
+        rgb = SRGB_TO_P3 * rgb;
+        if !LINEAR_TARGET {
+            rgb = linear_to_srgb(rgb);
+        }
+    }
+    return vec4<f32>(rgb, color.a);
+}
+
+// Colors are premultiplied from here on: the texture, the vertex color and
+// the paint are multiplied, and the pipeline blends with
+// `BlendState::PREMULTIPLIED_ALPHA_BLENDING`.
+fn premultiply(color: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
+
+// Divides the color components by alpha. Transparent colors become
+// transparent black.
+fn unpremultiply(color: vec4<f32>) -> vec4<f32> {
+    if color.a <= 0.0 {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>(color.rgb / color.a, color.a);
+}
+
+// Unpacks a straight RGBA8 color.
 fn unpack_color(color: u32) -> vec4<f32> {
     return vec4<f32>(
         f32(color & 255u),
@@ -9,6 +74,8 @@ fn unpack_color(color: u32) -> vec4<f32> {
 
 struct Uniforms {
     ortho : mat4x4<f32>,
+    // Seconds, as set by `RenderPipeline::set_time`.
+    time: f32,
 };
 
 @group(0) @binding(0)
@@ -24,6 +91,7 @@ struct VertexOutput {
     @builtin(position) position: vec4<f32>,
     @location(0) tex_coords: vec2<f32>,
     @location(1) color: vec4<f32>,
+    @location(2) local: vec2<f32>,
 }
 
 @vertex
@@ -32,7 +100,10 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
     out.tex_coords = model.tex_coords;
-    out.clip_position = uniforms.ortho * vec4<f32>(model.position, 0.0, 1.0);
+    out.color = premultiply(target_color(unpack_color(model.color)));
+    out.local = model.position;
+    let position = draw.transform * vec3<f32>(model.position, 1.0);
+    out.position = uniforms.ortho * vec4<f32>(position.xy, 0.0, 1.0);
     return out;
 }
 
@@ -41,8 +112,217 @@ var tex_diffuse: texture_2d<f32>;
 @group(1) @binding(1)
 var tex_sampler: sampler;
 
+// Paint kinds
+const PAINT_SOLID: u32 = 0u;
+const PAINT_LINEAR: u32 = 1u;
+const PAINT_RADIAL: u32 = 2u;
+const PAINT_CONIC: u32 = 3u;
+
+// Spread modes
+const SPREAD_REPEAT: u32 = 1u;
+const SPREAD_REFLECT: u32 = 2u;
+
+struct PaintUniforms {
+    color: vec4<f32>,
+    // Columns of the inverse gradient transform.
+    inverse_x: vec4<f32>,
+    inverse_y: vec4<f32>,
+    inverse_z: vec4<f32>,
+    // linear: start, end
+    // radial: center, radius
+    // conic: center, angle
+    params: vec4<f32>,
+    kind: u32,
+    spread: u32,
+    ramp_v: f32,
+}
+
+// Shape kinds
+const SHAPE_ROUNDED_RECT: u32 = 1u;
+const SHAPE_DROP_SHADOW: u32 = 2u;
+const SHAPE_INNER_SHADOW: u32 = 3u;
+
+struct ShapeUniforms {
+    // min, max
+    rect: vec4<f32>,
+    // top left, top right, bottom right, bottom left
+    radii: vec4<f32>,
+    border_color: vec4<f32>,
+    // Shape casting a shadow.
+    clip_rect: vec4<f32>,
+    clip_radii: vec4<f32>,
+    border_width: f32,
+    sigma: f32,
+    kind: u32,
+}
+
+struct DrawUniforms {
+    paint: PaintUniforms,
+    shape: ShapeUniforms,
+    // Maps user space to the viewport.
+    transform: mat3x3<f32>,
+}
+
+@group(2) @binding(0)
+var<uniform> draw: DrawUniforms;
+@group(2) @binding(1)
+var ramp_texture: texture_2d<f32>;
+@group(2) @binding(2)
+var ramp_sampler: sampler;
+
+fn apply_spread(t: f32, spread: u32) -> f32 {
+    switch spread {
+        case SPREAD_REPEAT: {
+            return fract(t);
+        }
+        case SPREAD_REFLECT: {
+            return 1.0 - abs(fract(t * 0.5) * 2.0 - 1.0);
+        }
+        default: {
+            return clamp(t, 0.0, 1.0);
+        }
+    }
+}
+
+// Premultiplied color of the paint at `local`.
+fn paint_color(local: vec2<f32>) -> vec4<f32> {
+    let paint = draw.paint;
+    if paint.kind == PAINT_SOLID {
+        return premultiply(target_color(paint.color));
+    }
+
+    let inverse = mat3x3<f32>(paint.inverse_x.xyz, paint.inverse_y.xyz, paint.inverse_z.xyz);
+    let p = (inverse * vec3<f32>(local, 1.0)).xy;
+    let params = paint.params;
+    var t = 0.0;
+    switch paint.kind {
+        case PAINT_LINEAR: {
+            let d = params.zw - params.xy;
+            let len2 = dot(d, d);
+            if len2 > 0.0 {
+                t = dot(p - params.xy, d) / len2;
+            }
+        }
+        case PAINT_RADIAL: {
+            t = 1.0;
+            if params.z > 0.0 {
+                t = length(p - params.xy) / params.z;
+            }
+        }
+        default: {
+            let angle = atan2(p.y - params.y, p.x - params.x) - params.z;
+            t = fract(angle / TAU);
+        }
+    }
+    t = apply_spread(t, paint.spread);
+    let u = (t * (RAMP_WIDTH - 1.0) + 0.5) / RAMP_WIDTH;
+    // Ramps hold premultiplied colors, so that texels are interpolated as
+    // on the CPU.
+    let ramp = textureSampleLevel(ramp_texture, ramp_sampler, vec2<f32>(u, paint.ramp_v), 0.0);
+    return premultiply(target_color(unpremultiply(ramp) * paint.color));
+}
+
+// Signed distance from `p` to the rounded rectangle `rect`.
+fn rounded_rect_distance(p: vec2<f32>, rect: vec4<f32>, radii: vec4<f32>) -> f32 {
+    let half_size = (rect.zw - rect.xy) * 0.5;
+    let c = p - rect.xy - half_size;
+    // Radius of the left and right corner in the half `p` is in.
+    let left = select(radii.w, radii.x, c.y < 0.0);
+    let right = select(radii.z, radii.y, c.y < 0.0);
+    let radius = select(right, left, c.x < 0.0);
+    let q = abs(c) - half_size + radius;
+    return min(max(q.x, q.y), 0.0) + length(max(q, vec2<f32>(0.0))) - radius;
+}
+
+// Error function, as `effect::erf`.
+fn erf(x: f32) -> f32 {
+    let t = 1.0 / (1.0 + 0.3275911 * abs(x));
+    let y = 1.0 - (((((1.0614054 * t - 1.4531521) * t) + 1.4214137) * t - 0.28449674) * t + 0.2548296) * t * exp(-x * x);
+    return select(y, -y, x < 0.0);
+}
+
+// Draws a mesh with the premultiplied `paint` color: multiplies it with the
+// texture and vertex color, then masks it by the shape.
+fn draw_mesh(in: VertexOutput, paint: vec4<f32>) -> vec4<f32> {
+    let color = textureSample(tex_diffuse, tex_sampler, in.tex_coords) * in.color * paint;
+    // Size of a pixel in local units, for one pixel wide anti-aliasing ramps.
+    let scale = max(length(fwidth(in.local)) * 0.70710678, 1e-4);
+    let shape = draw.shape;
+    if shape.kind == SHAPE_DROP_SHADOW || shape.kind == SHAPE_INNER_SHADOW {
+        let d = rounded_rect_distance(in.local, shape.rect, shape.radii);
+        var shadow = clamp(0.5 - d / scale, 0.0, 1.0);
+        if shape.sigma > 1e-3 {
+            shadow = 0.5 - 0.5 * erf(d / (shape.sigma * 1.41421356));
+        }
+        let inside = clamp(0.5 - rounded_rect_distance(in.local, shape.clip_rect, shape.clip_radii) / scale, 0.0, 1.0);
+        if shape.kind == SHAPE_INNER_SHADOW {
+            return color * ((1.0 - shadow) * inside);
+        }
+        return color * (shadow * (1.0 - inside));
+    }
+    if shape.kind != SHAPE_ROUNDED_RECT {
+        return color;
+    }
+
+    let d = rounded_rect_distance(in.local, shape.rect, shape.radii);
+    let outer = clamp(0.5 - d / scale, 0.0, 1.0);
+    let inner = clamp(0.5 - (d + shape.border_width) / scale, 0.0, 1.0);
+    return color * inner + premultiply(target_color(shape.border_color)) * (outer - inner);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(tex_diffuse * in.color, tex_sampler, in.tex_coords);
+    return draw_mesh(in, paint_color(in.local));
+}
+
+// Input of custom shader paints, which define
+// `fn shade(input: ShaderInput) -> vec4<f32>` returning a straight color.
+struct ShaderInput {
+    // Position in the user space of the mesh.
+    local: vec2<f32>,
+    time: f32,
+    // `ShaderPaint::params`.
+    params: array<vec4<f32>, 4>,
 }
 
+// Shader paints have no gradient: their parameters are held by the color
+// and the inverse transform.
+fn shader_params() -> array<vec4<f32>, 4> {
+    let paint = draw.paint;
+    return array<vec4<f32>, 4>(paint.color, paint.inverse_x, paint.inverse_y, paint.inverse_z);
+}
+
+// Programs of the other primitive kinds, evaluating less than `fs_main`.
+
+@fragment
+fn fs_solid(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color * premultiply(target_color(draw.paint.color));
+}
+
+@fragment
+fn fs_gradient(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color * paint_color(in.local);
+}
+
+@fragment
+fn fs_textured(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(tex_diffuse, tex_sampler, in.tex_coords) * in.color * paint_color(in.local);
+}
+
+// Coverage masks, such as glyphs, only use the texture alpha.
+@fragment
+fn fs_text(in: VertexOutput) -> @location(0) vec4<f32> {
+    let coverage = textureSample(tex_diffuse, tex_sampler, in.tex_coords).a;
+    return in.color * paint_color(in.local) * coverage;
+}
+
+// Writes clip meshes to the stencil only. Rounded rect clips are not
+// anti-aliased: fragments are kept when their center is inside.
+@fragment
+fn fs_clip(in: VertexOutput) -> @location(0) vec4<f32> {
+    let shape = draw.shape;
+    if shape.kind == SHAPE_ROUNDED_RECT && rounded_rect_distance(in.local, shape.rect, shape.radii) > 0.0 {
+        discard;
+    }
+    return vec4<f32>(0.0);
+}
@@ -0,0 +1,91 @@
+use crate::{
+    effect::BoxShadow,
+    geometry::RoundedRect,
+    paint::{Border, ColorF},
+};
+
+const SHAPE_NONE: u32 = 0;
+const SHAPE_ROUNDED_RECT: u32 = 1;
+const SHAPE_DROP_SHADOW: u32 = 2;
+const SHAPE_INNER_SHADOW: u32 = 3;
+
+/// Shape evaluated analytically by the fragment shader, masking a mesh.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    /// Rounded rectangle filled with the mesh color, with an optional border
+    /// drawn inside its outline.
+    RoundedRect {
+        rrect: RoundedRect,
+        border: Option<Border>,
+    },
+    /// Shadow of a rounded rectangle, in the mesh color.
+    BoxShadow {
+        rrect: RoundedRect,
+        shadow: BoxShadow,
+    },
+}
+
+/// Per draw shape parameters, matching `ShapeUniforms` in `shader.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ShapeUniforms {
+    rect: [f32; 4],
+    /// Top left, top right, bottom right, bottom left.
+    radii: [f32; 4],
+    border_color: ColorF,
+    clip_rect: [f32; 4],
+    clip_radii: [f32; 4],
+    border_width: f32,
+    sigma: f32,
+    kind: u32,
+    _padding: u32,
+}
+
+impl ShapeUniforms {
+    pub(crate) fn none() -> Self {
+        ShapeUniforms {
+            rect: [0.0; 4],
+            radii: [0.0; 4],
+            border_color: [0.0; 4],
+            clip_rect: [0.0; 4],
+            clip_radii: [0.0; 4],
+            border_width: 0.0,
+            sigma: 0.0,
+            kind: SHAPE_NONE,
+            _padding: 0,
+        }
+    }
+
+    pub(crate) fn new(shape: Option<&Shape>) -> Self {
+        match shape {
+            None => Self::none(),
+            Some(Shape::RoundedRect { rrect, border }) => {
+                let border = border.unwrap_or(Border::new(0.0, [0.0; 4]));
+                ShapeUniforms {
+                    rect: rrect.rect().into(),
+                    radii: rrect.radii().to_array(),
+                    border_color: border.color,
+                    border_width: border.width.max(0.0),
+                    kind: SHAPE_ROUNDED_RECT,
+                    ..Self::none()
+                }
+            }
+            Some(Shape::BoxShadow { rrect, shadow }) => {
+                let shape = shadow.shape(*rrect);
+                ShapeUniforms {
+                    rect: shape.rect().into(),
+                    radii: shape.radii().to_array(),
+                    clip_rect: rrect.rect().into(),
+                    clip_radii: rrect.radii().to_array(),
+                    sigma: shadow.sigma(),
+                    kind: if shadow.inset {
+                        SHAPE_INNER_SHADOW
+                    } else {
+                        SHAPE_DROP_SHADOW
+                    },
+                    ..Self::none()
+                }
+            }
+        }
+    }
+}
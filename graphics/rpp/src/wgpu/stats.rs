@@ -0,0 +1,167 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU8, Ordering},
+    },
+    time::Duration,
+};
+
+/// Statistics of a frame drawn by a [`WgpuDevice`](super::WgpuDevice), see
+/// [`WgpuDeviceBuilder::frame_stats`](super::WgpuDeviceBuilder::frame_stats).
+///
+/// Uploads made between frames, such as creating textures, count towards
+/// the next frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameStats {
+    /// Draw calls recorded: meshes, layer composites and blur passes.
+    pub draw_calls: u64,
+    /// Vertices of the meshes uploaded.
+    pub vertices: u64,
+    /// Texture writes: new and updated textures, and gradient ramps.
+    pub texture_uploads: u64,
+    /// Bytes written to buffers: vertices, indices and uniforms.
+    pub buffer_bytes: u64,
+    /// CPU time spent recording and submitting the frame.
+    pub encode_time: Duration,
+    /// GPU time spent drawing a frame, from timestamp queries. Timestamps
+    /// are read back without waiting for the GPU, so this is the time of
+    /// the last frame read back, usually the one before. `None` if the
+    /// device has no [`wgpu::Features::TIMESTAMP_QUERY`], or until the
+    /// first frame is read back.
+    pub gpu_time: Option<Duration>,
+}
+
+const PENDING: u8 = 0;
+const MAPPED: u8 = 1;
+const FAILED: u8 = 2;
+
+/// Measures the GPU time between two points of a command encoder, with
+/// empty compute passes writing timestamps.
+///
+/// One frame is timed at a time: frames drawn while the previous
+/// timestamps are read back are not timed.
+pub(crate) struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// State of the readback, `None` if idle.
+    readback: Option<Arc<AtomicU8>>,
+    /// Whether the encoder being recorded is timed.
+    timing: bool,
+}
+
+impl GpuTimer {
+    /// Creates a timer if the device has timestamp queries.
+    pub(crate) fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("rpp_timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: 2,
+        });
+        let size = 2 * wgpu::QUERY_SIZE as wgpu::BufferAddress;
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("rpp_timestamps_resolve"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("rpp_timestamps_readback"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Some(GpuTimer {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            period: queue.get_timestamp_period(),
+            readback: None,
+            timing: false,
+        })
+    }
+
+    /// Writes the start timestamp into `encoder`, unless the previous
+    /// timestamps are still read back.
+    pub(crate) fn begin(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.timing = self.readback.is_none();
+        if self.timing {
+            self.write_timestamp(encoder, 0);
+        }
+    }
+
+    /// Writes the end timestamp into `encoder`, and copies both to the
+    /// readback buffer.
+    pub(crate) fn end(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.timing {
+            return;
+        }
+        self.write_timestamp(encoder, 1);
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            self.resolve_buffer.size(),
+        );
+    }
+
+    /// Starts reading back the timestamps, once the encoder is submitted.
+    pub(crate) fn submitted(&mut self) {
+        if !std::mem::take(&mut self.timing) {
+            return;
+        }
+        let state = Arc::new(AtomicU8::new(PENDING));
+        let callback_state = state.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let value = if result.is_ok() { MAPPED } else { FAILED };
+                callback_state.store(value, Ordering::Release);
+            });
+        self.readback = Some(state);
+    }
+
+    /// Returns the time of the last timed frame once read back, without
+    /// waiting for the GPU.
+    pub(crate) fn poll(&mut self, device: &wgpu::Device) -> Option<Duration> {
+        let state = self.readback.as_ref()?;
+        let _ = device.poll(wgpu::PollType::Poll);
+        match state.load(Ordering::Acquire) {
+            PENDING => None,
+            MAPPED => {
+                self.readback = None;
+                let ticks = {
+                    let data = self.readback_buffer.slice(..).get_mapped_range();
+                    let timestamps: &[u64] = bytemuck::cast_slice(&data);
+                    timestamps[1].saturating_sub(timestamps[0])
+                };
+                self.readback_buffer.unmap();
+                Some(Duration::from_nanos(
+                    (ticks as f64 * self.period as f64) as u64,
+                ))
+            }
+            _ => {
+                self.readback = None;
+                None
+            }
+        }
+    }
+
+    fn write_timestamp(&self, encoder: &mut wgpu::CommandEncoder, index: u32) {
+        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("rpp_timestamp"),
+            timestamp_writes: Some(wgpu::ComputePassTimestampWrites {
+                query_set: &self.query_set,
+                beginning_of_pass_write_index: Some(index),
+                end_of_pass_write_index: None,
+            }),
+        });
+    }
+}
@@ -0,0 +1,132 @@
+use std::{collections::HashMap, hash::Hash};
+
+use crate::{
+    context::Context,
+    geometry::{Float, Mat3, Rect},
+    svg::Svg,
+};
+
+use super::{Mesh, WgpuContext};
+
+/// Tessellated meshes of SVG icons, by key, size and scale.
+///
+/// Paths are flattened for the size they are first drawn at and the scale
+/// of the current transform, so an icon drawn at several sizes or zoom
+/// levels is tessellated once for each. Moving an icon reuses its meshes.
+/// Meshes are flattened for the scale factor of the context they are drawn
+/// into, and all are tessellated again once it changes.
+pub struct SvgCache<K> {
+    /// Meshes by key, and the bits of the width, height and scale.
+    entries: HashMap<(K, [u32; 3]), Vec<Mesh>>,
+    /// Scale factor the entries are flattened for.
+    scale_factor: Float,
+}
+
+impl<K> Default for SvgCache<K> {
+    fn default() -> Self {
+        SvgCache {
+            entries: HashMap::new(),
+            scale_factor: 1.0,
+        }
+    }
+}
+
+impl<K: Hash + Eq> SvgCache<K> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of cached icon sizes.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Draws `svg` fitted into `rect`, tessellating it under `key` if it
+    /// has not been drawn at the size of `rect` and the scale of the
+    /// current transform yet.
+    pub fn draw(&mut self, context: &mut WgpuContext, key: K, svg: &Svg, rect: Rect) {
+        let scale_factor = context.scale_factor();
+        if scale_factor != self.scale_factor {
+            self.entries.clear();
+            self.scale_factor = scale_factor;
+        }
+        let scale = context.transform().max_scale();
+        let size = [
+            rect.width().to_bits(),
+            rect.height().to_bits(),
+            scale.to_bits(),
+        ];
+        let meshes = self.entries.entry((key, size)).or_insert_with(|| {
+            let mut icon = WgpuContext::with_scale_factor(scale_factor * scale);
+            svg.draw(
+                &mut icon,
+                Rect::from_float(0.0, 0.0, rect.width(), rect.height()),
+            );
+            icon.meshes().to_vec()
+        });
+        let origin = rect.min();
+        context.save();
+        context.set_transform(
+            context
+                .transform()
+                .multiply(&Mat3::translate(origin.x(), origin.y())),
+        );
+        context.append_meshes(meshes);
+        context.restore();
+    }
+
+    /// Removes the meshes of `key` at every size.
+    pub fn remove(&mut self, key: &K) {
+        self.entries.retain(|(k, _), _| k != key);
+    }
+
+    /// Removes the meshes of every icon at `width` x `height`, at every
+    /// scale.
+    pub fn remove_size(&mut self, width: Float, height: Float) {
+        let size = [width.to_bits(), height.to_bits()];
+        self.entries.retain(|(_, s), _| s[..2] != size);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn icons_are_tessellated_per_size_and_scale() {
+        let svg = Svg::parse(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+                <circle cx="5" cy="5" r="5"/>
+            </svg>"#,
+        )
+        .unwrap();
+        let mut cache = SvgCache::new();
+        let mut context = WgpuContext::new();
+        let icon = Rect::from_float(0.0, 0.0, 10.0, 10.0);
+        cache.draw(&mut context, 0, &svg, icon);
+        let vertices = |context: &WgpuContext| context.meshes().last().unwrap().vertices.len();
+        let small = vertices(&context);
+
+        // Moved: reused.
+        context.translate(20.0, 0.0);
+        cache.draw(&mut context, 0, &svg, icon);
+        assert_eq!(cache.len(), 1);
+
+        // Zoomed: flattened again, more finely.
+        context.scale(8.0, 8.0);
+        cache.draw(&mut context, 0, &svg, icon);
+        assert_eq!(cache.len(), 2);
+        assert!(vertices(&context) > small);
+
+        cache.remove_size(10.0, 10.0);
+        assert!(cache.is_empty());
+    }
+}
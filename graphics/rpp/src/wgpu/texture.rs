@@ -0,0 +1,324 @@
+use std::borrow::Cow;
+
+use crate::{
+    color::{linear_to_srgb, srgb_to_linear},
+    geometry::{Float, Size},
+    image::Sampling,
+};
+
+pub type TextureId = usize;
+
+/// Color space of the texels uploaded to a texture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureColorSpace {
+    /// sRGB encoded texels, decoded to linear when sampled.
+    Srgb,
+    /// Linear texels, sampled as is.
+    Linear,
+}
+
+impl TextureColorSpace {
+    pub(crate) fn to_wgpu_format(self) -> wgpu::TextureFormat {
+        match self {
+            TextureColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+            TextureColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+        }
+    }
+}
+
+/// Whether the texels uploaded to a texture are premultiplied by their alpha.
+///
+/// Textures are stored premultiplied: straight texels are premultiplied on
+/// upload, in linear space for [`TextureColorSpace::Srgb`] textures.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureAlphaMode {
+    /// Color components are already multiplied by alpha.
+    #[default]
+    Premultiplied,
+    /// Color components are independent of alpha.
+    Straight,
+}
+
+/// Region of a texture in texels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextureRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TextureRegion {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        TextureRegion {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+pub struct Texture {
+    wgpu_texture: wgpu::Texture,
+    /// Bind groups by [`Sampling`].
+    bind_groups: Vec<wgpu::BindGroup>,
+    size: Size,
+    color_space: TextureColorSpace,
+    alpha_mode: TextureAlphaMode,
+}
+
+impl Texture {
+    /// Creates a texture sampled with `samplers`, indexed by [`Sampling`].
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        samplers: &[wgpu::Sampler; Sampling::COUNT],
+        width: u32,
+        height: u32,
+        mip_level_count: u32,
+        color_space: TextureColorSpace,
+        alpha_mode: TextureAlphaMode,
+    ) -> Self {
+        let wgpu_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("rpp_texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: color_space.to_wgpu_format(),
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = wgpu_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_groups = samplers
+            .iter()
+            .map(|sampler| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(sampler),
+                        },
+                    ],
+                    label: Some("texture_bind_group"),
+                })
+            })
+            .collect();
+
+        Texture {
+            wgpu_texture,
+            bind_groups,
+            size: Size::new(width as f32, height as f32),
+            color_space,
+            alpha_mode,
+        }
+    }
+
+    pub(crate) fn write(&self, queue: &wgpu::Queue, region: TextureRegion, data: &[u8]) {
+        let data = match self.alpha_mode {
+            TextureAlphaMode::Premultiplied => Cow::Borrowed(data),
+            TextureAlphaMode::Straight => Cow::Owned(premultiply_texels(data, self.color_space)),
+        };
+        queue.write_texture(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.wgpu_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: region.x,
+                    y: region.y,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &data,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(region.width * 4),
+                rows_per_image: Some(region.height),
+            },
+            wgpu::Extent3d {
+                width: region.width,
+                height: region.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub(crate) fn destroy(&self) {
+        self.wgpu_texture.destroy();
+    }
+
+    pub(crate) fn wgpu_texture(&self) -> &wgpu::Texture {
+        &self.wgpu_texture
+    }
+
+    pub(crate) fn bind_group(&self, sampling: Sampling) -> &wgpu::BindGroup {
+        &self.bind_groups[sampling.index()]
+    }
+
+    pub fn width(&self) -> u32 {
+        self.wgpu_texture.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.wgpu_texture.height()
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    pub fn color_space(&self) -> TextureColorSpace {
+        self.color_space
+    }
+
+    pub fn alpha_mode(&self) -> TextureAlphaMode {
+        self.alpha_mode
+    }
+
+    /// Number of mipmap levels, 1 for textures without mipmaps.
+    pub fn mip_level_count(&self) -> u32 {
+        self.wgpu_texture.mip_level_count()
+    }
+
+    /// Returns `true` if `region` lies entirely inside the texture.
+    pub fn contains(&self, region: TextureRegion) -> bool {
+        region
+            .x
+            .checked_add(region.width)
+            .is_some_and(|r| r <= self.width())
+            && region
+                .y
+                .checked_add(region.height)
+                .is_some_and(|b| b <= self.height())
+    }
+}
+
+/// Premultiplies straight RGBA8 texels.
+fn premultiply_texels(data: &[u8], color_space: TextureColorSpace) -> Vec<u8> {
+    let mut out = data.to_vec();
+    for texel in out.chunks_exact_mut(4) {
+        let a = texel[3] as Float / 255.0;
+        if a >= 1.0 {
+            continue;
+        }
+        for c in &mut texel[..3] {
+            let v = *c as Float / 255.0;
+            let v = match color_space {
+                TextureColorSpace::Srgb => linear_to_srgb(srgb_to_linear(v) * a),
+                TextureColorSpace::Linear => v * a,
+            };
+            *c = (v * 255.0 + 0.5) as u8;
+        }
+    }
+    out
+}
+
+/// Bits of a [`TextureId`] indexing a slot of a [`TextureStore`], the rest
+/// being the generation of the slot.
+const SLOT_BITS: u32 = usize::BITS / 2;
+
+struct Slot<T> {
+    generation: usize,
+    texture: Option<T>,
+}
+
+/// Storage of textures owned by a pipeline.
+///
+/// Slots of destroyed textures are reused with a new generation tagged into
+/// their id, so a stale [`TextureId`] or [`ImageId`](crate::image::ImageId)
+/// finds no texture instead of a newer one. Slots are retired once their
+/// generations run out.
+pub(crate) struct TextureStore<T = Texture> {
+    slots: Vec<Slot<T>>,
+    free_slots: Vec<usize>,
+}
+
+impl<T> TextureStore<T> {
+    pub(crate) fn new() -> Self {
+        TextureStore {
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, texture: T) -> TextureId {
+        let index = self.free_slots.pop().unwrap_or_else(|| {
+            assert!(self.slots.len() < 1 << SLOT_BITS, "too many textures");
+            self.slots.push(Slot {
+                generation: 0,
+                texture: None,
+            });
+            self.slots.len() - 1
+        });
+        let slot = &mut self.slots[index];
+        slot.texture = Some(texture);
+        slot.generation << SLOT_BITS | index
+    }
+
+    pub(crate) fn remove(&mut self, id: TextureId) -> Option<T> {
+        let index = self.index(id)?;
+        let slot = &mut self.slots[index];
+        let texture = slot.texture.take();
+        if slot.generation < usize::MAX >> SLOT_BITS {
+            slot.generation += 1;
+            self.free_slots.push(index);
+        }
+        texture
+    }
+
+    pub(crate) fn get(&self, id: TextureId) -> Option<&T> {
+        self.slots[self.index(id)?].texture.as_ref()
+    }
+
+    /// Slot of `id`, if it holds the texture of that generation.
+    fn index(&self, id: TextureId) -> Option<usize> {
+        let index = id & ((1 << SLOT_BITS) - 1);
+        let slot = self.slots.get(index)?;
+        (slot.generation == id >> SLOT_BITS && slot.texture.is_some()).then_some(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_ids_find_no_texture() {
+        let mut store = TextureStore::new();
+        let a = store.insert("a");
+        assert_eq!(store.remove(a), Some("a"));
+        let b = store.insert("b");
+        assert_ne!(a, b);
+        assert_eq!(store.get(a), None);
+        assert_eq!(store.remove(a), None);
+        assert_eq!(store.get(b), Some(&"b"));
+        // The slot of `a` was reused.
+        assert_eq!(store.slots.len(), 1);
+    }
+
+    #[test]
+    fn slots_retire_once_their_generations_run_out() {
+        let mut store = TextureStore::new();
+        let id = store.insert(());
+        store.slots[0].generation = usize::MAX >> SLOT_BITS;
+        let last = (usize::MAX >> SLOT_BITS) << SLOT_BITS;
+        assert_eq!(store.get(id), None);
+        assert_eq!(store.remove(last), Some(()));
+        assert_ne!(store.insert(()), id);
+        assert_eq!(store.slots.len(), 2);
+    }
+}